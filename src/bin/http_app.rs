@@ -3,29 +3,52 @@ use std::{collections::HashMap, net::SocketAddr, path::PathBuf};
 use axum::{
     body::Body,
     extract::{OriginalUri, Path, Query, Request},
-    http::StatusCode,
-    response::IntoResponse,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
 
 use axum_server::tls_rustls::RustlsConfig;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use data_statistics::{
     configure_log4rs,
-    model::AxumAPIModel::{
-        DataToBinsRequest, PivotData, RuntimeErrorResponse, StatisticSearchPayload,
+    model::{
+        AxumAPIModel::{
+            BacktestRequest, BatchValuationRequest, BoxPlotRequest, DataToBinsRequest,
+            DepreciationRequest, ForecastRequest, HeatmapRequest, LiquidityRequest, PivotData,
+            PriceGapRequest, RenderRequest, RuntimeErrorResponse, StatisticSearchPayload,
+            TimeOnMarketRequest, TimeSeriesRequest,
+        },
+        Chart::ImageFormat,
     },
     services::{
         AnalysisService::{pivot_distribution, stat_distribution},
+        BacktestService::{backtest, write_report},
+        BoxPlotService::group_box_plots,
         ChartServices::{chartData, data_to_bins},
+        DepreciationService::depreciation,
+        ForecastService::market_forecast,
+        HeatmapService::market_heatmap,
+        HistoryService::{listing_history, write_snapshot},
+        LiquidityService::liquidity_ranking,
+        OutlierService::suspicious_listings,
         PivotService::pivot_chart,
         PriceCalculatorService::calculateStatistic,
+        PriceGapService::price_gaps,
+        RenderService::{render_distribution, render_pivot_chart, render_time_series},
+        SeasonalityService::seasonal_decomposition,
+        SurvivalService::time_on_market,
+        TimeSeriesService::market_trend,
+        ValuationService::{
+            parse_batch_json, parse_vehicles_csv, valuate_batch, write_valuations_csv,
+        },
+        VegaLiteService::distribution_vega_lite,
         VehicleService::search,
     },
-    Payload, VEHICLES_DATA,
+    Payload, ESTIMATED_PRICES_DATA, HISTORY_DIR, VEHICLES_DATA,
 };
-use log::info;
+use log::{error, info};
 
 use tower_http::cors::{Any, CorsLayer};
 
@@ -35,6 +58,50 @@ struct Args {
     /// Path to the directory containing the certificate files
     #[clap(short, long, default_value = "/etc/letsencrypt/live/ehomeho.com")]
     cert_dir: PathBuf,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Estimates held-out listings and reports the accuracy of the price calculator
+    Backtest {
+        /// `estimated_prices` or `sold`
+        #[clap(long, default_value = "estimated_prices")]
+        source: String,
+        /// Estimator strategy; defaults to the one configured in resources/estimators.yml
+        #[clap(long)]
+        estimator: Option<String>,
+        /// Number of listings to hold out; 0 evaluates all of them
+        #[clap(long, default_value_t = 500)]
+        sample_size: usize,
+        #[clap(long, default_value_t = 0)]
+        offset: usize,
+        /// Restrict the backtest to a single make
+        #[clap(long)]
+        make: Option<String>,
+        /// Report file; a `.csv` extension writes the metrics table, anything else JSON
+        #[clap(short, long, default_value = "backtest.json")]
+        output: PathBuf,
+    },
+    /// Values every vehicle of a CSV or JSON file
+    Valuate {
+        /// Vehicles to value; `.json` files are read as JSON, anything else as CSV
+        #[clap(short, long)]
+        input: PathBuf,
+        /// Estimator strategy for vehicles that do not name one
+        #[clap(long)]
+        estimator: Option<String>,
+        /// Annotated vehicles; a `.json` extension writes JSON, anything else CSV
+        #[clap(short, long, default_value = "valuations.csv")]
+        output: PathBuf,
+    },
+    /// Stores today's listing prices so that price changes can be tracked
+    Snapshot {
+        #[clap(long, default_value = HISTORY_DIR)]
+        dir: PathBuf,
+    },
 }
 
 #[tokio::main]
@@ -43,6 +110,11 @@ async fn main() {
     configure_log4rs("resources/log4rs.yml");
     info!("Starting server...");
     let args = Args::parse();
+    if let Some(command) = args.command {
+        run_command(command);
+        return;
+    }
+    take_snapshot(std::path::Path::new(HISTORY_DIR));
     let cert_dir = args.cert_dir;
     info!("Cert dir: {:?}", cert_dir);
     tracing_subscriber::fmt::format()
@@ -71,8 +143,23 @@ async fn main() {
         .route("/pivot-data", post(pivot_data))
         .route("/pivot-chart", post(pivot_chart_data))
         .route("/calculator", post(calculate))
+        .route("/calculator/batch", post(calculate_batch))
+        .route("/depreciation", post(depreciation_curve))
+        .route("/time-on-market", post(time_on_market_curves))
+        .route("/time-series", post(time_series))
+        .route("/time-series/decomposition", post(decomposition))
+        .route("/time-series/forecast", post(forecast))
+        .route("/liquidity", post(liquidity))
+        .route("/price-gap", post(price_gap))
+        .route("/heatmap", post(heatmap))
+        .route("/box-plot", post(box_plot))
+        .route("/listings/suspicious", post(suspicious))
+        .route("/listings/{advert_id}/history", get(price_history))
         .route("/data-distribution", post(data_bins))
         .route("/data-stat", post(data_stat))
+        .route("/render/pivot-chart", post(render_pivot))
+        .route("/render/time-series", post(render_trend))
+        .route("/render/data-distribution", post(render_bins))
         .route("/enums/{name}", get(enums))
         .route("/enums/{make}/models", get(models))
         //.route("/metrics", get(|| async move { metric_handle.render() }))
@@ -91,6 +178,89 @@ async fn main() {
         .unwrap();
 }
 
+fn run_command(command: Command) {
+    match command {
+        Command::Backtest {
+            source,
+            estimator,
+            sample_size,
+            offset,
+            make,
+            output,
+        } => {
+            let request = BacktestRequest {
+                source: Some(source),
+                estimator,
+                sample_size,
+                offset,
+                filter: StatisticSearchPayload {
+                    make,
+                    ..Default::default()
+                },
+            };
+            match backtest(&request).and_then(|report| {
+                info!(
+                    "Backtest {}: {:?} ({} failed)",
+                    report.strategy, report.overall, report.failed
+                );
+                write_report(&report, &output)
+            }) {
+                Ok(()) => info!("Backtest report written to {:?}", output),
+                Err(err) => error!("Backtest failed: {}", err),
+            }
+        }
+        Command::Valuate {
+            input,
+            estimator,
+            output,
+        } => match valuate_file(&input, estimator, &output) {
+            Ok(count) => info!("{} valuations written to {:?}", count, output),
+            Err(err) => error!("Valuation failed: {}", err),
+        },
+        Command::Snapshot { dir } => take_snapshot(&dir),
+    }
+}
+
+fn take_snapshot(dir: &std::path::Path) {
+    let today = chrono::Utc::now().date_naive();
+    if let Err(err) = write_snapshot(&VEHICLES_DATA, dir, today) {
+        error!("Snapshot failed: {}", err);
+    }
+}
+
+fn is_json(path: &std::path::Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
+
+fn valuate_file(
+    input: &std::path::Path,
+    estimator: Option<String>,
+    output: &std::path::Path,
+) -> Result<usize, String> {
+    let content = std::fs::read_to_string(input).map_err(|e| e.to_string())?;
+    let request = if is_json(input) {
+        parse_batch_json(&content)?
+    } else {
+        BatchValuationRequest {
+            estimator: None,
+            vehicles: parse_vehicles_csv(&content)?,
+        }
+    };
+    let estimator = estimator.or(request.estimator);
+    let valuations = valuate_batch(
+        &request.vehicles,
+        estimator.as_deref(),
+        &ESTIMATED_PRICES_DATA,
+    )?;
+    let file = std::fs::File::create(output).map_err(|e| e.to_string())?;
+    if is_json(output) {
+        serde_json::to_writer_pretty(file, &valuations).map_err(|e| e.to_string())?;
+    } else {
+        write_valuations_csv(&valuations, file)?;
+    }
+    Ok(valuations.len())
+}
+
 // basic handler that responds with a static string
 async fn root() -> &'static str {
     "Hello, World!"
@@ -131,9 +301,238 @@ async fn calculate(Json(payload): Json<StatisticSearchPayload>) -> impl IntoResp
     (StatusCode::OK, Json(response))
 }
 
+/// Accepts a CSV body (`Content-Type: text/csv`) or JSON and answers with CSV when
+/// `Accept: text/csv` or `?format=csv` is given, otherwise with JSON.
+async fn calculate_batch(
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: String,
+) -> impl IntoResponse {
+    let header_contains = |name: header::HeaderName, value: &str| {
+        headers
+            .get(name)
+            .and_then(|h| h.to_str().ok())
+            .is_some_and(|h| h.contains(value))
+    };
+    let request = if header_contains(header::CONTENT_TYPE, "csv") {
+        parse_vehicles_csv(&body).map(|vehicles| BatchValuationRequest {
+            estimator: params.get("estimator").cloned(),
+            vehicles,
+        })
+    } else {
+        parse_batch_json(&body)
+    };
+    let valuations = request.and_then(|request| {
+        info!("Batch valuation of {} vehicles", request.vehicles.len());
+        valuate_batch(
+            &request.vehicles,
+            request.estimator.as_deref(),
+            &ESTIMATED_PRICES_DATA,
+        )
+    });
+    let valuations = match valuations {
+        Ok(valuations) => valuations,
+        Err(err) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(RuntimeErrorResponse { message: err }),
+            )
+                .into_response()
+        }
+    };
+    if header_contains(header::ACCEPT, "text/csv")
+        || params.get("format").is_some_and(|f| f == "csv")
+    {
+        let mut csv = vec![];
+        match write_valuations_csv(&valuations, &mut csv) {
+            Ok(()) => (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, "text/csv")],
+                String::from_utf8_lossy(&csv).to_string(),
+            )
+                .into_response(),
+            Err(err) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(RuntimeErrorResponse { message: err }),
+            )
+                .into_response(),
+        }
+    } else {
+        (StatusCode::OK, Json(valuations)).into_response()
+    }
+}
+
+async fn depreciation_curve(Json(payload): Json<DepreciationRequest>) -> impl IntoResponse {
+    info!("Depreciation: Payload: {:?}", payload);
+    match depreciation(payload) {
+        Ok(model) => (StatusCode::OK, Json(model)).into_response(),
+        Err(err) => (
+            StatusCode::BAD_REQUEST,
+            Json(RuntimeErrorResponse { message: err }),
+        )
+            .into_response(),
+    }
+}
+
+async fn suspicious(Json(payload): Json<StatisticSearchPayload>) -> impl IntoResponse {
+    match suspicious_listings(payload) {
+        Ok(listings) => (StatusCode::OK, Json(listings)).into_response(),
+        Err(err) => (
+            StatusCode::BAD_REQUEST,
+            Json(RuntimeErrorResponse { message: err }),
+        )
+            .into_response(),
+    }
+}
+
+async fn price_history(Path(advert_id): Path<String>) -> impl IntoResponse {
+    match listing_history(std::path::Path::new(HISTORY_DIR), &advert_id) {
+        Ok(history) => (StatusCode::OK, Json(history)).into_response(),
+        Err(err) => (
+            StatusCode::NOT_FOUND,
+            Json(RuntimeErrorResponse { message: err }),
+        )
+            .into_response(),
+    }
+}
+
+async fn time_on_market_curves(Json(payload): Json<TimeOnMarketRequest>) -> impl IntoResponse {
+    info!("Time on market: Payload: {:?}", payload);
+    match time_on_market(payload) {
+        Ok(curves) => (StatusCode::OK, Json(curves)).into_response(),
+        Err(err) => (
+            StatusCode::BAD_REQUEST,
+            Json(RuntimeErrorResponse { message: err }),
+        )
+            .into_response(),
+    }
+}
+
+async fn time_series(Json(payload): Json<TimeSeriesRequest>) -> impl IntoResponse {
+    info!("Time series: Payload: {:?}", payload);
+    match market_trend(payload) {
+        Ok(series) => (StatusCode::OK, Json(series)).into_response(),
+        Err(err) => (
+            StatusCode::BAD_REQUEST,
+            Json(RuntimeErrorResponse { message: err }),
+        )
+            .into_response(),
+    }
+}
+
+async fn decomposition(Json(payload): Json<TimeSeriesRequest>) -> impl IntoResponse {
+    info!("Decomposition: Payload: {:?}", payload);
+    match seasonal_decomposition(payload) {
+        Ok(series) => (StatusCode::OK, Json(series)).into_response(),
+        Err(err) => (
+            StatusCode::BAD_REQUEST,
+            Json(RuntimeErrorResponse { message: err }),
+        )
+            .into_response(),
+    }
+}
+
+async fn forecast(Json(payload): Json<ForecastRequest>) -> impl IntoResponse {
+    info!("Forecast: Payload: {:?}", payload);
+    match market_forecast(payload) {
+        Ok(series) => (StatusCode::OK, Json(series)).into_response(),
+        Err(err) => (
+            StatusCode::BAD_REQUEST,
+            Json(RuntimeErrorResponse { message: err }),
+        )
+            .into_response(),
+    }
+}
+
+async fn liquidity(Json(payload): Json<LiquidityRequest>) -> impl IntoResponse {
+    info!("Liquidity: Payload: {:?}", payload);
+    match liquidity_ranking(payload) {
+        Ok(segments) => (StatusCode::OK, Json(segments)).into_response(),
+        Err(err) => (
+            StatusCode::BAD_REQUEST,
+            Json(RuntimeErrorResponse { message: err }),
+        )
+            .into_response(),
+    }
+}
+
+async fn price_gap(Json(payload): Json<PriceGapRequest>) -> impl IntoResponse {
+    info!("Price gap: Payload: {:?}", payload);
+    match price_gaps(payload) {
+        Ok(gaps) => (StatusCode::OK, Json(gaps)).into_response(),
+        Err(err) => (
+            StatusCode::BAD_REQUEST,
+            Json(RuntimeErrorResponse { message: err }),
+        )
+            .into_response(),
+    }
+}
+
+async fn heatmap(Json(payload): Json<HeatmapRequest>) -> impl IntoResponse {
+    info!("Heatmap: Payload: {:?}", payload);
+    match market_heatmap(payload) {
+        Ok(heatmap) => (StatusCode::OK, Json(heatmap)).into_response(),
+        Err(err) => (
+            StatusCode::BAD_REQUEST,
+            Json(RuntimeErrorResponse { message: err }),
+        )
+            .into_response(),
+    }
+}
+
+async fn box_plot(Json(payload): Json<BoxPlotRequest>) -> impl IntoResponse {
+    info!("Box plot: Payload: {:?}", payload);
+    match group_box_plots(payload) {
+        Ok(plots) => (StatusCode::OK, Json(plots)).into_response(),
+        Err(err) => (
+            StatusCode::BAD_REQUEST,
+            Json(RuntimeErrorResponse { message: err }),
+        )
+            .into_response(),
+    }
+}
+
 async fn data_stat(Json(payload): Json<StatisticSearchPayload>) -> impl IntoResponse {
-    let response = chartData(payload);
-    (StatusCode::OK, Json(response))
+    match chartData(payload) {
+        Ok(response) => (StatusCode::OK, Json(response)).into_response(),
+        Err(err) => (
+            StatusCode::BAD_REQUEST,
+            Json(RuntimeErrorResponse { message: err }),
+        )
+            .into_response(),
+    }
+}
+
+/// The rendered chart as an image of `format`.
+fn image_response(image: Result<Vec<u8>, String>, format: ImageFormat) -> Response {
+    match image {
+        Ok(image) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, format.content_type())],
+            image,
+        )
+            .into_response(),
+        Err(err) => (
+            StatusCode::BAD_REQUEST,
+            Json(RuntimeErrorResponse { message: err }),
+        )
+            .into_response(),
+    }
+}
+
+async fn render_pivot(Json(payload): Json<RenderRequest<PivotData>>) -> impl IntoResponse {
+    let format = payload.render.format;
+    image_response(render_pivot_chart(payload), format)
+}
+
+async fn render_trend(Json(payload): Json<RenderRequest<TimeSeriesRequest>>) -> impl IntoResponse {
+    let format = payload.render.format;
+    image_response(render_time_series(payload), format)
+}
+
+async fn render_bins(Json(payload): Json<RenderRequest<DataToBinsRequest>>) -> impl IntoResponse {
+    let format = payload.render.format;
+    image_response(render_distribution(payload), format)
 }
 
 async fn data_bins(Json(payload): Json<DataToBinsRequest>) -> impl IntoResponse {
@@ -144,6 +543,10 @@ async fn data_bins(Json(payload): Json<DataToBinsRequest>) -> impl IntoResponse
         payload.distribution_type,
         payload.number_of_bins,
     );
+    let response = match (response, &payload.vega_lite) {
+        (Ok(data), Some(intent)) => distribution_vega_lite(&data, intent),
+        (response, _) => response.map(|data| serde_json::json!(data)),
+    };
 
     match response {
         Ok(json) => (StatusCode::OK, Json(json)).into_response(),
@@ -12,11 +12,15 @@ use polars::{
     lazy::frame::{LazyCsvReader, LazyFileListReader, LazyFrame},
     prelude::{Field, Schema},
 };
+use services::BucketService::{with_breakdowns, BUCKETS};
 
 pub const VEHICLE_DATA_VIEW_FILE: &str = "./resources/Vehicles.csv";
 pub const STAT_PRICE_DATA_FILE: &str = "./resources/Prices.csv";
 pub const ESTIMATED_PRICES_DATA_FILE: &str = "./resources/PriceCalculatorData.csv";
 pub const VEHICLE_STATISTIC_DATA_FILE: &str = "./resources/VehicleStatistic.csv";
+pub const ESTIMATORS_CONFIG_FILE: &str = "./resources/estimators.yml";
+pub const BUCKETS_CONFIG_FILE: &str = "./resources/buckets.yml";
+pub const HISTORY_DIR: &str = "./resources/history";
 
 lazy_static! {
     static ref INIT_LOGGER: Once = Once::new();
@@ -135,38 +139,41 @@ lazy_static! {
         });
         let path = vec![PathBuf::from(VEHICLE_DATA_VIEW_FILE)];
         let param = Arc::from(path);
-        LazyCsvReader::new(VEHICLE_DATA_VIEW_FILE)
+        let df = LazyCsvReader::new(VEHICLE_DATA_VIEW_FILE)
             .with_paths(param)
             .with_try_parse_dates(true)
             .with_separator(b';')
             .with_schema(Some(VEHICLE_DATA_VIEW_SCHEMA.clone()))
             .finish()
-            .unwrap()
+            .unwrap();
+        with_breakdowns(df, &BUCKETS, &["mileage", "power"])
 
     };
 
     pub static ref PRICE_DATA: polars::prelude::LazyFrame = {
         let path = vec![PathBuf::from(STAT_PRICE_DATA_FILE)];
         let param = Arc::from(path);
-        LazyCsvReader::new(STAT_PRICE_DATA_FILE)
+        let df = LazyCsvReader::new(STAT_PRICE_DATA_FILE)
             .with_paths(param)
             .with_try_parse_dates(true)
             .with_separator(b';')
             .with_schema(Some(PRICES_SCHEMA.clone()))
             .finish()
-            .unwrap()
+            .unwrap();
+        with_breakdowns(df, &BUCKETS, &["mileage", "power"])
     };
 
     pub static ref ESTIMATED_PRICES_DATA: polars::prelude::LazyFrame = {
         let path = vec![PathBuf::from(ESTIMATED_PRICES_DATA_FILE)];
         let param = Arc::from(path);
-        LazyCsvReader::new(ESTIMATED_PRICES_DATA_FILE)
+        let df = LazyCsvReader::new(ESTIMATED_PRICES_DATA_FILE)
             .with_paths(param)
             .with_try_parse_dates(true)
             .with_separator(b';')
             .with_schema(Some(ESTIMATED_PRICES_SCHEMA.clone()))
             .finish()
-            .unwrap()
+            .unwrap();
+        with_breakdowns(df, &BUCKETS, &["mileage", "power"])
     };
 
     pub static ref VEHICLE_STATIC_DATA: polars::prelude::LazyFrame = {
@@ -176,13 +183,14 @@ lazy_static! {
         });
         let path = vec![PathBuf::from(VEHICLE_STATISTIC_DATA_FILE)];
         let param = Arc::from(path);
-        LazyCsvReader::new(VEHICLE_STATISTIC_DATA_FILE)
+        let df = LazyCsvReader::new(VEHICLE_STATISTIC_DATA_FILE)
             .with_paths(param)
             .with_try_parse_dates(true)
             .with_separator(b';')
             .with_schema(Some(VEHICLE_STATISTIC_SCHEMA.clone()))
             .finish()
-            .unwrap()
+            .unwrap();
+        with_breakdowns(df, &BUCKETS, &["mileage", "power"])
 
     };
 
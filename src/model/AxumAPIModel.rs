@@ -1,6 +1,11 @@
 use serde::{Deserialize, Serialize};
 
-use super::DistributionType;
+use super::{
+    Chart::{ChartIntent, GapFill, LabelOrder, PivotSeries, RenderOptions, TopN, Transform},
+    DistributionType,
+    TimeSeries::{Period, TrendMetric},
+    Valuation::OutlierMethod,
+};
 
 #[derive(Deserialize)]
 pub struct DataToBinsRequest {
@@ -9,6 +14,8 @@ pub struct DataToBinsRequest {
     pub all: bool,
     pub distribution_type: DistributionType,
     pub number_of_bins: usize,
+    /// Answer with a Vega-Lite specification of the bins instead of the bins.
+    pub vega_lite: Option<ChartIntent>,
 }
 
 #[derive(Deserialize)]
@@ -21,6 +28,7 @@ pub struct StatisticSearchPayload {
     pub search: Option<String>,
     pub make: Option<String>,
     pub model: Option<String>,
+    pub modelFamily: Option<String>,
 
     pub engine: Option<Vec<String>>,
     pub gearbox: Option<String>,
@@ -52,22 +60,160 @@ pub struct StatisticSearchPayload {
 
     pub group: Option<Vec<String>>,
     pub aggregators: Option<Vec<String>>,
+    #[serde(default)]
     pub order: Vec<Order>,
     pub stat_column: Option<String>,
     pub estimated_price: Option<i32>,
     pub price: Option<i32>,
     pub priceFrom: Option<i32>,
     pub priceTo: Option<i32>,
+    pub estimator: Option<String>,
+    pub comparables: Option<usize>,
+    /// Loosen the request with the relaxation ladder of resources/estimators.yml while
+    /// too few comparables match.
+    pub relax: Option<bool>,
+    /// Deal ratings to keep: great, good, fair or overpriced.
+    pub dealRating: Option<Vec<String>>,
+    pub dealScoreTo: Option<f64>,
+    /// Only listings whose price dropped between two snapshots of the last N days.
+    pub priceDroppedInDays: Option<i32>,
+    /// Count every likely duplicate of a car posted on several sources only once.
+    pub deduplicate: Option<bool>,
+    /// Leave out listings tagged as outliers within their make, model and year.
+    pub excludeOutliers: Option<bool>,
+    /// iqr, mad or zscore; defaults to the method configured in resources/estimators.yml.
+    pub outlierMethod: Option<OutlierMethod>,
+    /// Scale the calculator's estimate by the asking-price gap of sold listings.
+    pub soldCorrection: Option<bool>,
+    /// Only the top (or bottom) values of these group columns, the rest as one bucket.
+    #[serde(default)]
+    pub topN: Vec<TopN>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, Default)]
 pub struct PivotData {
     pub x_column: String,
     pub second_x_column: Option<String>,
+    #[serde(default)]
     pub y_column: String,
+    #[serde(default)]
     pub y_function: String,
     pub pivot_column: Option<String>,
     pub filter: StatisticSearchPayload,
+    /// Aggregated series drawn over the same labels; replaces `y_function` when given.
+    #[serde(default)]
+    pub series: Vec<PivotSeries>,
+    /// Natural order by default; breakdown columns by their `_order` column and, with a
+    /// sort `order` in the filter, the sorted order of the groups.
+    pub label_order: Option<LabelOrder>,
+    /// Missing values are 0 unless `null` is asked for.
+    #[serde(default)]
+    pub fill: GapFill,
+    /// Applied in this order to the aggregated values, e.g. `percent_of_row` for shares.
+    /// Gaps are filled with `fill` beforehand, so with 0 a gap is a drop for `diff`.
+    #[serde(default)]
+    pub transforms: Vec<Transform>,
+    /// Answer with a Vega-Lite specification instead of Chart.js labels and datasets.
+    pub vega_lite: Option<ChartIntent>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+pub struct DepreciationRequest {
+    pub make: String,
+    pub model: String,
+    pub engine: Option<String>,
+    pub source: Option<String>,
+    pub reference_year: Option<i32>,
+    pub age: Option<i32>,
+    pub mileage: Option<i32>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+pub struct LiquidityRequest {
+    /// Number of segments returned, 50 by default.
+    pub limit: Option<usize>,
+    /// Least liquid segments first.
+    #[serde(default)]
+    pub ascending: bool,
+    #[serde(default)]
+    pub filter: StatisticSearchPayload,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+pub struct PriceGapRequest {
+    /// Columns the gaps are reported by; make, model and year by default.
+    #[serde(default)]
+    pub group: Vec<String>,
+    #[serde(default)]
+    pub filter: StatisticSearchPayload,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+pub struct TimeOnMarketRequest {
+    /// Column the curves are split by, e.g. `make` or `mileage_breakdown`;
+    /// `price_band` splits by `price_bands`. Without it a single curve is returned.
+    pub group: Option<String>,
+    /// Upper bounds in EUR of the price bands.
+    #[serde(default)]
+    pub price_bands: Vec<i32>,
+    /// Listings on the market for longer are treated as unsold at this day.
+    pub max_days: Option<i32>,
+    #[serde(default)]
+    pub filter: StatisticSearchPayload,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+pub struct TimeSeriesRequest {
+    #[serde(default)]
+    pub period: Period,
+    #[serde(default)]
+    pub metric: TrendMetric,
+    /// Column every series is split by, e.g. `make`; a single series without it.
+    pub group: Option<String>,
+    /// Adds a trailing mean over this many periods to every dataset.
+    pub rolling: Option<usize>,
+    /// Adds the change to the previous period in percent to every dataset.
+    #[serde(default)]
+    pub change: bool,
+    /// Periods per season of the decomposition; 52 for weeks and 12 for months by default.
+    pub season_length: Option<usize>,
+    /// Applied in this order to the values of every period before `rolling` and `change`.
+    #[serde(default)]
+    pub transforms: Vec<Transform>,
+    #[serde(default)]
+    pub filter: StatisticSearchPayload,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+pub struct ForecastRequest {
+    /// Weeks by default.
+    pub period: Option<Period>,
+    /// Number of periods to forecast.
+    pub horizon: Option<usize>,
+    /// Periods per season; 52 for weeks and 12 for months by default.
+    pub season_length: Option<usize>,
+    /// Periods held out to measure the accuracy; the horizon by default.
+    pub holdout: Option<usize>,
+    #[serde(default)]
+    pub filter: StatisticSearchPayload,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+pub struct BatchValuationRequest {
+    /// Used for every vehicle that does not name its own estimator.
+    pub estimator: Option<String>,
+    pub vehicles: Vec<StatisticSearchPayload>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+pub struct BacktestRequest {
+    /// `estimated_prices` (default) or `sold`.
+    pub source: Option<String>,
+    pub estimator: Option<String>,
+    /// Number of listings to hold out; 0 evaluates every listing.
+    pub sample_size: usize,
+    pub offset: usize,
+    pub filter: StatisticSearchPayload,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, Default)]
@@ -76,6 +222,47 @@ pub struct Order {
     pub asc: bool,
 }
 
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+pub struct HeatmapRequest {
+    /// Column whose values make the rows, e.g. `year`.
+    pub row: String,
+    /// Column whose values make the columns, e.g. `mileage_breakdown`.
+    pub column: String,
+    /// Any aggregator of `/statistic`; `count` by default.
+    pub aggregator: Option<String>,
+    /// Aggregated column; the filter's `stat_column` or `price_in_eur` by default.
+    pub value_column: Option<String>,
+    /// Breakdown columns by their `_order` column, other columns naturally by default.
+    pub row_order: Option<LabelOrder>,
+    pub column_order: Option<LabelOrder>,
+    /// Colours from the smallest to the largest value as `#rrggbb` or `rgb(a)(...)`.
+    #[serde(default)]
+    pub colors: Vec<String>,
+    #[serde(default)]
+    pub filter: StatisticSearchPayload,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+pub struct BoxPlotRequest {
+    /// Outliers returned per group at most; 50 by default.
+    pub max_outliers: Option<usize>,
+    /// Adds a kernel density estimate of every group for violin plots.
+    #[serde(default)]
+    pub density: bool,
+    /// Points of the density estimate; 50 by default.
+    pub density_points: Option<usize>,
+    /// Listings filter with the `group` columns and the `stat_column` (default `price_in_eur`).
+    pub filter: StatisticSearchPayload,
+}
+
+/// A chart request of another endpoint, answered with an image of its chart.
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+pub struct RenderRequest<T> {
+    pub chart: T,
+    #[serde(default)]
+    pub render: RenderOptions,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct RuntimeErrorResponse {
     pub message: String,
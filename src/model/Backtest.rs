@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+
+/// A held-out listing estimated against the remaining data.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct BacktestObservation {
+    pub make: String,
+    pub model: String,
+    pub year: i32,
+    pub actual: f64,
+    pub estimation: f64,
+    pub lower: f64,
+    pub upper: f64,
+    pub comparables: usize,
+}
+
+impl BacktestObservation {
+    pub fn absolute_error(&self) -> f64 {
+        (self.estimation - self.actual).abs()
+    }
+
+    pub fn absolute_percentage_error(&self) -> f64 {
+        self.absolute_error() / self.actual * 100.0
+    }
+
+    pub fn in_range(&self) -> bool {
+        self.actual >= self.lower && self.actual <= self.upper
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct ErrorMetrics {
+    pub count: usize,
+    /// Mean absolute error in EUR.
+    pub mae: f64,
+    /// Mean absolute percentage error.
+    pub mape: f64,
+    /// Median absolute percentage error.
+    pub median_ape: f64,
+    /// Percentage of listings whose price falls within the estimated range.
+    pub coverage: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct SegmentMetrics {
+    pub dimension: String,
+    pub segment: String,
+    pub metrics: ErrorMetrics,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct BacktestReport {
+    pub strategy: String,
+    pub source: String,
+    pub sampled: usize,
+    /// Held-out listings for which no estimate could be produced.
+    pub failed: usize,
+    pub overall: ErrorMetrics,
+    pub segments: Vec<SegmentMetrics>,
+    pub observations: Vec<BacktestObservation>,
+}
@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// A closed range `from..=to` of a numeric column.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Bucket {
+    pub from: i32,
+    pub to: i32,
+    pub label: String,
+    /// Sort key of the bucket; written to the `<name>_breakdown_order` column.
+    pub order: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BucketDefinition {
+    /// Column the buckets are computed from.
+    pub column: String,
+    pub unit: String,
+    pub buckets: Vec<Bucket>,
+    /// Values offered by "up to" dropdowns; the bucket boundaries when empty.
+    #[serde(default)]
+    pub options: Vec<i32>,
+}
+
+impl BucketDefinition {
+    pub fn find(&self, value: i32) -> Option<&Bucket> {
+        self.buckets
+            .iter()
+            .find(|b| value >= b.from && value <= b.to)
+    }
+
+    /// The `(from, to)` pairs used by the price calculator.
+    pub fn ranges(&self) -> Vec<(i32, i32)> {
+        self.buckets.iter().map(|b| (b.from, b.to)).collect()
+    }
+}
+
+/// Bucket definitions shared by the calculator, the enum dropdowns and the
+/// `*_breakdown` columns. `version` is bumped whenever a boundary changes.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BucketConfig {
+    pub version: u32,
+    pub definitions: BTreeMap<String, BucketDefinition>,
+}
+
+fn bucket(from: i32, to: i32, label: &str, order: i32) -> Bucket {
+    Bucket {
+        from,
+        to,
+        label: label.to_string(),
+        order,
+    }
+}
+
+impl Default for BucketConfig {
+    fn default() -> Self {
+        let mut definitions = BTreeMap::new();
+        definitions.insert(
+            "mileage".to_string(),
+            BucketDefinition {
+                column: "mileage".to_string(),
+                unit: "km".to_string(),
+                buckets: vec![
+                    bucket(0, 20000, "Less than 20 000 km", 1),
+                    bucket(20001, 40000, "Between 20 000 km and 40 000 km", 2),
+                    bucket(40001, 60000, "Between 40 000 km and 60 000 km", 3),
+                    bucket(60001, 80000, "Between 60 000 km and 80 000 km", 4),
+                    bucket(80001, 100000, "Between 80 000 km and 100 000 km", 5),
+                    bucket(100001, 120000, "Between 100 000 km and 120 000 km", 6),
+                    bucket(120001, 150000, "Between 120 000 km and 150 000 km", 7),
+                    bucket(150001, 999999, "Over 150 000 km", 10),
+                ],
+                options: vec![
+                    5000, 10000, 20000, 30000, 40000, 50000, 60000, 70000, 80000, 90000, 100000,
+                    125000, 150000,
+                ],
+            },
+        );
+        definitions.insert(
+            "power".to_string(),
+            BucketDefinition {
+                column: "power".to_string(),
+                unit: "hp".to_string(),
+                buckets: vec![
+                    bucket(0, 90, "Up to 90 hp", 1),
+                    bucket(91, 130, "91 - 130 hp", 2),
+                    bucket(131, 150, "131 - 150 hp", 3),
+                    bucket(151, 200, "151 - 200 hp", 4),
+                    bucket(201, 252, "201 - 252 hp", 5),
+                    bucket(253, 303, "253 - 303 hp", 6),
+                    bucket(304, 358, "304 - 358 hp", 7),
+                    bucket(359, 404, "359 - 404 hp", 8),
+                    bucket(405, 454, "405 - 454 hp", 9),
+                    bucket(455, 9999, "Over 454 hp", 10),
+                ],
+                options: vec![
+                    34, 50, 60, 75, 90, 101, 118, 131, 150, 200, 252, 303, 358, 402, 454,
+                ],
+            },
+        );
+        BucketConfig {
+            version: 1,
+            definitions,
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use serde::{Deserialize, Serialize};
+
+/// How a dataset is drawn in a mixed Chart.js chart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ChartType {
+    #[default]
+    Bar,
+    Line,
+}
+
+/// The y axis a dataset is plotted against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Axis {
+    #[default]
+    Left,
+    Right,
+}
+
+impl Axis {
+    /// Chart.js `yAxisID` of the axis.
+    pub fn id(&self) -> &'static str {
+        match self {
+            Axis::Left => "y",
+            Axis::Right => "y1",
+        }
+    }
+}
+
+/// One aggregated y series of a pivot chart, e.g. the count as bars and the median
+/// price as a line on the right axis.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct PivotSeries {
+    /// Any aggregator of `/statistic`: count, median, mean, quantile_75, ...
+    pub aggregator: String,
+    /// Aggregated column; the filter's `stat_column` or `price_in_eur` by default.
+    pub column: Option<String>,
+    /// Dataset label; `<aggregator>_<column>` by default.
+    pub label: Option<String>,
+    #[serde(default)]
+    pub chart_type: ChartType,
+    #[serde(default)]
+    pub axis: Axis,
+}
+
+impl PivotSeries {
+    pub fn column_or<'a>(&'a self, default: &'a str) -> &'a str {
+        self.column.as_deref().unwrap_or(default)
+    }
+
+    pub fn label_or(&self, default_column: &str) -> String {
+        self.label
+            .clone()
+            .unwrap_or_else(|| format!("{}_{}", self.aggregator, self.column_or(default_column)))
+    }
+}
+
+/// Order of the labels (x values) of a chart.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LabelOrder {
+    /// Alphabetical, with the numbers within the labels compared by value ("2" before "10").
+    #[default]
+    Natural,
+    /// By the total of all datasets, largest first.
+    ValueDesc,
+    /// By the total of all datasets, smallest first.
+    ValueAsc,
+    /// These labels first, in this order; the others naturally after them.
+    Custom(Vec<String>),
+    /// By the smallest value of a column per label.
+    Column(String),
+    /// By `<x_column>_order`, e.g. `mileage_breakdown_order` for `mileage_breakdown`.
+    Breakdown,
+}
+
+/// Value of a dataset at a label without data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum GapFill {
+    Null,
+    #[default]
+    Zero,
+}
+
+/// Keeps the `limit` values of `column` with the largest (or smallest) metric and
+/// collapses all other values into one bucket, e.g. the ten most listed models.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct TopN {
+    pub column: String,
+    pub limit: usize,
+    /// Aggregator the values are ranked by; `count` by default.
+    pub by: Option<String>,
+    /// Column of the ranking aggregator; the filter's `stat_column` by default.
+    pub by_column: Option<String>,
+    /// Keep the smallest instead of the largest values.
+    #[serde(default)]
+    pub bottom: bool,
+    /// Label of the remaining values; `Other` by default.
+    pub other_label: Option<String>,
+    /// Leave the remaining values out instead of collapsing them.
+    #[serde(default)]
+    pub drop_other: bool,
+}
+
+impl TopN {
+    pub fn by_or(&self) -> &str {
+        self.by.as_deref().unwrap_or("count")
+    }
+
+    pub fn other_label_or(&self) -> &str {
+        self.other_label.as_deref().unwrap_or("Other")
+    }
+}
+
+/// Recalculation of the aggregated values of a chart, applied after the labels are ordered.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transform {
+    /// Percent of the total of all datasets at the same label, e.g. the market share per year.
+    PercentOfRow,
+    /// Percent of the total of the dataset.
+    PercentOfColumn,
+    /// Percent of the total of all datasets at all labels.
+    PercentOfTotal,
+    /// Running total over the labels.
+    Cumulative,
+    /// Running total in percent of the total of the dataset, e.g. a cumulative distribution.
+    RunningShare,
+    /// Values relative to the value at this label (the first label with a value other than
+    /// 0 when null) = 100.
+    IndexToBase(Option<String>),
+    /// Change to the value at the previous label.
+    Diff,
+}
+
+/// Vega-Lite mark of a [`ChartIntent`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Mark {
+    #[default]
+    Bar,
+    Line,
+    /// Rectangles of `x` × `y` coloured by the value.
+    Heatmap,
+    /// Box plot of the listing values of `y` per `x`.
+    Boxplot,
+    /// One point per listing.
+    Scatter,
+}
+
+impl Mark {
+    /// Whether the chart is drawn from listings instead of aggregated values.
+    pub fn uses_listings(&self) -> bool {
+        matches!(self, Mark::Boxplot | Mark::Scatter)
+    }
+}
+
+/// What a Vega-Lite chart shows; unset encodings are taken from the request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct ChartIntent {
+    #[serde(default)]
+    pub mark: Mark,
+    pub x: Option<String>,
+    pub y: Option<String>,
+    pub color: Option<String>,
+    /// Point size of a scatter chart.
+    pub size: Option<String>,
+    pub title: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Vega colour scheme, e.g. `tableau10` or `viridis`.
+    pub scheme: Option<String>,
+}
+
+/// Image format of a server-side rendered chart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageFormat {
+    #[default]
+    Svg,
+    Png,
+}
+
+impl ImageFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Svg => "image/svg+xml",
+            ImageFormat::Png => "image/png",
+        }
+    }
+}
+
+/// Look of a server-side rendered chart.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct RenderOptions {
+    #[serde(default)]
+    pub format: ImageFormat,
+    /// Pixels; 800 by default.
+    pub width: Option<u32>,
+    /// Pixels; 450 by default.
+    pub height: Option<u32>,
+    pub title: Option<String>,
+    pub x_label: Option<String>,
+    pub y_label: Option<String>,
+    /// Colours of the datasets as `#rrggbb` or `rgb(a)(...)`; the Chart.js colours by default.
+    #[serde(default)]
+    pub palette: Vec<String>,
+}
+
+/// Colour of the smallest and the largest value of a heatmap, with optional stops between.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColorScale {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// Evenly spread from `min` to `max`.
+    pub colors: Vec<String>,
+}
+
+/// Dense matrix of one aggregate per row and column value, e.g. the median price per
+/// year and mileage bucket.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Heatmap {
+    pub row_column: String,
+    pub column_column: String,
+    pub rows: Vec<String>,
+    pub columns: Vec<String>,
+    /// `values[row][column]`; null without listings.
+    pub values: Vec<Vec<Option<f64>>>,
+    /// Listings per cell.
+    pub counts: Vec<Vec<u32>>,
+    /// The aggregate over all listings of a row, not a sum of the cells.
+    pub row_totals: Vec<Option<f64>>,
+    pub column_totals: Vec<Option<f64>>,
+    pub total: Option<f64>,
+    /// Colour of every cell on `scale`; null without a value.
+    pub colors: Vec<Vec<Option<String>>>,
+    pub scale: Option<ColorScale>,
+}
+
+/// Kernel density estimate of a group for a violin plot.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct Density {
+    /// Evenly spread from the smallest to the largest value.
+    pub x: Vec<f64>,
+    pub y: Vec<f64>,
+    /// Gaussian kernel bandwidth (Silverman's rule of thumb).
+    pub bandwidth: f64,
+}
+
+/// Spread of the values of one group for a box plot.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct BoxPlot {
+    /// Values of the group columns, joined with ", ".
+    pub label: String,
+    pub group: Vec<String>,
+    pub count: usize,
+    pub mean: f64,
+    pub min: f64,
+    pub q1: f64,
+    pub median: f64,
+    pub q3: f64,
+    pub max: f64,
+    /// Smallest and largest value within 1.5 interquartile ranges of the quartiles.
+    pub lower_whisker: f64,
+    pub upper_whisker: f64,
+    /// The most extreme values beyond the whiskers, sorted.
+    pub outliers: Vec<f64>,
+    /// All values beyond the whiskers, also those left out of `outliers`.
+    pub outlier_count: usize,
+    pub density: Option<Density>,
+}
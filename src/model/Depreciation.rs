@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct CurvePoint {
+    pub age: i32,
+    pub mileage: i32,
+    pub value: i32,
+    pub lower: i32,
+    pub upper: i32,
+}
+
+/// Log-linear depreciation model `ln(price) = b0 + b_age * age + b_mileage * mileage / 10 000`
+/// fitted for a single make/model/engine.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct DepreciationModel {
+    pub make: String,
+    pub model: String,
+    pub engine: Option<String>,
+    pub reference_year: i32,
+    pub count: usize,
+    pub intercept: f64,
+    pub age_coefficient: f64,
+    pub mileage_coefficient: f64,
+    /// Share of value lost per year of age at constant mileage.
+    pub annual_depreciation: f64,
+    /// Share of value lost per additional 10 000 km at constant age.
+    pub mileage_depreciation: f64,
+    pub annual_mileage: i32,
+    pub r_squared: f64,
+    /// Standard deviation of the residuals in log space.
+    pub residual_std: f64,
+    pub curve: Vec<CurvePoint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub valuation: Option<CurvePoint>,
+}
+
+impl DepreciationModel {
+    /// Expected value for a vehicle of the given age and mileage together with
+    /// a 95% range derived from the residual dispersion.
+    pub fn value_at(&self, age: i32, mileage: i32) -> CurvePoint {
+        let log_value = self.intercept
+            + self.age_coefficient * age as f64
+            + self.mileage_coefficient * mileage as f64 / 10_000.0;
+        let spread = 1.96 * self.residual_std;
+        CurvePoint {
+            age,
+            mileage,
+            value: log_value.exp().round() as i32,
+            lower: (log_value - spread).exp().round() as i32,
+            upper: (log_value + spread).exp().round() as i32,
+        }
+    }
+}
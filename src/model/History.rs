@@ -0,0 +1,26 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+/// Price of a listing in one dataset snapshot.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct PricePoint {
+    pub date: Option<NaiveDate>,
+    pub price: Option<i32>,
+    pub currency: String,
+    pub price_in_eur: Option<i32>,
+    /// Difference to the previous snapshot in EUR; `None` for the first one.
+    pub change: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct ListingHistory {
+    pub advert_id: String,
+    pub first_seen: Option<NaiveDate>,
+    pub last_seen: Option<NaiveDate>,
+    pub first_price: Option<i32>,
+    pub current_price: Option<i32>,
+    pub price_changes: usize,
+    pub last_price_drop: Option<NaiveDate>,
+    /// One point per snapshot in which the price differed from the previous one.
+    pub history: Vec<PricePoint>,
+}
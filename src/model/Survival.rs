@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+
+/// Kaplan–Meier estimate after the listings sold on `day`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct SurvivalPoint {
+    pub day: i32,
+    /// Listings still for sale at the start of the day.
+    pub at_risk: usize,
+    pub sold: usize,
+    /// Listings last seen unsold on that day.
+    pub censored: usize,
+    /// Probability that a listing is still unsold after `day` days.
+    pub survival: f64,
+}
+
+/// Time-to-sale curve of one segment, ready to be drawn as a Chart.js line dataset.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SurvivalCurve {
+    pub label: String,
+    /// Survival at every day of [`TimeOnMarket::labels`].
+    pub data: Vec<f64>,
+    pub border_color: String,
+    pub count: usize,
+    pub sold: usize,
+    pub censored: usize,
+    /// First day on which at most half of the listings are still unsold.
+    pub median_days: Option<i32>,
+    pub points: Vec<SurvivalPoint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct TimeOnMarket {
+    /// Days on the market.
+    pub labels: Vec<i32>,
+    pub datasets: Vec<SurvivalCurve>,
+}
@@ -0,0 +1,86 @@
+use chrono::{Datelike, Months, NaiveDate, TimeDelta};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Period {
+    Week,
+    #[default]
+    Month,
+}
+
+impl Period {
+    /// First day of the period containing `date`; weeks start on Monday.
+    pub fn start(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Week => date - TimeDelta::days(date.weekday().num_days_from_monday() as i64),
+            Period::Month => date.with_day(1).unwrap(),
+        }
+    }
+
+    pub fn next(&self, start: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Week => start + TimeDelta::days(7),
+            Period::Month => start + Months::new(1),
+        }
+    }
+
+    pub fn label(&self, start: NaiveDate) -> String {
+        match self {
+            Period::Week => {
+                let week = start.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            }
+            Period::Month => start.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TrendMetric {
+    /// Listings created in the period.
+    #[default]
+    NewListings,
+    /// Listings sold in the period.
+    Sold,
+    /// Median asking price in EUR of the listings created in the period.
+    MedianPrice,
+    /// Listings created before the end of the period and not sold by then.
+    ActiveInventory,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ForecastMethod {
+    /// Additive Holt-Winters: level, trend and season.
+    #[default]
+    HoltWinters,
+    /// Holt's linear trend, used when there are fewer than two seasons of history.
+    Holt,
+}
+
+/// Point forecasts with a 95% prediction interval for the periods after the history.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct Forecast {
+    pub method: ForecastMethod,
+    pub alpha: f64,
+    pub beta: f64,
+    pub gamma: f64,
+    pub forecast: Vec<f64>,
+    pub lower: Vec<f64>,
+    pub upper: Vec<f64>,
+    /// Root mean squared one-step error on the history.
+    pub rmse: f64,
+}
+
+/// Accuracy of a forecast fitted without the last `holdout` periods.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct ForecastAccuracy {
+    pub holdout: usize,
+    pub mae: f64,
+    /// Mean absolute percentage error over the periods with a non-zero value.
+    pub mape: Option<f64>,
+    /// Share of held-out values within the prediction interval.
+    pub coverage: f64,
+}
@@ -0,0 +1,441 @@
+use serde::{Deserialize, Serialize};
+
+/// Output of a price estimator for a single vehicle.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct Estimate {
+    pub strategy: String,
+    pub estimation: f64,
+    pub lower: f64,
+    pub upper: f64,
+    /// Between 0 and 1; grows with the number of comparables and shrinks with their dispersion.
+    pub confidence: f64,
+    pub count: usize,
+    pub explanation: String,
+}
+
+/// A change the calculator made to the requested filter to find comparables.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct RelaxationStep {
+    pub step: String,
+    pub description: String,
+    /// Number of comparables after the step was applied.
+    pub count: usize,
+}
+
+/// A rung of the relaxation ladder, applied when too few comparables were found.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Relaxation {
+    /// Extends the mileage range by the neighbouring buckets.
+    WidenMileage,
+    /// Extends the power range by the neighbouring buckets.
+    WidenPower,
+    /// Accepts one year before and after the requested one.
+    YearRange,
+    DropGearbox,
+    DropEngine,
+    /// Replaces the model by every model of its family, e.g. 320 by 3xx.
+    ModelFamily,
+}
+
+impl Relaxation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Relaxation::WidenMileage => "widen_mileage",
+            Relaxation::WidenPower => "widen_power",
+            Relaxation::YearRange => "year_range",
+            Relaxation::DropGearbox => "drop_gearbox",
+            Relaxation::DropEngine => "drop_engine",
+            Relaxation::ModelFamily => "model_family",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RelaxationConfig {
+    /// The ladder stops as soon as at least this many comparables are found.
+    pub min_sample_size: usize,
+    pub ladder: Vec<Relaxation>,
+}
+
+impl Default for RelaxationConfig {
+    fn default() -> Self {
+        RelaxationConfig {
+            min_sample_size: 10,
+            ladder: vec![
+                Relaxation::WidenMileage,
+                Relaxation::WidenPower,
+                Relaxation::YearRange,
+                Relaxation::DropGearbox,
+                Relaxation::DropEngine,
+                Relaxation::ModelFamily,
+            ],
+        }
+    }
+}
+
+/// A listing similar to the valued vehicle.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct ComparableListing {
+    pub advert_id: String,
+    pub title: String,
+    pub make: String,
+    pub model: String,
+    pub year: Option<i32>,
+    pub mileage: Option<i32>,
+    pub power: Option<i32>,
+    pub price_in_eur: Option<i32>,
+    pub url: String,
+    /// 1 for an identical vehicle, approaching 0 as year, mileage and power diverge.
+    pub similarity: f64,
+}
+
+/// A vehicle of a batch valuation as read from a CSV row.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct BatchVehicle {
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub year: Option<i32>,
+    pub engine: Option<String>,
+    pub gearbox: Option<String>,
+    pub power: Option<i32>,
+    pub mileage: Option<i32>,
+    pub cc: Option<i32>,
+    pub estimator: Option<String>,
+}
+
+/// One vehicle of a batch valuation annotated with its estimate. `status` is `ok`
+/// or the reason the vehicle could not be valued.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct BatchValuation {
+    pub row: usize,
+    pub make: String,
+    pub model: String,
+    pub year: Option<i32>,
+    pub engine: String,
+    pub gearbox: String,
+    pub power: Option<i32>,
+    pub mileage: Option<i32>,
+    pub status: String,
+    pub estimator: String,
+    pub estimation: Option<i32>,
+    pub lower: Option<i32>,
+    pub upper: Option<i32>,
+    pub confidence: Option<f64>,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WeightKind {
+    /// The configured value is used as is.
+    #[default]
+    Fixed,
+    /// The configured value is multiplied by `1 - rsd`.
+    Scaled,
+    /// The weight is the relative standard deviation itself.
+    Rsd,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StatisticWeight {
+    pub statistic: String,
+    #[serde(default)]
+    pub kind: WeightKind,
+    #[serde(default)]
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WeightBand {
+    /// The band applies when the RSD of the comparables is at most this value.
+    /// `None` matches every RSD and should be used by the last band.
+    pub max_rsd: Option<f64>,
+    #[serde(default)]
+    pub normalize: bool,
+    pub weights: Vec<StatisticWeight>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WeightedQuantileConfig {
+    pub bands: Vec<WeightBand>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MedianConfig {
+    pub lower_quantile: f64,
+    pub upper_quantile: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct KnnConfig {
+    pub k: usize,
+    pub year_scale: f64,
+    pub mileage_scale: f64,
+    pub power_scale: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RegressionConfig {
+    pub min_observations: usize,
+}
+
+/// Z-score limits of the deal ratings; a listing priced above `fair` is overpriced.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DealScoreConfig {
+    /// Segments with fewer priced listings fall back to make, model and year.
+    pub min_segment_size: usize,
+    pub great: f64,
+    pub good: f64,
+    pub fair: f64,
+}
+
+impl Default for DealScoreConfig {
+    fn default() -> Self {
+        DealScoreConfig {
+            min_segment_size: 5,
+            great: -1.0,
+            good: -0.5,
+            fair: 0.5,
+        }
+    }
+}
+
+/// Tolerances within which two listings of the same make, model and year are treated
+/// as the same car.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DedupConfig {
+    /// Allowed mileage difference in km; the larger of this and `mileage_ratio` applies.
+    pub mileage_tolerance: i32,
+    pub mileage_ratio: f64,
+    pub power_tolerance: i32,
+    /// Allowed price difference relative to the lower price.
+    pub price_ratio: f64,
+    /// Minimum token overlap (Jaccard) of the titles when both listings have one.
+    pub title_similarity: f64,
+    /// Also match listings of the same source, e.g. a car relisted on one site.
+    #[serde(default)]
+    pub same_source: bool,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        DedupConfig {
+            mileage_tolerance: 2_000,
+            mileage_ratio: 0.02,
+            power_tolerance: 5,
+            price_ratio: 0.05,
+            title_similarity: 0.5,
+            same_source: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OutlierMethod {
+    /// Outside `q1 - iqr_factor * IQR` .. `q3 + iqr_factor * IQR`.
+    #[default]
+    Iqr,
+    /// Modified z-score `0.6745 * (x - median) / MAD` above `mad_threshold`.
+    Mad,
+    /// Z-score above `z_threshold`.
+    Zscore,
+}
+
+impl OutlierMethod {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutlierMethod::Iqr => "iqr",
+            OutlierMethod::Mad => "mad",
+            OutlierMethod::Zscore => "zscore",
+        }
+    }
+}
+
+/// Outliers are detected per make, model and year; smaller segments are only checked
+/// against the fixed limits.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OutlierConfig {
+    pub method: OutlierMethod,
+    pub columns: Vec<String>,
+    pub min_segment_size: usize,
+    pub iqr_factor: f64,
+    pub mad_threshold: f64,
+    pub z_threshold: f64,
+    /// Listings priced below this are always suspicious.
+    pub min_price: i32,
+    /// Listings with a higher mileage are always suspicious.
+    pub max_mileage: i32,
+}
+
+impl Default for OutlierConfig {
+    fn default() -> Self {
+        OutlierConfig {
+            method: OutlierMethod::Iqr,
+            columns: vec!["price_in_eur".to_string(), "mileage".to_string()],
+            min_segment_size: 8,
+            iqr_factor: 1.5,
+            mad_threshold: 3.5,
+            z_threshold: 3.0,
+            min_price: 500,
+            max_mileage: 800_000,
+        }
+    }
+}
+
+/// Liquidity index of a make, model and year: a weighted mean of the percentile rank
+/// (0-100) of every component among all segments, where fast-selling is high.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LiquidityConfig {
+    /// Segments with fewer listings are not ranked.
+    pub min_segment_size: usize,
+    /// Days before the latest listing that count as recent for demand and supply.
+    pub window_days: i32,
+    pub sell_through_weight: f64,
+    pub days_on_market_weight: f64,
+    pub demand_supply_weight: f64,
+    pub price_dispersion_weight: f64,
+    /// Segments with an index of at least `hot` are "hot", below `cold` "cold".
+    pub hot: f64,
+    pub cold: f64,
+}
+
+impl Default for LiquidityConfig {
+    fn default() -> Self {
+        LiquidityConfig {
+            min_segment_size: 5,
+            window_days: 28,
+            sell_through_weight: 0.35,
+            days_on_market_weight: 0.3,
+            demand_supply_weight: 0.2,
+            price_dispersion_weight: 0.15,
+            hot: 67.0,
+            cold: 33.0,
+        }
+    }
+}
+
+/// Correction of the calculator by the gap between the last asking price of sold
+/// listings and their estimate.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PriceGapConfig {
+    /// Sold listings with an estimate required before a segment gets a correction.
+    pub min_sold: usize,
+    /// Largest correction, as a share of the estimate.
+    pub max_correction: f64,
+}
+
+impl Default for PriceGapConfig {
+    fn default() -> Self {
+        PriceGapConfig {
+            min_sold: 5,
+            max_correction: 0.15,
+        }
+    }
+}
+
+/// Factor the calculator's estimate was multiplied with for `"soldCorrection": true`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PriceCorrection {
+    /// Columns and values of the segment the factor was derived from.
+    pub segment: String,
+    pub sold: usize,
+    /// Median of `asking price / estimate - 1` of the sold listings, in percent.
+    pub median_gap: f64,
+    pub factor: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EstimatorConfig {
+    pub default: String,
+    pub weighted_quantile: WeightedQuantileConfig,
+    pub median: MedianConfig,
+    pub knn: KnnConfig,
+    pub regression: RegressionConfig,
+    #[serde(default)]
+    pub relaxation: RelaxationConfig,
+    #[serde(default)]
+    pub deal_score: DealScoreConfig,
+    #[serde(default)]
+    pub dedup: DedupConfig,
+    #[serde(default)]
+    pub outliers: OutlierConfig,
+    #[serde(default)]
+    pub liquidity: LiquidityConfig,
+    #[serde(default)]
+    pub price_gap: PriceGapConfig,
+}
+
+fn weight(statistic: &str, kind: WeightKind, value: f64) -> StatisticWeight {
+    StatisticWeight {
+        statistic: statistic.to_string(),
+        kind,
+        value,
+    }
+}
+
+impl Default for EstimatorConfig {
+    fn default() -> Self {
+        EstimatorConfig {
+            default: "weighted_quantile".to_string(),
+            weighted_quantile: WeightedQuantileConfig {
+                bands: vec![
+                    WeightBand {
+                        max_rsd: Some(0.1),
+                        normalize: false,
+                        weights: vec![
+                            weight("mean", WeightKind::Scaled, 0.4),
+                            weight("median", WeightKind::Scaled, 0.4),
+                            weight("quantile_66", WeightKind::Scaled, 0.12),
+                            weight("quantile_80", WeightKind::Scaled, 0.1),
+                            weight("max", WeightKind::Rsd, 0.0),
+                        ],
+                    },
+                    WeightBand {
+                        max_rsd: Some(0.3),
+                        normalize: true,
+                        weights: vec![
+                            weight("mean", WeightKind::Scaled, 0.2),
+                            weight("median", WeightKind::Scaled, 0.2),
+                            weight("quantile_66", WeightKind::Scaled, 0.3),
+                            weight("quantile_75", WeightKind::Fixed, 0.15),
+                            weight("quantile_80", WeightKind::Scaled, 0.3),
+                        ],
+                    },
+                    WeightBand {
+                        max_rsd: None,
+                        normalize: false,
+                        weights: vec![
+                            weight("mean", WeightKind::Fixed, 0.2),
+                            weight("median", WeightKind::Fixed, 0.2),
+                            weight("quantile_66", WeightKind::Fixed, 0.25),
+                            weight("quantile_75", WeightKind::Fixed, 0.15),
+                            weight("quantile_80", WeightKind::Fixed, 0.10),
+                            weight("max", WeightKind::Fixed, 0.10),
+                        ],
+                    },
+                ],
+            },
+            median: MedianConfig {
+                lower_quantile: 0.25,
+                upper_quantile: 0.75,
+            },
+            knn: KnnConfig {
+                k: 10,
+                year_scale: 1.0,
+                mileage_scale: 20_000.0,
+                power_scale: 30.0,
+            },
+            regression: RegressionConfig {
+                min_observations: 10,
+            },
+            relaxation: RelaxationConfig::default(),
+            deal_score: DealScoreConfig::default(),
+            dedup: DedupConfig::default(),
+            outliers: OutlierConfig::default(),
+            liquidity: LiquidityConfig::default(),
+            price_gap: PriceGapConfig::default(),
+        }
+    }
+}
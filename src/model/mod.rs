@@ -2,8 +2,16 @@ use serde::{Deserialize, Serialize, Serializer};
 use Quantiles::Quantile;
 
 pub mod AxumAPIModel;
+pub mod Backtest;
+pub mod Buckets;
+pub mod Chart;
+pub mod Depreciation;
+pub mod History;
 pub mod Intervals;
 pub mod Quantiles;
+pub mod Survival;
+pub mod TimeSeries;
+pub mod Valuation;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum DistributionType {
@@ -12,7 +12,10 @@ use serde_json::Value;
 
 use crate::{
     model::AxumAPIModel::{PivotData, StatisticSearchPayload},
-    services::Utils::{to_aggregator, to_predicate},
+    services::{
+        LiquidityService::with_requested_liquidity,
+        Utils::{filter_listings, to_aggregator},
+    },
     PRICE_DATA,
 };
 
@@ -69,11 +72,6 @@ pub fn pivot_distribution(payload: PivotData) -> Result<HashMap<String, Value>,
 }
 
 pub fn filterAndAggregateData(search: &StatisticSearchPayload) -> Result<LazyFrame, PolarsError> {
-    use polars::prelude::*;
-
-    // Clone the PRICE_DATA LazyFrame
-    let df: LazyFrame = PRICE_DATA.clone();
-
     // Log the incoming search payload
     info!("Payload: {:?}", search);
 
@@ -101,8 +99,15 @@ pub fn filterAndAggregateData(search: &StatisticSearchPayload) -> Result<LazyFra
     // Convert aggregators into their corresponding Polars aggregation expressions
     let aggregators = to_aggregator(aggregators.clone(), &stat_column);
 
-    // Build filter conditions from the search payload
-    let filter_conditions = to_predicate(search.clone());
+    // Return the LazyFrame with transformations applied
+    Ok(filterData(search).group_by(by.as_slice()).agg(&aggregators))
+}
+
+/// The listings of `PRICE_DATA` matching `search`, with the columns that can be grouped
+/// by and aggregated.
+pub fn filterData(search: &StatisticSearchPayload) -> LazyFrame {
+    // Clone the PRICE_DATA LazyFrame
+    let df: LazyFrame = PRICE_DATA.clone();
 
     // Select relevant columns
     let selected_columns = vec![
@@ -127,12 +132,8 @@ pub fn filterAndAggregateData(search: &StatisticSearchPayload) -> Result<LazyFra
     .map(col)
     .collect::<Vec<_>>();
 
-    // Return the LazyFrame with transformations applied
-    Ok(df
-        .with_columns(&selected_columns)
-        .filter(filter_conditions)
-        .group_by(by.as_slice())
-        .agg(&aggregators))
+    let df = with_requested_liquidity(df.with_columns(&selected_columns), search);
+    filter_listings(df, search)
 }
 
 pub fn process_results(
@@ -0,0 +1,368 @@
+use std::{collections::BTreeMap, fs::File, path::Path};
+
+use log::info;
+use polars::{
+    frame::DataFrame,
+    prelude::{col, lit, IntoLazy},
+};
+
+use crate::{
+    model::{
+        AxumAPIModel::{BacktestRequest, StatisticSearchPayload},
+        Backtest::{BacktestObservation, BacktestReport, ErrorMetrics, SegmentMetrics},
+    },
+    ESTIMATED_PRICES_DATA, VEHICLE_STATIC_DATA,
+};
+
+use super::{
+    EstimatorService::{estimator, quantile, ESTIMATOR_CONFIG},
+    PriceCalculatorService::comparables,
+    Utils::to_predicate,
+};
+
+const ROW_INDEX: &str = "backtest_row";
+
+const PRICE_BANDS: [(f64, &str); 6] = [
+    (5_000.0, "<5K"),
+    (10_000.0, "5K-10K"),
+    (20_000.0, "10K-20K"),
+    (30_000.0, "20K-30K"),
+    (50_000.0, "30K-50K"),
+    (f64::MAX, "50K+"),
+];
+
+/// Holds out listings, estimates each of them with the requested strategy and
+/// reports the estimation error.
+///
+/// With the `estimated_prices` source every held-out listing is removed from the
+/// comparables; with `sold` the sold vehicles of `VEHICLE_STATIC_DATA` are
+/// estimated against `ESTIMATED_PRICES_DATA`.
+pub fn backtest(request: &BacktestRequest) -> Result<BacktestReport, String> {
+    let predicate = to_predicate(request.filter.clone());
+    let training = ESTIMATED_PRICES_DATA
+        .clone()
+        .collect()
+        .map_err(|e| e.to_string())?;
+    match request.source.as_deref().unwrap_or("estimated_prices") {
+        "estimated_prices" => {
+            let training = with_row_index(&training)?;
+            let holdout = training
+                .clone()
+                .lazy()
+                .filter(predicate)
+                .collect()
+                .map_err(|e| e.to_string())?;
+            run_backtest(&holdout, &training, request)
+        }
+        "sold" => {
+            let holdout = VEHICLE_STATIC_DATA
+                .clone()
+                .filter(col("sold_date").is_not_null().and(predicate))
+                .collect()
+                .map_err(|e| e.to_string())?;
+            run_backtest(&holdout, &training, request)
+        }
+        other => Err(format!("Unknown backtest source: {}", other)),
+    }
+}
+
+/// Numbers the listings so that a held-out row can be excluded from its comparables.
+pub fn with_row_index(df: &DataFrame) -> Result<DataFrame, String> {
+    df.clone()
+        .with_row_index(ROW_INDEX.into(), None)
+        .map_err(|e| e.to_string())
+}
+
+/// Runs the backtest over `holdout`. When both frames carry the row index added by
+/// [`with_row_index`], each held-out listing is excluded from its own comparables.
+pub fn run_backtest(
+    holdout: &DataFrame,
+    training: &DataFrame,
+    request: &BacktestRequest,
+) -> Result<BacktestReport, String> {
+    let estimator = estimator(request.estimator.as_deref(), &ESTIMATOR_CONFIG)?;
+    let rows = sample_rows(holdout.height(), request.sample_size, request.offset);
+    info!(
+        "Backtesting {} of {} listings with {}",
+        rows.len(),
+        holdout.height(),
+        estimator.name()
+    );
+    let training = training.clone().lazy();
+    let mut observations = vec![];
+    let mut failed = 0;
+    for row in rows {
+        let Some((spec, actual, index)) = spec_at(holdout, row, request) else {
+            failed += 1;
+            continue;
+        };
+        let vehicles = match index {
+            Some(index) => training.clone().filter(col(ROW_INDEX).neq(lit(index))),
+            None => training.clone(),
+        };
+        let estimate = comparables(&spec, &vehicles)
+            .and_then(|comparables| estimator.estimate(&spec, &comparables.data));
+        match estimate {
+            Ok(estimate) => observations.push(BacktestObservation {
+                make: spec.make.clone().unwrap_or_default(),
+                model: spec.model.clone().unwrap_or_default(),
+                year: spec.year.unwrap_or_default(),
+                actual,
+                estimation: estimate.estimation,
+                lower: estimate.lower,
+                upper: estimate.upper,
+                comparables: estimate.count,
+            }),
+            Err(_) => failed += 1,
+        }
+    }
+
+    Ok(BacktestReport {
+        strategy: estimator.name().to_string(),
+        source: request
+            .source
+            .clone()
+            .unwrap_or("estimated_prices".to_string()),
+        sampled: observations.len() + failed,
+        failed,
+        overall: error_metrics(&observations.iter().collect::<Vec<_>>()),
+        segments: segment_metrics(&observations),
+        observations,
+    })
+}
+
+/// Evenly spaced row indices so that repeated runs use the same listings.
+fn sample_rows(height: usize, sample_size: usize, offset: usize) -> Vec<usize> {
+    if height == 0 {
+        return vec![];
+    }
+    if sample_size == 0 || sample_size >= height {
+        return (0..height).collect();
+    }
+    let step = height / sample_size;
+    (0..sample_size)
+        .map(|i| (offset % step + i * step) % height)
+        .collect()
+}
+
+fn spec_at(
+    df: &DataFrame,
+    row: usize,
+    request: &BacktestRequest,
+) -> Option<(StatisticSearchPayload, f64, Option<u32>)> {
+    let text = |name: &str| -> Option<String> {
+        df.column(name)
+            .ok()?
+            .str()
+            .ok()?
+            .get(row)
+            .map(|v| v.to_string())
+    };
+    let number =
+        |name: &str| -> Option<f64> { df.column(name).ok()?.get(row).ok()?.extract::<f64>() };
+    let actual = number("price_in_eur").filter(|p| *p > 0.0)?;
+    let index = df
+        .column(ROW_INDEX)
+        .ok()
+        .and_then(|c| c.u32().ok().and_then(|c| c.get(row)));
+    let spec = StatisticSearchPayload {
+        make: Some(text("make")?),
+        model: Some(text("model")?),
+        year: Some(number("year")? as i32),
+        engine: text("engine").map(|e| vec![e]),
+        gearbox: text("gearbox"),
+        power: number("power").map(|v| v as i32),
+        mileage: number("mileage").map(|v| v as i32),
+        estimator: request.estimator.clone(),
+        ..Default::default()
+    };
+    Some((spec, actual, index))
+}
+
+pub fn error_metrics(observations: &[&BacktestObservation]) -> ErrorMetrics {
+    if observations.is_empty() {
+        return ErrorMetrics::default();
+    }
+    let count = observations.len() as f64;
+    let mut ape = observations
+        .iter()
+        .map(|o| o.absolute_percentage_error())
+        .collect::<Vec<f64>>();
+    ape.sort_by(|a, b| a.total_cmp(b));
+    ErrorMetrics {
+        count: observations.len(),
+        mae: observations.iter().map(|o| o.absolute_error()).sum::<f64>() / count,
+        mape: ape.iter().sum::<f64>() / count,
+        median_ape: quantile(&ape, 0.5),
+        coverage: observations.iter().filter(|o| o.in_range()).count() as f64 * 100.0 / count,
+    }
+}
+
+fn year_band(year: i32) -> String {
+    let start = year / 5 * 5;
+    format!("{}-{}", start, start + 4)
+}
+
+fn price_band(price: f64) -> String {
+    PRICE_BANDS
+        .iter()
+        .find(|(limit, _)| price < *limit)
+        .map(|(_, label)| label.to_string())
+        .unwrap_or_default()
+}
+
+type SegmentKey = fn(&BacktestObservation) -> String;
+
+fn segment_metrics(observations: &[BacktestObservation]) -> Vec<SegmentMetrics> {
+    let dimensions: [(&str, SegmentKey); 3] = [
+        ("make", |o| o.make.clone()),
+        ("year_band", |o| year_band(o.year)),
+        ("price_band", |o| price_band(o.actual)),
+    ];
+    let mut segments = vec![];
+    for (dimension, key) in dimensions {
+        let mut groups: BTreeMap<String, Vec<&BacktestObservation>> = BTreeMap::new();
+        for o in observations {
+            groups.entry(key(o)).or_default().push(o);
+        }
+        for (segment, group) in groups {
+            segments.push(SegmentMetrics {
+                dimension: dimension.to_string(),
+                segment,
+                metrics: error_metrics(&group),
+            });
+        }
+    }
+    segments
+}
+
+/// Writes the report as JSON or, for a `.csv` path, the overall and segment metrics as CSV.
+pub fn write_report(report: &BacktestReport, path: &Path) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    if path.extension().is_some_and(|ext| ext == "csv") {
+        let mut writer = csv::WriterBuilder::new().delimiter(b';').from_writer(file);
+        writer
+            .write_record([
+                "strategy",
+                "dimension",
+                "segment",
+                "count",
+                "mae",
+                "mape",
+                "median_ape",
+                "coverage",
+            ])
+            .map_err(|e| e.to_string())?;
+        let overall = SegmentMetrics {
+            dimension: "all".to_string(),
+            segment: "all".to_string(),
+            metrics: report.overall.clone(),
+        };
+        for s in std::iter::once(&overall).chain(report.segments.iter()) {
+            writer
+                .write_record([
+                    report.strategy.clone(),
+                    s.dimension.clone(),
+                    s.segment.clone(),
+                    s.metrics.count.to_string(),
+                    format!("{:.2}", s.metrics.mae),
+                    format!("{:.2}", s.metrics.mape),
+                    format!("{:.2}", s.metrics.median_ape),
+                    format!("{:.2}", s.metrics.coverage),
+                ])
+                .map_err(|e| e.to_string())?;
+        }
+        writer.flush().map_err(|e| e.to_string())
+    } else {
+        serde_json::to_writer_pretty(file, report).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::df;
+
+    use super::*;
+
+    fn listings() -> DataFrame {
+        let years = [
+            2017, 2018, 2018, 2019, 2019, 2020, 2020, 2021, 2018, 2019, 2020, 2017,
+        ];
+        let mileages = [
+            110_000, 95_000, 85_000, 70_000, 65_000, 45_000, 55_000, 30_000, 90_000, 75_000,
+            50_000, 105_000,
+        ];
+        let prices = [
+            18_000, 20_000, 21_000, 23_000, 24_000, 27_000, 26_000, 30_000, 20_500, 23_500, 26_500,
+            18_500,
+        ];
+        df!(
+            "make" => vec!["BMW"; 12],
+            "model" => vec!["320"; 12],
+            "engine" => vec!["Diesel"; 12],
+            "gearbox" => vec!["Automatic"; 12],
+            "year" => years,
+            "mileage" => mileages,
+            "power" => vec![190; 12],
+            "cc" => vec![2000; 12],
+            "price_in_eur" => prices
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_sample_rows() {
+        assert_eq!(sample_rows(10, 0, 0), (0..10).collect::<Vec<_>>());
+        assert_eq!(sample_rows(10, 5, 1), vec![1, 3, 5, 7, 9]);
+        assert!(sample_rows(0, 5, 0).is_empty());
+    }
+
+    #[test]
+    fn test_error_metrics() {
+        let observations = [
+            BacktestObservation {
+                actual: 100.0,
+                estimation: 110.0,
+                lower: 90.0,
+                upper: 120.0,
+                ..Default::default()
+            },
+            BacktestObservation {
+                actual: 200.0,
+                estimation: 180.0,
+                lower: 150.0,
+                upper: 190.0,
+                ..Default::default()
+            },
+        ];
+        let metrics = error_metrics(&observations.iter().collect::<Vec<_>>());
+        assert_eq!(metrics.count, 2);
+        assert_eq!(metrics.mae, 15.0);
+        assert_eq!(metrics.mape, 10.0);
+        assert_eq!(metrics.coverage, 50.0);
+    }
+
+    #[test]
+    fn test_run_backtest_leave_one_out() {
+        let data = with_row_index(&listings()).unwrap();
+        let request = BacktestRequest {
+            estimator: Some("median".to_string()),
+            ..Default::default()
+        };
+        let report = run_backtest(&data, &data, &request).unwrap();
+        assert_eq!(report.strategy, "median");
+        assert_eq!(report.sampled, 12);
+        assert!(report.overall.count > 0);
+        for o in report.observations.iter() {
+            assert!(o.comparables < 12);
+        }
+        assert!(report
+            .segments
+            .iter()
+            .any(|s| s.dimension == "year_band" && s.segment == "2015-2019"));
+        assert!(report
+            .segments
+            .iter()
+            .any(|s| s.dimension == "price_band" && s.segment == "20K-30K"));
+    }
+}
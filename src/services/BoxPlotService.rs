@@ -0,0 +1,241 @@
+use std::f64::consts::PI;
+
+use polars::prelude::{col, DataType, LazyFrame, SortOptions};
+
+use crate::model::{
+    AxumAPIModel::BoxPlotRequest,
+    Chart::{BoxPlot, Density},
+};
+
+use super::{
+    AnalysisService::filterData,
+    ChartMatrix::{natural_cmp, strings},
+    TopNService::with_top_n,
+};
+
+const VALUES: &str = "box_plot_values";
+/// Tukey fences at 1.5 interquartile ranges below the first and above the third quartile.
+const WHISKER: f64 = 1.5;
+pub const DEFAULT_MAX_OUTLIERS: usize = 50;
+pub const DEFAULT_DENSITY_POINTS: usize = 50;
+
+/// Quantile `q` of `sorted`, linearly interpolated between the closest ranks like
+/// polars' `QuantileMethod::Linear`.
+pub fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let position = q * (sorted.len() - 1) as f64;
+    let (lower, upper) = (position.floor() as usize, position.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+/// Gaussian kernel density of `sorted` at `points` points from its smallest to its
+/// largest value; `None` for fewer than two distinct values.
+pub fn density(sorted: &[f64], iqr: f64, points: usize) -> Option<Density> {
+    let n = sorted.len() as f64;
+    if sorted.len() < 2 || points < 2 {
+        return None;
+    }
+    let mean = sorted.iter().sum::<f64>() / n;
+    let sd = (sorted.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt();
+    let spread = if iqr > 0.0 { sd.min(iqr / 1.34) } else { sd };
+    let bandwidth = 0.9 * spread * n.powf(-0.2);
+    if bandwidth <= 0.0 || !bandwidth.is_finite() {
+        return None;
+    }
+    let (min, max) = (sorted[0], sorted[sorted.len() - 1]);
+    let step = (max - min) / (points - 1) as f64;
+    let x = (0..points)
+        .map(|i| min + step * i as f64)
+        .collect::<Vec<_>>();
+    let norm = n * bandwidth * (2.0 * PI).sqrt();
+    let y = x
+        .iter()
+        .map(|x| {
+            sorted
+                .iter()
+                .map(|v| (-0.5 * ((x - v) / bandwidth).powi(2)).exp())
+                .sum::<f64>()
+                / norm
+        })
+        .collect();
+    Some(Density { x, y, bandwidth })
+}
+
+/// Box plot of the `sorted` values of one group with at most `max_outliers` outliers,
+/// the furthest beyond the fences, and with `density_points` the density for a violin.
+pub fn box_plot(
+    group: Vec<String>,
+    sorted: &[f64],
+    max_outliers: usize,
+    density_points: Option<usize>,
+) -> Option<BoxPlot> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let (q1, median, q3) = (
+        quantile(sorted, 0.25),
+        quantile(sorted, 0.5),
+        quantile(sorted, 0.75),
+    );
+    let iqr = q3 - q1;
+    let (lower_fence, upper_fence) = (q1 - WHISKER * iqr, q3 + WHISKER * iqr);
+    let inside = sorted
+        .iter()
+        .filter(|v| (lower_fence..=upper_fence).contains(*v))
+        .collect::<Vec<_>>();
+    let mut outliers = sorted
+        .iter()
+        .copied()
+        .filter(|v| !(lower_fence..=upper_fence).contains(v))
+        .collect::<Vec<_>>();
+    let outlier_count = outliers.len();
+    let beyond = |v: &f64| (lower_fence - v).max(v - upper_fence);
+    outliers.sort_by(|a, b| beyond(b).total_cmp(&beyond(a)));
+    outliers.truncate(max_outliers);
+    outliers.sort_by(f64::total_cmp);
+    Some(BoxPlot {
+        label: group.join(", "),
+        group,
+        count: sorted.len(),
+        mean: sorted.iter().sum::<f64>() / sorted.len() as f64,
+        min: sorted[0],
+        q1,
+        median,
+        q3,
+        max: sorted[sorted.len() - 1],
+        lower_whisker: **inside.first()?,
+        upper_whisker: **inside.last()?,
+        outliers,
+        outlier_count,
+        density: density_points.and_then(|points| density(sorted, iqr, points)),
+    })
+}
+
+/// Box plots of `stat_column` of the listings in `df` per value of the `group` columns,
+/// in natural order of their labels.
+pub fn box_plots(
+    df: LazyFrame,
+    group: &[String],
+    stat_column: &str,
+    max_outliers: usize,
+    density_points: Option<usize>,
+) -> Result<Vec<BoxPlot>, String> {
+    let data = df
+        .filter(col(stat_column).is_not_null())
+        .group_by(group.iter().map(col).collect::<Vec<_>>())
+        .agg([col(stat_column)
+            .cast(DataType::Float64)
+            .sort(SortOptions::default())
+            .alias(VALUES)])
+        .collect()
+        .map_err(|e| e.to_string())?;
+    let labels = group
+        .iter()
+        .map(|c| strings(&data, c))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let values = data.column(VALUES).map_err(|e| e.to_string())?;
+    let values = values.list().map_err(|e| e.to_string())?;
+    let mut plots = vec![];
+    for (row, series) in values.into_iter().enumerate() {
+        let Some(series) = series else { continue };
+        let sorted = series
+            .f64()
+            .map_err(|e| e.to_string())?
+            .into_no_null_iter()
+            .collect::<Vec<_>>();
+        let group = labels.iter().map(|l| l[row].clone()).collect();
+        plots.extend(box_plot(group, &sorted, max_outliers, density_points));
+    }
+    plots.sort_by(|a, b| natural_cmp(&a.label, &b.label));
+    Ok(plots)
+}
+
+/// Box plots of the listings matching `request.filter` per its `group` columns.
+pub fn group_box_plots(request: BoxPlotRequest) -> Result<Vec<BoxPlot>, String> {
+    let search = &request.filter;
+    let group = match &search.group {
+        Some(group) if !group.is_empty() => group.clone(),
+        _ => return Err("Group is required".to_string()),
+    };
+    let stat_column = search
+        .stat_column
+        .clone()
+        .unwrap_or("price_in_eur".to_string());
+    let df = with_top_n(filterData(search), search, &group, &stat_column)?;
+    let density_points = request
+        .density
+        .then(|| request.density_points.unwrap_or(DEFAULT_DENSITY_POINTS));
+    box_plots(
+        df,
+        &group,
+        &stat_column,
+        request.max_outliers.unwrap_or(DEFAULT_MAX_OUTLIERS),
+        density_points,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::{df, prelude::IntoLazy};
+
+    use super::*;
+
+    fn listings() -> LazyFrame {
+        let mut engines = vec!["Diesel"; 10];
+        engines.extend(["Petrol"; 7]);
+        let mut prices = (1..=9).collect::<Vec<i32>>();
+        prices.extend([100, 0, 50, 51, 52, 53, 54, 200]);
+        df!(
+            "engine" => engines,
+            "price_in_eur" => prices
+        )
+        .unwrap()
+        .lazy()
+    }
+
+    #[test]
+    fn test_box_plots() {
+        let group = ["engine".to_string()];
+        let plots = box_plots(listings(), &group, "price_in_eur", 1, None).unwrap();
+        assert_eq!(plots.len(), 2);
+
+        let diesel = &plots[0];
+        assert_eq!(diesel.label, "Diesel");
+        assert_eq!(diesel.count, 10);
+        assert_eq!(diesel.mean, 14.5);
+        assert_eq!((diesel.q1, diesel.median, diesel.q3), (3.25, 5.5, 7.75));
+        assert_eq!((diesel.lower_whisker, diesel.upper_whisker), (1.0, 9.0));
+        assert_eq!((diesel.min, diesel.max), (1.0, 100.0));
+        assert_eq!(diesel.outliers, vec![100.0]);
+
+        // Two outliers, only the one furthest beyond its fence is kept.
+        let petrol = &plots[1];
+        assert_eq!((petrol.lower_whisker, petrol.upper_whisker), (50.0, 54.0));
+        assert_eq!(petrol.outlier_count, 2);
+        assert_eq!(petrol.outliers, vec![200.0]);
+        assert_eq!(petrol.density, None);
+    }
+
+    #[test]
+    fn test_density() {
+        let sorted = [1.0, 2.0, 2.0, 3.0, 3.0, 3.0, 4.0, 4.0, 5.0];
+        let plot = box_plot(vec!["all".to_string()], &sorted, 10, Some(5)).unwrap();
+        let density = plot.density.unwrap();
+        assert_eq!(density.x, vec![1.0, 2.0, 3.0, 4.0, 5.0]);
+        let peak = density
+            .y
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap()
+            .0;
+        assert_eq!(peak, 2);
+        assert!((density.y[1] - density.y[3]).abs() < 1e-12);
+
+        assert_eq!(density_of_constant(), None);
+    }
+
+    fn density_of_constant() -> Option<Density> {
+        density(&[7.0, 7.0, 7.0], 0.0, 10)
+    }
+}
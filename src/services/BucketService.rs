@@ -0,0 +1,222 @@
+use std::{collections::BTreeMap, fs};
+
+use lazy_static::lazy_static;
+use log::{error, info};
+use polars::prelude::{col, lit, when, DataType, Expr, LazyFrame, NULL};
+
+use crate::{
+    model::Buckets::{BucketConfig, BucketDefinition},
+    BUCKETS_CONFIG_FILE,
+};
+
+lazy_static! {
+    pub static ref BUCKETS: BucketConfig = load_bucket_config(BUCKETS_CONFIG_FILE);
+}
+
+pub fn load_bucket_config(file: &str) -> BucketConfig {
+    let config = match fs::read_to_string(file) {
+        Ok(content) => serde_yaml::from_str::<BucketConfig>(&content).map_err(|e| e.to_string()),
+        Err(_) => {
+            info!("Bucket config {} not found. Using defaults", file);
+            return BucketConfig::default();
+        }
+    };
+    match config.and_then(|config| validate(&config).map(|_| config)) {
+        Ok(config) => {
+            info!("Bucket config {} version {}", file, config.version);
+            config
+        }
+        Err(e) => {
+            error!("Invalid bucket config {}: {}. Using defaults", file, e);
+            BucketConfig::default()
+        }
+    }
+}
+
+/// Buckets must be ascending and must not overlap.
+pub fn validate(config: &BucketConfig) -> Result<(), String> {
+    for (name, definition) in config.definitions.iter() {
+        if definition.buckets.is_empty() {
+            return Err(format!("{} has no buckets", name));
+        }
+        for b in definition.buckets.iter() {
+            if b.from > b.to {
+                return Err(format!("{}: {} starts after it ends", name, b.label));
+            }
+        }
+        for pair in definition.buckets.windows(2) {
+            if pair[1].from <= pair[0].to {
+                return Err(format!(
+                    "{}: {} overlaps {}",
+                    name, pair[0].label, pair[1].label
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn definition(name: &str) -> Option<&'static BucketDefinition> {
+    BUCKETS.definitions.get(name)
+}
+
+/// `(from, to)` pairs of the named buckets; empty when the name is not configured.
+pub fn ranges(name: &str) -> Vec<(i32, i32)> {
+    definition(name).map(|d| d.ranges()).unwrap_or_default()
+}
+
+fn thousands(value: i32) -> String {
+    let digits = value.to_string();
+    let mut formatted = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(c);
+    }
+    formatted
+}
+
+/// The configured options of a definition, or the upper boundaries of all but its
+/// open-ended last bucket, for "up to" dropdowns.
+pub fn threshold_options(definition: &BucketDefinition) -> BTreeMap<String, String> {
+    let thresholds = if definition.options.is_empty() {
+        let bounded = definition.buckets.len().saturating_sub(1);
+        definition
+            .buckets
+            .iter()
+            .take(bounded)
+            .map(|b| b.to)
+            .collect()
+    } else {
+        definition.options.clone()
+    };
+    thresholds
+        .into_iter()
+        .map(|t| {
+            (
+                t.to_string(),
+                format!("{} {}", thousands(t), definition.unit),
+            )
+        })
+        .collect()
+}
+
+/// Bucket labels keyed by their order, for "in range" dropdowns.
+pub fn bucket_options(definition: &BucketDefinition) -> BTreeMap<String, String> {
+    definition
+        .buckets
+        .iter()
+        .map(|b| (b.order.to_string(), b.label.clone()))
+        .collect()
+}
+
+/// `<name>_breakdown` and `<name>_breakdown_order` computed from the bucket boundaries.
+pub fn breakdown_columns(name: &str, definition: &BucketDefinition) -> Vec<Expr> {
+    let value = col(&definition.column);
+    let mut labels = lit(NULL).cast(DataType::String);
+    let mut orders = lit(NULL).cast(DataType::Int32);
+    for b in definition.buckets.iter().rev() {
+        let condition = value
+            .clone()
+            .gt_eq(lit(b.from))
+            .and(value.clone().lt_eq(lit(b.to)));
+        labels = when(condition.clone())
+            .then(lit(b.label.clone()))
+            .otherwise(labels);
+        orders = when(condition).then(lit(b.order)).otherwise(orders);
+    }
+    vec![
+        labels.alias(format!("{}_breakdown", name)),
+        orders
+            .cast(DataType::Int32)
+            .alias(format!("{}_breakdown_order", name)),
+    ]
+}
+
+/// Adds (or replaces) the breakdown columns of every configured bucket whose
+/// source column is present in `df`.
+pub fn with_breakdowns(df: LazyFrame, config: &BucketConfig, columns: &[&str]) -> LazyFrame {
+    let expressions = config
+        .definitions
+        .iter()
+        .filter(|(_, d)| columns.contains(&d.column.as_str()))
+        .flat_map(|(name, d)| breakdown_columns(name, d))
+        .collect::<Vec<_>>();
+    if expressions.is_empty() {
+        df
+    } else {
+        df.with_columns(expressions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::{df, prelude::IntoLazy};
+
+    use super::*;
+
+    #[test]
+    fn test_default_config_matches_file() {
+        let config = load_bucket_config("resources/buckets.yml");
+        assert_eq!(config, BucketConfig::default());
+        assert!(validate(&config).is_ok());
+    }
+
+    #[test]
+    fn test_overlapping_buckets_are_rejected() {
+        let mut config = BucketConfig::default();
+        config.definitions.get_mut("power").unwrap().buckets[1].from = 80;
+        assert!(validate(&config).is_err());
+    }
+
+    #[test]
+    fn test_options() {
+        let config = BucketConfig::default();
+        let mileage = &config.definitions["mileage"];
+        let thresholds = threshold_options(mileage);
+        assert_eq!(thresholds.len(), 13);
+        assert_eq!(thresholds["5000"], "5,000 km");
+        assert_eq!(thresholds["150000"], "150,000 km");
+        assert_eq!(
+            threshold_options(&config.definitions["power"])["34"],
+            "34 hp"
+        );
+        let unconfigured = BucketDefinition {
+            options: vec![],
+            ..mileage.clone()
+        };
+        let thresholds = threshold_options(&unconfigured);
+        assert_eq!(thresholds.len(), 7);
+        assert_eq!(thresholds["20000"], "20,000 km");
+        let options = bucket_options(mileage);
+        assert_eq!(options["10"], "Over 150 000 km");
+        assert_eq!(mileage.find(61234).unwrap().to, 80000);
+        assert_eq!(mileage.ranges()[3], (60001, 80000));
+    }
+
+    #[test]
+    fn test_breakdown_columns() {
+        let df = df!(
+            "mileage" => [5_000, 61_234, 200_000],
+            "power" => [75, 190, 500]
+        )
+        .unwrap()
+        .lazy();
+        let result = with_breakdowns(df, &BucketConfig::default(), &["mileage", "power"])
+            .collect()
+            .unwrap();
+        let labels = result.column("mileage_breakdown").unwrap().str().unwrap();
+        assert_eq!(labels.get(0), Some("Less than 20 000 km"));
+        assert_eq!(labels.get(1), Some("Between 60 000 km and 80 000 km"));
+        let orders = result
+            .column("power_breakdown_order")
+            .unwrap()
+            .i32()
+            .unwrap();
+        assert_eq!(
+            orders.into_iter().collect::<Vec<_>>(),
+            vec![Some(1), Some(4), Some(10)]
+        );
+    }
+}
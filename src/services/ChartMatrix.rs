@@ -0,0 +1,509 @@
+use std::{cmp::Ordering, collections::HashMap, iter::Peekable, str::Chars};
+
+use polars::{
+    frame::DataFrame,
+    prelude::{Column, DataType, LazyFrame, PolarsResult},
+};
+use serde_json::{json, Map, Value};
+
+use crate::model::Chart::{GapFill, LabelOrder, Transform};
+
+use super::{
+    ChartTransform::{apply_all, keeps_integers},
+    Utils::generate_colors,
+};
+
+/// One dataset of a [`ChartMatrix`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatrixSeries {
+    pub label: String,
+    /// One value per label of the matrix.
+    pub values: Vec<Option<f64>>,
+    /// Values are written as integers.
+    pub integer: bool,
+    /// Added to the dataset, e.g. the Chart.js `type` and `yAxisID`.
+    pub extra: Map<String, Value>,
+}
+
+/// Dense label × series matrix behind a Chart.js `labels` / `datasets` answer.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ChartMatrix {
+    pub labels: Vec<String>,
+    pub series: Vec<MatrixSeries>,
+    /// Sort key of every label for [`LabelOrder::Column`] and [`LabelOrder::Breakdown`].
+    order_keys: Vec<Option<f64>>,
+}
+
+/// Which columns of aggregated rows make the labels, the series and the values.
+pub struct MatrixSpec<'a> {
+    /// Joined with ", " into the label.
+    pub labels: &'a [String],
+    /// Joined with ", " into the series label; one series when empty.
+    pub series: &'a [String],
+    pub value: &'a str,
+    /// Prefix of the series labels; the value column when there are no series columns.
+    pub name: Option<&'a str>,
+    /// Column whose smallest value per label is the label's sort key.
+    pub order_column: Option<&'a str>,
+}
+
+/// Values of `column` as labels; missing values are `Unknown`.
+pub fn strings(data: &DataFrame, column: &str) -> PolarsResult<Vec<String>> {
+    let values = data.column(column)?.cast(&DataType::String)?;
+    Ok(values
+        .str()?
+        .into_iter()
+        .map(|v| v.unwrap_or("Unknown").to_string())
+        .collect())
+}
+
+pub fn floats(data: &DataFrame, column: &str) -> PolarsResult<Vec<Option<f64>>> {
+    let values = data.column(column)?.cast(&DataType::Float64)?;
+    Ok(values.f64()?.into_iter().collect())
+}
+
+fn joined(columns: &[Vec<String>], row: usize) -> String {
+    columns
+        .iter()
+        .map(|values| values[row].as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The digit run at the start of `chars` without leading zeros.
+fn number(chars: &mut Peekable<Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+        digits.push(*c);
+        chars.next();
+    }
+    digits.trim_start_matches('0').to_string()
+}
+
+/// Compares digit runs by value and everything else character by character.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, y) = (number(&mut a), number(&mut b));
+                let ordering = x.len().cmp(&y.len()).then_with(|| x.cmp(&y));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+/// Breakdown columns follow their buckets, other columns are sorted naturally.
+pub fn default_order(column: &str) -> LabelOrder {
+    if column.ends_with("_breakdown") {
+        LabelOrder::Breakdown
+    } else {
+        LabelOrder::Natural
+    }
+}
+
+/// Column with the sort keys of the labels of `column` in `order`, if it needs one.
+pub fn order_key_column(order: &LabelOrder, column: &str) -> Option<String> {
+    match order {
+        LabelOrder::Column(key) => Some(key.clone()),
+        LabelOrder::Breakdown => Some(format!("{}_order", column)),
+        _ => None,
+    }
+}
+
+pub fn has_column(df: &LazyFrame, column: &str) -> bool {
+    df.clone()
+        .collect_schema()
+        .map(|schema| schema.contains(column))
+        .unwrap_or(false)
+}
+
+/// Positions of `labels` in `order`, given the total of every label for the value
+/// orders and the sort key of every label for [`LabelOrder::Column`] and
+/// [`LabelOrder::Breakdown`].
+pub fn label_positions(
+    labels: &[String],
+    totals: &[f64],
+    keys: &[Option<f64>],
+    order: &LabelOrder,
+) -> Vec<usize> {
+    let natural = |a: &usize, b: &usize| natural_cmp(&labels[*a], &labels[*b]);
+    let mut positions = (0..labels.len()).collect::<Vec<_>>();
+    match order {
+        LabelOrder::Natural => positions.sort_by(natural),
+        LabelOrder::ValueDesc => {
+            positions.sort_by(|a, b| totals[*b].total_cmp(&totals[*a]).then(natural(a, b)))
+        }
+        LabelOrder::ValueAsc => {
+            positions.sort_by(|a, b| totals[*a].total_cmp(&totals[*b]).then(natural(a, b)))
+        }
+        LabelOrder::Custom(custom) => {
+            let rank = |position: &usize| {
+                custom
+                    .iter()
+                    .position(|l| *l == labels[*position])
+                    .unwrap_or(custom.len())
+            };
+            positions.sort_by(|a, b| rank(a).cmp(&rank(b)).then(natural(a, b)))
+        }
+        LabelOrder::Column(_) | LabelOrder::Breakdown => {
+            // Labels without a key go last.
+            let key = |position: &usize| keys[*position].unwrap_or(f64::INFINITY);
+            positions.sort_by(|a, b| key(a).total_cmp(&key(b)).then(natural(a, b)))
+        }
+    }
+    positions
+}
+
+impl ChartMatrix {
+    /// Matrix of aggregated rows; labels keep the order of the rows, series are sorted
+    /// naturally and values of the same label and series are added up.
+    pub fn from_rows(data: &DataFrame, spec: &MatrixSpec) -> PolarsResult<ChartMatrix> {
+        let label_values = spec
+            .labels
+            .iter()
+            .map(|c| strings(data, c))
+            .collect::<PolarsResult<Vec<_>>>()?;
+        let series_values = spec
+            .series
+            .iter()
+            .map(|c| strings(data, c))
+            .collect::<PolarsResult<Vec<_>>>()?;
+        let values = floats(data, spec.value)?;
+        let order_values = match spec.order_column {
+            Some(column) => Some(floats(data, column)?),
+            None => None,
+        };
+        let integer = data.column(spec.value)?.dtype().is_integer();
+
+        let mut matrix = ChartMatrix::default();
+        let mut labels: HashMap<String, usize> = HashMap::new();
+        let mut cells: HashMap<(String, usize), f64> = HashMap::new();
+        let mut series_labels = vec![];
+        for row in 0..data.height() {
+            let label = joined(&label_values, row);
+            let position = *labels.entry(label.clone()).or_insert_with(|| {
+                matrix.labels.push(label);
+                matrix.order_keys.push(None);
+                matrix.labels.len() - 1
+            });
+            if let Some(key) = order_values.as_ref().and_then(|o| o[row]) {
+                let current = &mut matrix.order_keys[position];
+                *current = Some(current.map_or(key, |c| c.min(key)));
+            }
+            let series = match (spec.series.is_empty(), spec.name) {
+                (true, name) => name.unwrap_or(spec.value).to_string(),
+                (false, Some(name)) => format!("{} - {}", name, joined(&series_values, row)),
+                (false, None) => joined(&series_values, row),
+            };
+            if !series_labels.contains(&series) {
+                series_labels.push(series.clone());
+            }
+            if let Some(value) = values[row] {
+                *cells.entry((series, position)).or_insert(0.0) += value;
+            }
+        }
+        series_labels.sort_by(|a, b| natural_cmp(a, b));
+        matrix.series = series_labels
+            .into_iter()
+            .map(|label| MatrixSeries {
+                values: (0..matrix.labels.len())
+                    .map(|position| cells.get(&(label.clone(), position)).copied())
+                    .collect(),
+                label,
+                integer,
+                extra: Map::new(),
+            })
+            .collect();
+        Ok(matrix)
+    }
+
+    /// Adds the series of `other`, aligned to the union of both label lists.
+    pub fn merge(mut self, other: ChartMatrix) -> ChartMatrix {
+        let mut positions = vec![];
+        for (label, key) in other.labels.iter().zip(other.order_keys.iter()) {
+            let position = match self.labels.iter().position(|l| l == label) {
+                Some(position) => position,
+                None => {
+                    self.labels.push(label.clone());
+                    self.order_keys.push(None);
+                    for series in self.series.iter_mut() {
+                        series.values.push(None);
+                    }
+                    self.labels.len() - 1
+                }
+            };
+            if let Some(key) = key {
+                let current = &mut self.order_keys[position];
+                *current = Some(current.map_or(*key, |c| c.min(*key)));
+            }
+            positions.push(position);
+        }
+        for series in other.series {
+            let mut values = vec![None; self.labels.len()];
+            for (value, position) in series.values.into_iter().zip(positions.iter()) {
+                values[*position] = value;
+            }
+            self.series.push(MatrixSeries { values, ..series });
+        }
+        self
+    }
+
+    /// Reorders the labels and the values of every series alike.
+    pub fn order_labels(&mut self, order: &LabelOrder) {
+        let totals = (0..self.labels.len())
+            .map(|position| {
+                self.series
+                    .iter()
+                    .filter_map(|s| s.values[position])
+                    .sum::<f64>()
+            })
+            .collect::<Vec<_>>();
+        let positions = label_positions(&self.labels, &totals, &self.order_keys, order);
+        self.labels = positions.iter().map(|p| self.labels[*p].clone()).collect();
+        self.order_keys = positions.iter().map(|p| self.order_keys[*p]).collect();
+        for series in self.series.iter_mut() {
+            series.values = positions.iter().map(|p| series.values[*p]).collect();
+        }
+    }
+
+    /// Moves the labels and series that are, or are made of, `value` behind the others.
+    pub fn move_last(&mut self, value: &str) {
+        let contains = |label: &str| {
+            label
+                .split(", ")
+                .flat_map(|part| part.split(" - "))
+                .any(|part| part == value)
+        };
+        let mut positions = (0..self.labels.len()).collect::<Vec<_>>();
+        positions.sort_by_key(|p| contains(&self.labels[*p]));
+        self.labels = positions.iter().map(|p| self.labels[*p].clone()).collect();
+        self.order_keys = positions.iter().map(|p| self.order_keys[*p]).collect();
+        for series in self.series.iter_mut() {
+            series.values = positions.iter().map(|p| series.values[*p]).collect();
+        }
+        self.series.sort_by_key(|s| contains(&s.label));
+    }
+
+    /// Recalculates the values of every series with `transforms`, see [`apply_all`].
+    pub fn transform(&mut self, transforms: &[Transform]) -> Result<(), String> {
+        let mut values = self
+            .series
+            .iter()
+            .map(|s| s.values.clone())
+            .collect::<Vec<_>>();
+        apply_all(transforms, &self.labels, &mut values)?;
+        let integer = transforms.iter().all(keeps_integers);
+        for (series, values) in self.series.iter_mut().zip(values) {
+            series.values = values;
+            series.integer &= integer;
+        }
+        Ok(())
+    }
+
+    pub fn fill(&mut self, fill: GapFill) {
+        if fill == GapFill::Zero {
+            for series in self.series.iter_mut() {
+                series.values.iter_mut().for_each(|v| *v = v.or(Some(0.0)));
+            }
+        }
+    }
+
+    /// One row per label and series with a value, in the order of the labels, as the
+    /// columns `label`, `series` and `value`; values are integers when every series is.
+    pub fn to_frame(&self, label: &str, series: &str, value: &str) -> PolarsResult<DataFrame> {
+        let mut labels = vec![];
+        let mut names = vec![];
+        let mut values = vec![];
+        for (position, name) in self.labels.iter().enumerate() {
+            for dataset in self.series.iter() {
+                if let Some(v) = dataset.values[position] {
+                    labels.push(name.as_str());
+                    names.push(dataset.label.as_str());
+                    values.push(v);
+                }
+            }
+        }
+        let mut values = Column::new(value.into(), values);
+        if self.series.iter().all(|s| s.integer) {
+            values = values.cast(&DataType::Int64)?;
+        }
+        DataFrame::new(vec![
+            Column::new(label.into(), labels),
+            Column::new(series.into(), names),
+            values,
+        ])
+    }
+
+    /// Chart.js datasets; every series keeps its colour as long as the series stay the same.
+    pub fn datasets(&self) -> Vec<Value> {
+        let colors = generate_colors(self.series.len());
+        self.series
+            .iter()
+            .zip(colors)
+            .map(|(series, color)| {
+                let data = series
+                    .values
+                    .iter()
+                    .map(|v| match v {
+                        Some(v) if series.integer => json!(v.round() as i64),
+                        Some(v) => json!(v),
+                        None => Value::Null,
+                    })
+                    .collect::<Vec<_>>();
+                let mut dataset = json!({
+                    "label": series.label,
+                    "data": data,
+                    "backgroundColor": color,
+                    "borderColor": color,
+                });
+                for (key, value) in series.extra.iter() {
+                    dataset[key] = value.clone();
+                }
+                dataset
+            })
+            .collect()
+    }
+
+    pub fn to_json(&self) -> HashMap<String, Value> {
+        let mut json_map = HashMap::new();
+        json_map.insert("labels".to_string(), json!(self.labels));
+        json_map.insert("datasets".to_string(), Value::Array(self.datasets()));
+        json_map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::df;
+
+    use super::*;
+
+    fn rows() -> DataFrame {
+        df!(
+            "mileage_breakdown" => ["100k+", "0-50k", "50-100k", "0-50k", "100k+"],
+            "mileage_breakdown_order" => [3, 1, 2, 1, 3],
+            "engine" => ["Diesel", "Petrol", "Diesel", "Diesel", "Petrol"],
+            "median" => [-1.5, 20_000.25, 15_000.0, 21_000.0, 9_000.0]
+        )
+        .unwrap()
+    }
+
+    fn spec<'a>(labels: &'a [String], series: &'a [String]) -> MatrixSpec<'a> {
+        MatrixSpec {
+            labels,
+            series,
+            value: "median",
+            name: None,
+            order_column: Some("mileage_breakdown_order"),
+        }
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut labels = vec!["10", "9", "A10", "A2", "a", "100k+"];
+        labels.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(labels, vec!["9", "10", "100k+", "A2", "A10", "a"]);
+    }
+
+    #[test]
+    fn test_dense_matrix() {
+        let labels = ["mileage_breakdown".to_string()];
+        let series = ["engine".to_string()];
+        let mut matrix = ChartMatrix::from_rows(&rows(), &spec(&labels, &series)).unwrap();
+        assert_eq!(matrix.labels, vec!["100k+", "0-50k", "50-100k"]);
+        matrix.order_labels(&LabelOrder::Breakdown);
+        assert_eq!(matrix.labels, vec!["0-50k", "50-100k", "100k+"]);
+        assert_eq!(matrix.series[0].label, "Diesel");
+        // Decimals and negative values are kept.
+        assert_eq!(
+            matrix.series[0].values,
+            vec![Some(21_000.0), Some(15_000.0), Some(-1.5)]
+        );
+        assert_eq!(
+            matrix.series[1].values,
+            vec![Some(20_000.25), None, Some(9_000.0)]
+        );
+        matrix.fill(GapFill::Zero);
+        assert_eq!(matrix.series[1].values[1], Some(0.0));
+
+        matrix.order_labels(&LabelOrder::Custom(vec!["50-100k".to_string()]));
+        assert_eq!(matrix.labels, vec!["50-100k", "0-50k", "100k+"]);
+        matrix.order_labels(&LabelOrder::ValueDesc);
+        assert_eq!(matrix.labels, vec!["0-50k", "50-100k", "100k+"]);
+        let datasets = matrix.datasets();
+        assert_eq!(datasets[1]["data"], json!([20_000.25, 0.0, 9_000.0]));
+    }
+
+    #[test]
+    fn test_merge_and_integers() {
+        let counts = df!(
+            "year" => [2020, 2019],
+            "count" => [3u32, 2u32]
+        )
+        .unwrap();
+        let medians = df!(
+            "year" => [2021, 2020],
+            "median" => [30_000.5, 25_000.0]
+        )
+        .unwrap();
+        let labels = ["year".to_string()];
+        let matrix = |data: &DataFrame, value: &str| {
+            ChartMatrix::from_rows(
+                data,
+                &MatrixSpec {
+                    labels: &labels,
+                    series: &[],
+                    value,
+                    name: None,
+                    order_column: None,
+                },
+            )
+            .unwrap()
+        };
+        let mut merged = matrix(&counts, "count").merge(matrix(&medians, "median"));
+        merged.order_labels(&LabelOrder::Natural);
+        assert_eq!(merged.labels, vec!["2019", "2020", "2021"]);
+        let datasets = merged.datasets();
+        assert_eq!(datasets[0]["data"], json!([2, 3, null]));
+        assert_eq!(datasets[1]["data"], json!([null, 25_000.0, 30_000.5]));
+    }
+
+    #[test]
+    fn test_move_last() {
+        let labels = ["mileage_breakdown".to_string()];
+        let series = ["engine".to_string()];
+        let data = df!(
+            "mileage_breakdown" => ["Other", "0-50k", "0-50k"],
+            "engine" => ["Diesel", "Other", "Diesel"],
+            "median" => [1.0, 2.0, 3.0]
+        )
+        .unwrap();
+        let mut matrix = ChartMatrix::from_rows(
+            &data,
+            &MatrixSpec {
+                order_column: None,
+                ..spec(&labels, &series)
+            },
+        )
+        .unwrap();
+        matrix.move_last("Other");
+        assert_eq!(matrix.labels, vec!["0-50k", "Other"]);
+        assert_eq!(matrix.series[0].label, "Diesel");
+        assert_eq!(matrix.series[0].values, vec![Some(3.0), Some(1.0)]);
+        assert_eq!(matrix.series[1].values, vec![Some(2.0), None]);
+    }
+}
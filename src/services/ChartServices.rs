@@ -17,13 +17,13 @@ use crate::{
         Quantiles::{generate_quantiles, Quantile},
         Statistics,
     },
-    services::Utils::to_predicate,
+    services::Utils::filter_listings,
     VEHICLE_STATIC_DATA,
 };
 
-use super::Utils::to_aggregator;
+use super::{TopNService::with_top_n, Utils::to_aggregator};
 
-pub fn chartData(search: StatisticSearchPayload) -> StatisticResponse {
+pub fn chartData(search: StatisticSearchPayload) -> Result<StatisticResponse, String> {
     let df = VEHICLE_STATIC_DATA.clone();
     let group = if search.group.is_none() {
         vec![]
@@ -31,29 +31,34 @@ pub fn chartData(search: StatisticSearchPayload) -> StatisticResponse {
         search.group.clone().unwrap()
     };
     if group.is_empty() {
-        return StatisticResponse {
+        return Ok(StatisticResponse {
             metadata: vec![],
             dimensions: vec![],
             data: vec![],
             total_count: 0,
-        };
+        });
     }
 
-    let filterConditions = to_predicate(search.clone());
-    let stat_column = search.stat_column.unwrap_or("advert_id".to_string());
+    let stat_column = search
+        .stat_column
+        .clone()
+        .unwrap_or("advert_id".to_string());
     let aggregators = if stat_column == "advert_id" || search.aggregators.is_none() {
         to_aggregator(vec!["count".to_string()], &stat_column)
     } else {
-        let aggregators = search.aggregators.unwrap();
+        let aggregators = search.aggregators.clone().unwrap();
         to_aggregator(aggregators, &stat_column)
     };
 
     let by = group.iter().map(col).collect::<Vec<_>>();
-    let df = df
-        .with_columns(&by)
-        .filter(filterConditions)
-        .group_by(by.as_slice())
-        .agg(&aggregators);
+    let df = with_top_n(
+        filter_listings(df.with_columns(&by), &search),
+        &search,
+        &group,
+        &stat_column,
+    )?
+    .group_by(by.as_slice())
+    .agg(&aggregators);
     let result = if !search.order.is_empty() {
         let mut columns = Vec::new();
         let mut orders = Vec::new();
@@ -72,7 +77,7 @@ pub fn chartData(search: StatisticSearchPayload) -> StatisticResponse {
             .with_nulls_last(true);
         df.sort(vec!["count".to_string()], sort).collect().unwrap()
     };
-    to_static_response(&result, group.clone())
+    Ok(to_static_response(&result, group.clone()))
 }
 
 pub fn get_statistic_data(
@@ -83,13 +88,12 @@ pub fn get_statistic_data(
 ) -> Result<Statistics, PolarsError> {
     let df = VEHICLE_STATIC_DATA.clone();
 
-    let filter_conditions = to_predicate(search.clone());
     let price_series = df
         .with_column(col(column))
         .with_column(lit(1).alias("tmp_col"));
 
     // Apply the base filter conditions
-    let mut qr = price_series.filter(filter_conditions);
+    let mut qr = filter_listings(price_series, search);
     let quantiles = generate_quantiles(column, bins);
 
     // Apply the interval filter only if `interval` is `Some`
@@ -163,14 +167,11 @@ fn load_statistics(df: &DataFrame, source: Vec<Quantile>) -> Result<Statistics,
 pub fn clean_data(column: &str, search: StatisticSearchPayload) -> StatInterval {
     let df = VEHICLE_STATIC_DATA.clone();
 
-    let filterConditions = to_predicate(search.clone());
     let price_series = df
         .with_column(col(column))
         .with_column(lit(1).alias("tmp_col"));
 
-    let filtered = price_series
-        .clone()
-        .filter(filterConditions.clone())
+    let filtered = filter_listings(price_series, &search)
         .group_by(vec![col("tmp_col")])
         .agg(&[
             col(column).min().alias("min"),
@@ -352,9 +353,7 @@ pub fn calculate(
     let min = intervals.min().unwrap().start;
     let max = intervals.max().unwrap().end;
     let df = VEHICLE_STATIC_DATA.clone();
-    let filterConditions = to_predicate(search.clone());
-    let df = df
-        .filter(filterConditions)
+    let df = filter_listings(df, search)
         .filter((col(column_name).gt_eq(lit(min))).and(col(column_name).lt_eq(lit(max))));
     let case_column: Expr;
     if intervals.len() == 1 {
@@ -0,0 +1,202 @@
+use crate::model::Chart::Transform;
+
+/// `value / total * 100`, unknown without a total.
+fn percent(value: Option<f64>, total: f64) -> Option<f64> {
+    value.filter(|_| total != 0.0).map(|v| v / total * 100.0)
+}
+
+fn total(values: &[Option<f64>]) -> f64 {
+    values.iter().flatten().sum()
+}
+
+/// Running total; a label without a value keeps the total reached so far.
+fn cumulative(values: &[Option<f64>]) -> Vec<Option<f64>> {
+    let mut sum = None;
+    values
+        .iter()
+        .map(|v| {
+            if let Some(v) = v {
+                sum = Some(sum.unwrap_or(0.0) + v);
+            }
+            sum
+        })
+        .collect()
+}
+
+/// Applies `transform` to every series, each holding one value per label of `labels`.
+pub fn apply(
+    transform: &Transform,
+    labels: &[String],
+    series: &mut [Vec<Option<f64>>],
+) -> Result<(), String> {
+    match transform {
+        Transform::PercentOfRow => {
+            let totals = (0..labels.len())
+                .map(|i| series.iter().filter_map(|s| s[i]).sum::<f64>())
+                .collect::<Vec<_>>();
+            for values in series.iter_mut() {
+                for (value, total) in values.iter_mut().zip(totals.iter()) {
+                    *value = percent(*value, *total);
+                }
+            }
+        }
+        Transform::PercentOfColumn => {
+            for values in series.iter_mut() {
+                let total = total(values);
+                values.iter_mut().for_each(|v| *v = percent(*v, total));
+            }
+        }
+        Transform::PercentOfTotal => {
+            let total = series.iter().map(|s| total(s)).sum::<f64>();
+            for values in series.iter_mut() {
+                values.iter_mut().for_each(|v| *v = percent(*v, total));
+            }
+        }
+        Transform::Cumulative => {
+            for values in series.iter_mut() {
+                *values = cumulative(values);
+            }
+        }
+        Transform::RunningShare => {
+            for values in series.iter_mut() {
+                let total = total(values);
+                *values = cumulative(values)
+                    .into_iter()
+                    .map(|v| percent(v, total))
+                    .collect();
+            }
+        }
+        Transform::IndexToBase(base) => {
+            let position = match base {
+                Some(base) => Some(
+                    labels
+                        .iter()
+                        .position(|l| l == base)
+                        .ok_or_else(|| format!("Unknown base label '{}'", base))?,
+                ),
+                None => None,
+            };
+            for values in series.iter_mut() {
+                let base = match position {
+                    Some(position) => values[position],
+                    None => values.iter().flatten().find(|v| **v != 0.0).copied(),
+                };
+                let base = base.unwrap_or(0.0);
+                values.iter_mut().for_each(|v| *v = percent(*v, base));
+            }
+        }
+        Transform::Diff => {
+            for values in series.iter_mut() {
+                let mut previous = None;
+                for value in values.iter_mut() {
+                    let current = *value;
+                    *value = current.zip(previous).map(|(c, p)| c - p);
+                    previous = current;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Applies `transforms` one after the other.
+pub fn apply_all(
+    transforms: &[Transform],
+    labels: &[String],
+    series: &mut [Vec<Option<f64>>],
+) -> Result<(), String> {
+    for transform in transforms {
+        apply(transform, labels, series)?;
+    }
+    Ok(())
+}
+
+/// Whether integer values stay integers.
+pub fn keeps_integers(transform: &Transform) -> bool {
+    matches!(transform, Transform::Cumulative | Transform::Diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::TestUtils::rounded;
+
+    fn labels() -> Vec<String> {
+        ["2020", "2021", "2022"]
+            .iter()
+            .map(|l| l.to_string())
+            .collect()
+    }
+
+    /// Diesel and petrol listings per year; no petrol listings in 2021.
+    fn series() -> Vec<Vec<Option<f64>>> {
+        vec![
+            vec![Some(30.0), Some(20.0), Some(10.0)],
+            vec![Some(10.0), None, Some(30.0)],
+        ]
+    }
+
+    fn transformed(transform: Transform) -> Vec<Vec<Option<f64>>> {
+        let mut series = series();
+        apply(&transform, &labels(), &mut series).unwrap();
+        series
+            .into_iter()
+            .map(|values| values.into_iter().map(|v| v.map(rounded)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_percentages() {
+        assert_eq!(
+            transformed(Transform::PercentOfRow),
+            vec![
+                vec![Some(75.0), Some(100.0), Some(25.0)],
+                vec![Some(25.0), None, Some(75.0)],
+            ]
+        );
+        assert_eq!(
+            transformed(Transform::PercentOfColumn)[1],
+            vec![Some(25.0), None, Some(75.0)]
+        );
+        assert_eq!(
+            transformed(Transform::PercentOfTotal)[0],
+            vec![Some(30.0), Some(20.0), Some(10.0)]
+        );
+    }
+
+    #[test]
+    fn test_running_values() {
+        assert_eq!(
+            transformed(Transform::Cumulative)[1],
+            vec![Some(10.0), Some(10.0), Some(40.0)]
+        );
+        assert_eq!(
+            transformed(Transform::RunningShare)[0],
+            vec![Some(50.0), Some(83.333333), Some(100.0)]
+        );
+        assert_eq!(
+            transformed(Transform::Diff),
+            vec![vec![None, Some(-10.0), Some(-10.0)], vec![None, None, None]]
+        );
+    }
+
+    #[test]
+    fn test_index_to_base() {
+        assert_eq!(
+            transformed(Transform::IndexToBase(None))[0],
+            vec![Some(100.0), Some(66.666667), Some(33.333333)]
+        );
+        assert_eq!(
+            transformed(Transform::IndexToBase(Some("2022".to_string())))[1],
+            vec![Some(33.333333), None, Some(100.0)]
+        );
+        // A gap filled with 0 is not a base.
+        let mut filled = vec![vec![Some(0.0), Some(50.0), Some(25.0)]];
+        apply(&Transform::IndexToBase(None), &labels(), &mut filled).unwrap();
+        assert_eq!(filled[0], vec![Some(0.0), Some(100.0), Some(50.0)]);
+
+        let mut series = series();
+        let unknown = Transform::IndexToBase(Some("2019".to_string()));
+        assert!(apply(&unknown, &labels(), &mut series).is_err());
+    }
+}
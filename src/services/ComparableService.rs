@@ -0,0 +1,115 @@
+use polars::{
+    frame::DataFrame,
+    prelude::{col, LazyFrame},
+};
+
+use crate::model::{AxumAPIModel::StatisticSearchPayload, Valuation::ComparableListing};
+
+use super::{
+    EstimatorService::{VehicleFeatures, ESTIMATOR_CONFIG},
+    Utils::to_predicate,
+};
+
+/// The `limit` listings of `vehicles` matching `filter` that are closest to `spec`
+/// in year, mileage and power, most similar first.
+pub fn similar_listings(
+    spec: &StatisticSearchPayload,
+    filter: &StatisticSearchPayload,
+    vehicles: &LazyFrame,
+    limit: usize,
+) -> Result<Vec<ComparableListing>, String> {
+    let listings = vehicles
+        .clone()
+        .filter(to_predicate(filter.clone()).and(col("price_in_eur").gt(0)))
+        .collect()
+        .map_err(|e| e.to_string())?;
+    rank_listings(spec, &listings, limit)
+}
+
+pub fn rank_listings(
+    spec: &StatisticSearchPayload,
+    listings: &DataFrame,
+    limit: usize,
+) -> Result<Vec<ComparableListing>, String> {
+    let target = VehicleFeatures::from_spec(spec);
+    let features = VehicleFeatures::from_frame(listings)?;
+    let mut ranked = features
+        .iter()
+        .enumerate()
+        .map(|(row, f)| (row, 1.0 / (1.0 + target.distance(f, &ESTIMATOR_CONFIG.knn))))
+        .collect::<Vec<(usize, f64)>>();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked.truncate(limit);
+
+    let text = |name: &str, row: usize| -> String {
+        listings
+            .column(name)
+            .ok()
+            .and_then(|c| c.str().ok().and_then(|c| c.get(row)).map(|v| v.to_string()))
+            .unwrap_or_default()
+    };
+    let number = |name: &str, row: usize| -> Option<i32> {
+        listings.column(name).ok()?.get(row).ok()?.extract::<i32>()
+    };
+    Ok(ranked
+        .into_iter()
+        .map(|(row, similarity)| ComparableListing {
+            advert_id: text("advert_id", row),
+            title: text("title", row),
+            make: text("make", row),
+            model: text("model", row),
+            year: number("year", row),
+            mileage: number("mileage", row),
+            power: number("power", row),
+            price_in_eur: number("price_in_eur", row),
+            url: text("url", row),
+            similarity,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::{df, prelude::IntoLazy};
+
+    use super::*;
+
+    fn listings() -> DataFrame {
+        df!(
+            "advert_id" => ["a1", "a2", "a3", "a4"],
+            "title" => ["BMW 320d", "BMW 320d M", "BMW 320d Touring", "BMW 320i"],
+            "make" => ["BMW"; 4],
+            "model" => ["320"; 4],
+            "year" => [2015, 2019, 2018, 2019],
+            "mileage" => [180_000, 62_000, 90_000, 60_000],
+            "power" => [190, 190, 190, 184],
+            "price_in_eur" => [12_000, 24_000, 21_000, 0],
+            "url" => ["u1", "u2", "u3", "u4"]
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_similar_listings_most_similar_first() {
+        let spec = StatisticSearchPayload {
+            make: Some("BMW".to_string()),
+            model: Some("320".to_string()),
+            year: Some(2019),
+            mileage: Some(60_000),
+            power: Some(190),
+            ..Default::default()
+        };
+        let filter = StatisticSearchPayload {
+            make: Some("BMW".to_string()),
+            ..Default::default()
+        };
+        let similar = similar_listings(&spec, &filter, &listings().lazy(), 2).unwrap();
+        assert_eq!(similar.len(), 2);
+        assert_eq!(similar[0].advert_id, "a2");
+        assert_eq!(similar[0].url, "u2");
+        assert_eq!(similar[0].price_in_eur, Some(24_000));
+        assert_eq!(similar[1].advert_id, "a3");
+        assert!(similar[0].similarity > similar[1].similarity);
+        assert!(similar[0].similarity <= 1.0);
+    }
+}
@@ -0,0 +1,235 @@
+use polars::prelude::{col, lit, when, DataType, Expr, LazyFrame, RankMethod, RankOptions, NULL};
+
+use crate::model::{AxumAPIModel::StatisticSearchPayload, Valuation::DealScoreConfig};
+
+/// The calculator's segmentation: the requested vehicle with mileage and power bucketed.
+pub const SEGMENT: [&str; 7] = [
+    "make",
+    "model",
+    "year",
+    "engine",
+    "gearbox",
+    "mileage_breakdown",
+    "power_breakdown",
+];
+
+/// Used for listings whose segment has too few priced listings.
+pub const FALLBACK_SEGMENT: [&str; 3] = ["make", "model", "year"];
+
+const PRICE: &str = "deal_price";
+
+struct SegmentStats {
+    count: Expr,
+    score: Expr,
+    percentile: Expr,
+}
+
+fn segment_stats(segment: &[&str]) -> SegmentStats {
+    let partition = segment.iter().map(|c| col(*c)).collect::<Vec<_>>();
+    let price = col(PRICE);
+    let mean = price.clone().mean().over(&partition);
+    let std = price.clone().std(1).over(&partition);
+    let rank = price
+        .clone()
+        .rank(
+            RankOptions {
+                method: RankMethod::Average,
+                descending: false,
+            },
+            None,
+        )
+        .cast(DataType::Float64)
+        .over(&partition);
+    let count = price.clone().count().over(&partition);
+    SegmentStats {
+        score: when(std.clone().gt(lit(0.0)))
+            .then((price.clone() - mean) / std)
+            .otherwise(lit(0.0)),
+        percentile: (rank - lit(0.5)) * lit(100.0) / count.clone().cast(DataType::Float64),
+        count,
+    }
+}
+
+/// Adds `deal_score` (z-score of `price_in_eur` within the listing's segment),
+/// `deal_percentile`, `deal_rating` and `deal_segment_size` to `df`.
+///
+/// Listings without a price, or whose segment and fallback segment both have fewer
+/// than `min_segment_size` priced listings, are not scored.
+pub fn with_deal_scores(df: LazyFrame, config: &DealScoreConfig) -> LazyFrame {
+    let fine = segment_stats(&SEGMENT);
+    let coarse = segment_stats(&FALLBACK_SEGMENT);
+    let min = lit(config.min_segment_size as u32);
+    let use_fine = fine.count.clone().gt_eq(min.clone());
+    let use_coarse = coarse.count.clone().gt_eq(min);
+    let pick = |fine: Expr, coarse: Expr, dtype: DataType| {
+        when(col(PRICE).is_null())
+            .then(lit(NULL).cast(dtype.clone()))
+            .when(use_fine.clone())
+            .then(fine)
+            .when(use_coarse.clone())
+            .then(coarse)
+            .otherwise(lit(NULL).cast(dtype))
+    };
+    let score = col("deal_score");
+    df.with_column(
+        when(col("price_in_eur").gt(lit(0)))
+            .then(col("price_in_eur").cast(DataType::Float64))
+            .otherwise(lit(NULL).cast(DataType::Float64))
+            .alias(PRICE),
+    )
+    .with_columns([
+        pick(fine.score, coarse.score, DataType::Float64).alias("deal_score"),
+        pick(fine.percentile, coarse.percentile, DataType::Float64).alias("deal_percentile"),
+        pick(
+            fine.count.cast(DataType::Int32),
+            coarse.count.cast(DataType::Int32),
+            DataType::Int32,
+        )
+        .alias("deal_segment_size"),
+    ])
+    .with_column(
+        when(score.clone().is_null())
+            .then(lit(NULL).cast(DataType::String))
+            .when(score.clone().lt_eq(lit(config.great)))
+            .then(lit("great"))
+            .when(score.clone().lt_eq(lit(config.good)))
+            .then(lit("good"))
+            .when(score.lt_eq(lit(config.fair)))
+            .then(lit("fair"))
+            .otherwise(lit("overpriced"))
+            .alias("deal_rating"),
+    )
+    .drop([PRICE])
+}
+
+/// The `dealRating` and `dealScoreTo` filters of `search`, for frames with deal scores.
+pub fn deal_predicate(search: &StatisticSearchPayload) -> Option<Expr> {
+    let ratings = search.dealRating.as_ref().and_then(|ratings| {
+        ratings
+            .iter()
+            .map(|r| col("deal_rating").eq(lit(r.clone())))
+            .reduce(|acc, p| acc.or(p))
+    });
+    let score = search
+        .dealScoreTo
+        .map(|score| col("deal_score").lt_eq(lit(score)));
+    [ratings, score]
+        .into_iter()
+        .flatten()
+        .reduce(|acc, p| acc.and(p))
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::{df, frame::DataFrame, prelude::IntoLazy};
+
+    use super::*;
+
+    fn listings() -> DataFrame {
+        let prices = [15_000, 19_000, 20_000, 21_000, 25_000, 0];
+        df!(
+            "make" => ["BMW"; 6],
+            "model" => ["320"; 6],
+            "year" => [2018; 6],
+            "engine" => ["Diesel"; 6],
+            "gearbox" => ["Automatic", "Automatic", "Automatic", "Automatic", "Automatic", "Manual"],
+            "mileage_breakdown" => ["a"; 6],
+            "power_breakdown" => ["b"; 6],
+            "price_in_eur" => prices
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_deal_scores() {
+        let config = DealScoreConfig {
+            min_segment_size: 5,
+            ..Default::default()
+        };
+        let scored = with_deal_scores(listings().lazy(), &config)
+            .collect()
+            .unwrap();
+        let ratings = scored.column("deal_rating").unwrap().str().unwrap();
+        assert_eq!(ratings.get(0), Some("great"));
+        assert_eq!(ratings.get(2), Some("fair"));
+        assert_eq!(ratings.get(4), Some("overpriced"));
+        assert_eq!(ratings.get(5), None);
+        let percentiles = scored.column("deal_percentile").unwrap().f64().unwrap();
+        assert_eq!(percentiles.get(0), Some(10.0));
+        assert_eq!(percentiles.get(4), Some(90.0));
+        let sizes = scored.column("deal_segment_size").unwrap().i32().unwrap();
+        assert_eq!(sizes.get(0), Some(5));
+        assert!(scored.column(PRICE).is_err());
+    }
+
+    #[test]
+    fn test_small_segments_fall_back() {
+        let mut listings = listings();
+        listings
+            .with_column(polars::prelude::Column::new(
+                "gearbox".into(),
+                [
+                    "Automatic",
+                    "Manual",
+                    "Automatic",
+                    "Manual",
+                    "Automatic",
+                    "Manual",
+                ],
+            ))
+            .unwrap();
+        let config = DealScoreConfig {
+            min_segment_size: 4,
+            ..Default::default()
+        };
+        let scored = with_deal_scores(listings.clone().lazy(), &config)
+            .collect()
+            .unwrap();
+        let sizes = scored.column("deal_segment_size").unwrap().i32().unwrap();
+        assert_eq!(sizes.get(0), Some(5));
+
+        let config = DealScoreConfig {
+            min_segment_size: 6,
+            ..Default::default()
+        };
+        let scored = with_deal_scores(listings.lazy(), &config)
+            .collect()
+            .unwrap();
+        assert_eq!(scored.column("deal_score").unwrap().null_count(), 6);
+    }
+
+    #[test]
+    fn test_deal_predicate() {
+        let config = DealScoreConfig {
+            min_segment_size: 5,
+            ..Default::default()
+        };
+        let search = StatisticSearchPayload {
+            dealRating: Some(vec!["great".to_string(), "fair".to_string()]),
+            dealScoreTo: Some(0.0),
+            ..Default::default()
+        };
+        let deals = with_deal_scores(listings().lazy(), &config)
+            .filter(deal_predicate(&search).unwrap())
+            .collect()
+            .unwrap();
+        assert_eq!(
+            deals
+                .column("price_in_eur")
+                .unwrap()
+                .i32()
+                .unwrap()
+                .to_vec(),
+            vec![Some(15_000), Some(19_000), Some(20_000)]
+        );
+        assert!(deal_predicate(&StatisticSearchPayload::default()).is_none());
+
+        // Frames without deal scores ignore the deal filters of a shared search.
+        let filtered = listings()
+            .lazy()
+            .filter(crate::services::Utils::to_predicate(search))
+            .collect()
+            .unwrap();
+        assert_eq!(filtered.height(), 6);
+    }
+}
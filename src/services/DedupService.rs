@@ -0,0 +1,267 @@
+use std::collections::{BTreeSet, HashMap};
+
+use polars::{
+    frame::{DataFrame, UniqueKeepStrategy},
+    prelude::{Column, DataType},
+};
+
+use crate::model::Valuation::DedupConfig;
+
+/// Listings are only compared within the same make, model and year.
+const BLOCK: [&str; 3] = ["make", "model", "year"];
+
+struct Listing {
+    source: Option<String>,
+    mileage: Option<i32>,
+    power: Option<i32>,
+    price: Option<i32>,
+    title: Option<BTreeSet<String>>,
+}
+
+fn strings(df: &DataFrame, name: &str) -> Vec<Option<String>> {
+    match df.column(name).and_then(|c| c.cast(&DataType::String)) {
+        Ok(c) => c
+            .str()
+            .unwrap()
+            .into_iter()
+            .map(|v| v.map(|v| v.trim().to_lowercase()))
+            .collect(),
+        Err(_) => vec![None; df.height()],
+    }
+}
+
+fn ints(df: &DataFrame, name: &str) -> Vec<Option<i32>> {
+    match df.column(name).and_then(|c| c.cast(&DataType::Int32)) {
+        Ok(c) => c.i32().unwrap().to_vec(),
+        Err(_) => vec![None; df.height()],
+    }
+}
+
+fn words(title: &str) -> BTreeSet<String> {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+fn jaccard(a: &BTreeSet<String>, b: &BTreeSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+/// Both values known and within `tolerance`, or both unknown.
+fn close(a: Option<i32>, b: Option<i32>, tolerance: f64) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => ((a - b).abs() as f64) <= tolerance,
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// Listings of one source are only matched with `same_source`; an unknown source
+/// counts as a different one.
+fn other_source(a: &Listing, b: &Listing, config: &DedupConfig) -> bool {
+    config.same_source || a.source.is_none() || a.source != b.source
+}
+
+fn is_duplicate(a: &Listing, b: &Listing, config: &DedupConfig) -> bool {
+    let price_tolerance = match (a.price, b.price) {
+        (Some(a), Some(b)) => a.min(b) as f64 * config.price_ratio,
+        _ => 0.0,
+    };
+    other_source(a, b, config)
+        && close(a.power, b.power, config.power_tolerance as f64)
+        && close(a.price, b.price, price_tolerance)
+        && match (&a.title, &b.title) {
+            (Some(a), Some(b)) => jaccard(a, b) >= config.title_similarity,
+            _ => true,
+        }
+}
+
+fn mileage_tolerance(mileage: i32, config: &DedupConfig) -> i32 {
+    (config.mileage_tolerance as f64).max(mileage as f64 * config.mileage_ratio) as i32
+}
+
+fn find(parents: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    let mut i = i;
+    while parents[i] != root {
+        let next = parents[i];
+        parents[i] = root;
+        i = next;
+    }
+    root
+}
+
+/// Cluster of every row of `df`: the index of the first row of the cluster.
+pub fn cluster_ids(df: &DataFrame, config: &DedupConfig) -> Vec<u32> {
+    let keys = BLOCK.map(|c| strings(df, c));
+    let sources = strings(df, "source");
+    let mileages = ints(df, "mileage");
+    let powers = ints(df, "power");
+    let prices = ints(df, "price_in_eur");
+    let titles = strings(df, "title");
+    let listings = (0..df.height())
+        .map(|i| Listing {
+            source: sources[i].clone(),
+            mileage: mileages[i],
+            power: powers[i],
+            price: prices[i],
+            title: titles[i].as_deref().map(words),
+        })
+        .collect::<Vec<_>>();
+
+    let mut blocks: HashMap<Vec<Option<String>>, Vec<usize>> = HashMap::new();
+    for i in 0..df.height() {
+        let key = keys.iter().map(|k| k[i].clone()).collect::<Vec<_>>();
+        blocks.entry(key).or_default().push(i);
+    }
+
+    let mut parents = (0..df.height()).collect::<Vec<_>>();
+    for rows in blocks.values_mut() {
+        // Sorted by mileage so that the comparison stops at the first listing out of range.
+        rows.sort_by_key(|i| listings[*i].mileage);
+        for (n, &i) in rows.iter().enumerate() {
+            for &j in rows[n + 1..].iter() {
+                let (a, b) = (&listings[i], &listings[j]);
+                if let (Some(from), Some(to)) = (a.mileage, b.mileage) {
+                    if to - from > mileage_tolerance(from, config) {
+                        break;
+                    }
+                } else if a.mileage != b.mileage {
+                    continue;
+                }
+                if is_duplicate(a, b, config) {
+                    let (ri, rj) = (find(&mut parents, i), find(&mut parents, j));
+                    parents[ri.max(rj)] = ri.min(rj);
+                }
+            }
+        }
+    }
+    (0..df.height())
+        .map(|i| find(&mut parents, i) as u32)
+        .collect()
+}
+
+/// Adds `cluster_id` (shared by listings that are likely the same car) and `cluster_size`.
+pub fn with_clusters(mut df: DataFrame, config: &DedupConfig) -> Result<DataFrame, String> {
+    let ids = cluster_ids(&df, config);
+    let mut sizes: HashMap<u32, u32> = HashMap::new();
+    for id in ids.iter() {
+        *sizes.entry(*id).or_default() += 1;
+    }
+    let sizes = ids.iter().map(|id| sizes[id]).collect::<Vec<_>>();
+    df.with_column(Column::new("cluster_id".into(), ids))
+        .and_then(|df| df.with_column(Column::new("cluster_size".into(), sizes)))
+        .map_err(|e| e.to_string())?;
+    Ok(df)
+}
+
+/// Keeps the first listing of every cluster.
+pub fn deduplicate(df: DataFrame, config: &DedupConfig) -> Result<DataFrame, String> {
+    with_clusters(df, config)?
+        .unique_stable(
+            Some(&["cluster_id".to_string()]),
+            UniqueKeepStrategy::First,
+            None,
+        )
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::{df, prelude::IntoLazy};
+
+    use crate::{model::AxumAPIModel::StatisticSearchPayload, services::Utils::filter_listings};
+
+    use super::*;
+
+    fn listings() -> DataFrame {
+        df!(
+            "source" => ["mobile.bg", "cars.bg", "auto.bg", "mobile.bg", "cars.bg", "mobile.bg"],
+            "title" => [
+                "BMW 320d M Sport",
+                "BMW 320 d M-Sport",
+                "BMW 320d M Sport",
+                "BMW 320d Luxury Line",
+                "BMW 320d M Sport",
+                "BMW 320d M Sport",
+            ],
+            "make" => ["BMW", "BMW", "BMW", "BMW", "BMW", "BMW"],
+            "model" => ["320", "320", "320", "320", "320", "320"],
+            "year" => [2018, 2018, 2018, 2018, 2019, 2018],
+            "mileage" => [120_000, 121_500, 119_000, 120_000, 120_000, 150_000],
+            "power" => [190, 190, 188, 190, 190, 190],
+            "price_in_eur" => [20_000, 20_500, 19_800, 20_000, 20_000, 20_000]
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_cluster_ids() {
+        let ids = cluster_ids(&listings(), &DedupConfig::default());
+        // Same car on three sites; a different trim, year and mileage are separate cars.
+        assert_eq!(ids, vec![0, 0, 0, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_deduplicate() {
+        let clustered = with_clusters(listings(), &DedupConfig::default()).unwrap();
+        let sizes = clustered.column("cluster_size").unwrap().u32().unwrap();
+        assert_eq!(sizes.get(1), Some(3));
+        assert_eq!(sizes.get(3), Some(1));
+
+        let deduplicated = deduplicate(listings(), &DedupConfig::default()).unwrap();
+        assert_eq!(deduplicated.height(), 4);
+        let sources = deduplicated.column("source").unwrap().str().unwrap();
+        assert_eq!(sources.get(0), Some("mobile.bg"));
+    }
+
+    #[test]
+    fn test_same_source() {
+        // The first two listings again, both on the same site.
+        let mut listings = listings().slice(0, 2);
+        listings
+            .with_column(Column::new("source".into(), ["mobile.bg"; 2]))
+            .unwrap();
+        assert_eq!(cluster_ids(&listings, &DedupConfig::default()), vec![0, 1]);
+
+        let config = DedupConfig {
+            same_source: true,
+            ..Default::default()
+        };
+        assert_eq!(cluster_ids(&listings, &config), vec![0, 0]);
+    }
+
+    #[test]
+    fn test_without_titles() {
+        let mut listings = listings();
+        let _ = listings.drop_in_place("title").unwrap();
+        let ids = cluster_ids(&listings, &DedupConfig::default());
+        // Without titles the trim no longer tells the fourth listing apart.
+        assert_eq!(ids, vec![0, 0, 0, 0, 4, 5]);
+
+        let filter = StatisticSearchPayload {
+            make: Some("BMW".to_string()),
+            deduplicate: Some(true),
+            ..Default::default()
+        };
+        let all = filter_listings(
+            listings.clone().lazy(),
+            &StatisticSearchPayload {
+                deduplicate: None,
+                ..filter.clone()
+            },
+        );
+        assert_eq!(all.collect().unwrap().height(), 6);
+        let deduplicated = filter_listings(listings.lazy(), &filter);
+        assert_eq!(deduplicated.collect().unwrap().height(), 3);
+    }
+}
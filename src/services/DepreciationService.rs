@@ -0,0 +1,185 @@
+use chrono::{Datelike, Utc};
+use log::info;
+use polars::{
+    error::PolarsError,
+    frame::DataFrame,
+    prelude::{col, lit, DataType, LazyFrame},
+};
+
+use crate::{
+    model::{
+        AxumAPIModel::DepreciationRequest,
+        Depreciation::{CurvePoint, DepreciationModel},
+    },
+    ESTIMATED_PRICES_DATA, PRICE_DATA,
+};
+
+use super::{extract_column_values, Regression::least_squares};
+
+const MIN_OBSERVATIONS: usize = 10;
+
+pub fn depreciation(request: DepreciationRequest) -> Result<DepreciationModel, String> {
+    let source = match request.source.as_deref() {
+        Some("prices") => PRICE_DATA.clone(),
+        _ => ESTIMATED_PRICES_DATA.clone(),
+    };
+    let data = training_data(source, &request).map_err(|e| e.to_string())?;
+    let mut model = fit_depreciation(&data, &request)?;
+    if let Some(age) = request.age {
+        let mileage = request.mileage.unwrap_or(model.annual_mileage * age.max(0));
+        model.valuation = Some(model.value_at(age, mileage));
+    }
+    Ok(model)
+}
+
+fn training_data(df: LazyFrame, request: &DepreciationRequest) -> Result<DataFrame, PolarsError> {
+    let mut predicate = col("make")
+        .eq(lit(request.make.clone()))
+        .and(col("model").eq(lit(request.model.clone())));
+    if let Some(engine) = &request.engine {
+        predicate = predicate.and(col("engine").eq(lit(engine.clone())));
+    }
+    df.filter(predicate)
+        .select([col("year"), col("mileage"), col("price_in_eur")])
+        .drop_nulls(None)
+        .filter(
+            col("price_in_eur")
+                .gt(lit(0))
+                .and(col("mileage").gt_eq(lit(0))),
+        )
+        .collect()
+}
+
+/// Fits the depreciation model on a frame with `year`, `mileage` and `price_in_eur` columns.
+pub fn fit_depreciation(
+    data: &DataFrame,
+    request: &DepreciationRequest,
+) -> Result<DepreciationModel, String> {
+    if data.height() < MIN_OBSERVATIONS {
+        return Err(format!(
+            "Not enough data to fit a depreciation model: {} rows, {} required",
+            data.height(),
+            MIN_OBSERVATIONS
+        ));
+    }
+    let reference_year = request.reference_year.unwrap_or(Utc::now().year());
+    let values = |name: &str| -> Result<Vec<f64>, String> {
+        let column = data
+            .column(name)
+            .and_then(|c| c.cast(&DataType::Float64))
+            .map_err(|e| e.to_string())?;
+        extract_column_values(column.as_materialized_series()).map_err(|e| e.to_string())
+    };
+    let years = values("year")?;
+    let mileages = values("mileage")?;
+    let prices = values("price_in_eur")?;
+
+    let ages = years
+        .iter()
+        .map(|year| (reference_year as f64 - year).max(0.0))
+        .collect::<Vec<f64>>();
+    let rows = ages
+        .iter()
+        .zip(&mileages)
+        .map(|(age, mileage)| vec![*age, mileage / 10_000.0])
+        .collect::<Vec<_>>();
+    let targets = prices.iter().map(|p| p.ln()).collect::<Vec<f64>>();
+
+    let fit = least_squares(&rows, &targets)
+        .ok_or("Depreciation model could not be fitted: features are collinear")?;
+    info!(
+        "Depreciation fit for {} {}: {:?}",
+        request.make, request.model, fit
+    );
+
+    let mut per_year = ages
+        .iter()
+        .zip(&mileages)
+        .map(|(age, mileage)| mileage / age.max(1.0))
+        .collect::<Vec<f64>>();
+    per_year.sort_by(|a, b| a.total_cmp(b));
+    let annual_mileage = per_year[per_year.len() / 2].round() as i32;
+
+    let mut model = DepreciationModel {
+        make: request.make.clone(),
+        model: request.model.clone(),
+        engine: request.engine.clone(),
+        reference_year,
+        count: fit.count,
+        intercept: fit.coefficients[0],
+        age_coefficient: fit.coefficients[1],
+        mileage_coefficient: fit.coefficients[2],
+        annual_depreciation: 1.0 - fit.coefficients[1].exp(),
+        mileage_depreciation: 1.0 - fit.coefficients[2].exp(),
+        annual_mileage,
+        r_squared: fit.r_squared,
+        residual_std: fit.residual_std,
+        curve: vec![],
+        valuation: None,
+    };
+    let max_age = ages.iter().cloned().fold(0.0, f64::max) as i32;
+    model.curve = (0..=max_age)
+        .map(|age| model.value_at(age, age * annual_mileage))
+        .collect::<Vec<CurvePoint>>();
+    Ok(model)
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::df;
+
+    use super::*;
+
+    #[test]
+    fn test_fit_depreciation() {
+        let years = (2010..2022).collect::<Vec<i32>>();
+        let mileages = years
+            .iter()
+            .map(|y| (2024 - y) * 15_000 + (y % 3) * 4_000)
+            .collect::<Vec<i32>>();
+        let prices = years
+            .iter()
+            .zip(&mileages)
+            .map(|(y, m)| {
+                (10.5 - 0.1 * (2024 - y) as f64 - 0.02 * (*m as f64 / 10_000.0)
+                    + if y % 2 == 0 { 0.01 } else { -0.01 })
+                .exp()
+                .round() as i32
+            })
+            .collect::<Vec<i32>>();
+        let data = df!(
+            "year" => years,
+            "mileage" => mileages,
+            "price_in_eur" => prices
+        )
+        .unwrap();
+        let request = DepreciationRequest {
+            make: "BMW".to_string(),
+            model: "320".to_string(),
+            reference_year: Some(2024),
+            ..Default::default()
+        };
+
+        let model = fit_depreciation(&data, &request).unwrap();
+        assert_eq!(model.count, 12);
+        assert!(model.annual_mileage >= 15_000 && model.annual_mileage < 20_000);
+        assert!(model.age_coefficient < 0.0);
+        assert!(model.r_squared > 0.99);
+        assert_eq!(model.curve.len(), 15);
+        assert!(model.curve[0].value > model.curve[14].value);
+
+        let point = model.value_at(5, 75_000);
+        assert!(point.lower < point.value && point.value < point.upper);
+    }
+
+    #[test]
+    fn test_fit_depreciation_not_enough_data() {
+        let data = df!(
+            "year" => [2018, 2019],
+            "mileage" => [50_000, 40_000],
+            "price_in_eur" => [20_000, 22_000]
+        )
+        .unwrap();
+        assert!(fit_depreciation(&data, &DepreciationRequest::default()).is_err());
+    }
+}
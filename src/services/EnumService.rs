@@ -11,36 +11,9 @@ use polars::{
     },
 };
 
+use super::BucketService::{bucket_options, definition, threshold_options};
+
 lazy_static! {
-    pub static ref MILEAGE_FILTER: BTreeMap<String, String> = {
-        let mut map = BTreeMap::new();
-        map.insert("5000".to_string(), "5,000km".to_string());
-        map.insert("10000".to_string(), "10,000km".to_string());
-        map.insert("20000".to_string(), "20,000km".to_string());
-        map.insert("30000".to_string(), "30,000km".to_string());
-        map.insert("40000".to_string(), "40,000km".to_string());
-        map.insert("50000".to_string(), "50,000km".to_string());
-        map.insert("60000".to_string(), "60,000 km".to_string());
-        map.insert("70000".to_string(), "70,000 km".to_string());
-        map.insert("80000".to_string(), "80,000 km".to_string());
-        map.insert("90000".to_string(), "90,000 km".to_string());
-        map.insert("100000".to_string().to_string(), "100,000km".to_string());
-        map.insert("125000".to_string(), "125,000 km".to_string());
-        map.insert("150000".to_string(), "150,000 km".to_string());
-        map
-    };
-    pub static ref MILEAGE_STATUS_FILTER: BTreeMap<String, String> = {
-        let mut map = BTreeMap::new();
-        map.insert("1".to_string(), "Less than 20 000 km".to_string());
-        map.insert("2".to_string(), "Betweeen 20 000 km. and 40 000 km.".to_string());
-        map.insert("3".to_string(), "Betweeen 40 000 km. and 60 000 km.".to_string());
-        map.insert("4".to_string(), "Betweeen 60 000 km. and 80 000 km.".to_string());
-        map.insert("5".to_string(), "Betweeen 80 000 km. and 100 000 km.".to_string());
-        map.insert("6".to_string(), "Betweeen 100 000 km. and 120 000 km.".to_string());
-        map.insert("7".to_string(), "Betweeen 120 000 km. and 150 000 km.".to_string());
-        map.insert("10".to_string(), "Over 150 000 km.".to_string());
-        map
-    };
     pub static ref CC_FILTER: BTreeMap<String, String> = {
         let mut map = BTreeMap::new();
         map.insert("900".to_string(), "900".to_string());
@@ -58,25 +31,6 @@ lazy_static! {
         map.insert("4500".to_string(), "4,500".to_string());
         map
     };
-    pub static ref POWER_FILTER: BTreeMap<String, String> = {
-        let mut map = BTreeMap::new();
-        map.insert("34".to_string(), "34".to_string());
-        map.insert("50".to_string(), "50".to_string());
-        map.insert("60".to_string(), "60".to_string());
-        map.insert("75".to_string(), "75".to_string());
-        map.insert("90".to_string(), "90".to_string());
-        map.insert("101".to_string(), "101".to_string());
-        map.insert("118".to_string(), "118".to_string());
-        map.insert("131".to_string(), "131".to_string());
-        map.insert("150".to_string(), "150".to_string());
-        map.insert("200".to_string(), "200".to_string());
-        map.insert("252".to_string(), "252".to_string());
-        map.insert("303".to_string(), "303".to_string());
-        map.insert("358".to_string(), "358".to_string());
-        map.insert("402".to_string(), "402".to_string());
-        map.insert("454".to_string(), "454".to_string());
-        map
-    };
     pub static ref PRICE_FILTER: BTreeMap<String, String> = {
         let mut map = BTreeMap::new();
         map.insert("5000".to_string(), "5,000".to_string());
@@ -197,9 +151,10 @@ pub fn select(filter: &str, df: &LazyFrame) -> HashMap<String, String> {
         return HashMap::new();
     }
     let btreemap = match filter.to_lowercase().as_str() {
-        "mileage" => MILEAGE_FILTER.clone(),
-        "mileage_status" => MILEAGE_STATUS_FILTER.clone(),
-        "power" => POWER_FILTER.clone(),
+        "mileage" => bucket_thresholds("mileage"),
+        "mileage_status" => bucket_labels("mileage"),
+        "power" => bucket_thresholds("power"),
+        "power_status" => bucket_labels("power"),
         "make" => unique_values(df, filter),
         "engine" => unique_values(df, filter),
         "gearbox" => unique_values(df, filter),
@@ -230,6 +185,14 @@ pub fn select(filter: &str, df: &LazyFrame) -> HashMap<String, String> {
     btreemap.into_iter().collect()
 }
 
+fn bucket_thresholds(name: &str) -> BTreeMap<String, String> {
+    definition(name).map(threshold_options).unwrap_or_default()
+}
+
+fn bucket_labels(name: &str) -> BTreeMap<String, String> {
+    definition(name).map(bucket_options).unwrap_or_default()
+}
+
 fn unique_values(df: &polars::prelude::LazyFrame, column_name: &str) -> BTreeMap<String, String> {
     let unique = &df
         .clone()
@@ -0,0 +1,563 @@
+use std::{collections::HashMap, fs};
+
+use lazy_static::lazy_static;
+use log::{error, info};
+use polars::{
+    error::PolarsError,
+    frame::DataFrame,
+    prelude::{col, DataType, IntoLazy},
+};
+use serde_json::Value;
+
+use crate::{
+    model::{
+        AxumAPIModel::StatisticSearchPayload,
+        Valuation::{
+            Estimate, EstimatorConfig, KnnConfig, MedianConfig, RegressionConfig, WeightKind,
+            WeightedQuantileConfig,
+        },
+    },
+    ESTIMATORS_CONFIG_FILE,
+};
+
+use super::{Regression::least_squares, VehicleService::to_generic_json};
+
+lazy_static! {
+    pub static ref ESTIMATOR_CONFIG: EstimatorConfig =
+        load_estimator_config(ESTIMATORS_CONFIG_FILE);
+}
+
+pub fn load_estimator_config(file: &str) -> EstimatorConfig {
+    match fs::read_to_string(file) {
+        Ok(content) => match serde_yaml::from_str::<EstimatorConfig>(&content) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Invalid estimator config {}: {}. Using defaults", file, e);
+                EstimatorConfig::default()
+            }
+        },
+        Err(_) => {
+            info!("Estimator config {} not found. Using defaults", file);
+            EstimatorConfig::default()
+        }
+    }
+}
+
+/// Estimates the value of `spec` from a set of comparable listings.
+///
+/// The comparables are rows of `ESTIMATED_PRICES_DATA` (or a frame with the same
+/// columns) that already match the search filter.
+pub trait PriceEstimator {
+    fn name(&self) -> &'static str;
+
+    fn estimate(
+        &self,
+        spec: &StatisticSearchPayload,
+        comparables: &DataFrame,
+    ) -> Result<Estimate, String>;
+}
+
+pub fn estimator(
+    name: Option<&str>,
+    config: &EstimatorConfig,
+) -> Result<Box<dyn PriceEstimator>, String> {
+    match name.unwrap_or(config.default.as_str()) {
+        "weighted_quantile" => Ok(Box::new(WeightedQuantileEstimator {
+            config: config.weighted_quantile.clone(),
+        })),
+        "median" => Ok(Box::new(MedianEstimator {
+            config: config.median.clone(),
+        })),
+        "knn" => Ok(Box::new(KnnEstimator {
+            config: config.knn.clone(),
+        })),
+        "regression" => Ok(Box::new(RegressionEstimator {
+            config: config.regression.clone(),
+        })),
+        other => Err(format!("Unknown estimator: {}", other)),
+    }
+}
+
+/// Count, mean, median, quantiles and RSD of `price_in_eur` over the comparables.
+pub fn comparable_statistics(
+    comparables: &DataFrame,
+) -> Result<HashMap<String, Value>, PolarsError> {
+    let column = "price_in_eur";
+    let data = comparables
+        .clone()
+        .lazy()
+        .select([
+            col(column).count().alias("count"),
+            col(column).min().alias("min"),
+            col(column).mean().alias("mean"),
+            col(column).median().alias("median"),
+            col(column)
+                .quantile(0.66.into(), polars::prelude::QuantileMethod::Nearest)
+                .alias("quantile_66"),
+            col(column)
+                .quantile(0.75.into(), polars::prelude::QuantileMethod::Nearest)
+                .alias("quantile_75"),
+            col(column)
+                .quantile(0.8.into(), polars::prelude::QuantileMethod::Nearest)
+                .alias("quantile_80"),
+            col(column)
+                .quantile(0.85.into(), polars::prelude::QuantileMethod::Nearest)
+                .alias("quantile_85"),
+            col(column).max().alias("max"),
+            col(column).std(1).alias("std"),
+            (col(column).std(1) / col(column).mean()).alias("rsd"),
+        ])
+        .collect()?;
+    Ok(to_generic_json(&data))
+}
+
+fn statistic(stats: &HashMap<String, Value>, name: &str) -> Result<f64, String> {
+    stats
+        .get(name)
+        .and_then(|v| v.as_array())
+        .and_then(|v| v.first())
+        .and_then(|v| v.as_f64())
+        .ok_or(format!("Statistic {} is not available", name))
+}
+
+/// Non-null values of a numeric column converted to `f64`.
+pub fn column_values(df: &DataFrame, name: &str) -> Result<Vec<Option<f64>>, String> {
+    let column = df
+        .column(name)
+        .and_then(|c| c.cast(&DataType::Float64))
+        .map_err(|e| e.to_string())?;
+    Ok(column
+        .f64()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .collect())
+}
+
+/// Nearest-rank quantile of an ascending slice.
+pub fn quantile(sorted: &[f64], q: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let index = (q * (sorted.len() - 1) as f64).round() as usize;
+    sorted[index.min(sorted.len() - 1)]
+}
+
+pub fn confidence(count: usize, dispersion: f64) -> f64 {
+    (1.0 - dispersion).clamp(0.0, 1.0) * count as f64 / (count as f64 + 10.0)
+}
+
+fn sorted_prices(comparables: &DataFrame) -> Result<Vec<f64>, String> {
+    let mut prices = column_values(comparables, "price_in_eur")?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<f64>>();
+    prices.sort_by(|a, b| a.total_cmp(b));
+    Ok(prices)
+}
+
+/// The calculator's original method: a weighted blend of mean, median and upper
+/// quantiles whose weights depend on the relative standard deviation.
+pub struct WeightedQuantileEstimator {
+    pub config: WeightedQuantileConfig,
+}
+
+impl WeightedQuantileEstimator {
+    /// Returns the estimation and the index of the weight band that was applied.
+    pub fn weighted_value(&self, stats: &HashMap<String, Value>) -> Result<(f64, usize), String> {
+        let rsd = statistic(stats, "rsd")?;
+        let (index, band) = self
+            .config
+            .bands
+            .iter()
+            .enumerate()
+            .find(|(_, band)| band.max_rsd.is_none_or(|max| rsd <= max))
+            .ok_or(format!("No weight band configured for RSD {}", rsd))?;
+
+        let mut estimation = 0.0;
+        let mut total = 0.0;
+        for weight in band.weights.iter() {
+            let w = match weight.kind {
+                WeightKind::Fixed => weight.value,
+                WeightKind::Scaled => (1.0 - rsd) * weight.value,
+                WeightKind::Rsd => rsd,
+            };
+            estimation += w * statistic(stats, &weight.statistic)?;
+            total += w;
+        }
+        if band.normalize && (total - 1.0).abs() > 0.0001 {
+            estimation *= 1.0 / total;
+        }
+        Ok((estimation, index))
+    }
+}
+
+impl PriceEstimator for WeightedQuantileEstimator {
+    fn name(&self) -> &'static str {
+        "weighted_quantile"
+    }
+
+    fn estimate(
+        &self,
+        _spec: &StatisticSearchPayload,
+        comparables: &DataFrame,
+    ) -> Result<Estimate, String> {
+        let stats = comparable_statistics(comparables).map_err(|e| e.to_string())?;
+        let (estimation, band) = self.weighted_value(&stats)?;
+        let count = statistic(&stats, "count")? as usize;
+        let rsd = statistic(&stats, "rsd").unwrap_or_default();
+        Ok(Estimate {
+            strategy: self.name().to_string(),
+            estimation,
+            lower: statistic(&stats, "median")?.min(estimation),
+            upper: statistic(&stats, "quantile_85")?.max(estimation),
+            confidence: confidence(count, rsd),
+            count,
+            explanation: format!(
+                "Weighted blend of mean, median and quantiles of {} comparables; RSD {:.0}% selected weight band {}",
+                count,
+                rsd * 100.0,
+                band + 1
+            ),
+        })
+    }
+}
+
+/// Median of the comparables with an inter-quantile range.
+pub struct MedianEstimator {
+    pub config: MedianConfig,
+}
+
+impl PriceEstimator for MedianEstimator {
+    fn name(&self) -> &'static str {
+        "median"
+    }
+
+    fn estimate(
+        &self,
+        _spec: &StatisticSearchPayload,
+        comparables: &DataFrame,
+    ) -> Result<Estimate, String> {
+        let prices = sorted_prices(comparables)?;
+        if prices.is_empty() {
+            return Err("No comparables found".to_string());
+        }
+        let median = quantile(&prices, 0.5);
+        let lower = quantile(&prices, self.config.lower_quantile);
+        let upper = quantile(&prices, self.config.upper_quantile);
+        let spread = if median > 0.0 {
+            (upper - lower) / median
+        } else {
+            1.0
+        };
+        Ok(Estimate {
+            strategy: self.name().to_string(),
+            estimation: median,
+            lower,
+            upper,
+            confidence: confidence(prices.len(), spread),
+            count: prices.len(),
+            explanation: format!(
+                "Median of {} comparables; range spans quantiles {} to {}",
+                prices.len(),
+                self.config.lower_quantile,
+                self.config.upper_quantile
+            ),
+        })
+    }
+}
+
+/// Year, mileage and power of a vehicle, used to measure similarity.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VehicleFeatures {
+    pub year: Option<f64>,
+    pub mileage: Option<f64>,
+    pub power: Option<f64>,
+}
+
+impl VehicleFeatures {
+    pub fn from_spec(spec: &StatisticSearchPayload) -> Self {
+        VehicleFeatures {
+            year: spec.year.map(|v| v as f64),
+            mileage: spec.mileage.map(|v| v as f64),
+            power: spec.power.map(|v| v as f64),
+        }
+    }
+
+    pub fn from_frame(df: &DataFrame) -> Result<Vec<Self>, String> {
+        let years = column_values(df, "year")?;
+        let mileages = column_values(df, "mileage")?;
+        let powers = column_values(df, "power")?;
+        Ok(years
+            .into_iter()
+            .zip(mileages)
+            .zip(powers)
+            .map(|((year, mileage), power)| VehicleFeatures {
+                year,
+                mileage,
+                power,
+            })
+            .collect())
+    }
+
+    /// Scaled euclidean distance over the features known for `self`.
+    /// A feature missing on `other` counts as one scale unit away.
+    pub fn distance(&self, other: &VehicleFeatures, scales: &KnnConfig) -> f64 {
+        [
+            (self.year, other.year, scales.year_scale),
+            (self.mileage, other.mileage, scales.mileage_scale),
+            (self.power, other.power, scales.power_scale),
+        ]
+        .iter()
+        .filter_map(|(a, b, scale)| {
+            a.map(|a| match b {
+                Some(b) => ((a - b) / scale).powi(2),
+                None => 1.0,
+            })
+        })
+        .sum::<f64>()
+        .sqrt()
+    }
+}
+
+/// Inverse-distance weighted mean of the `k` comparables closest in year,
+/// mileage and power.
+pub struct KnnEstimator {
+    pub config: KnnConfig,
+}
+
+impl PriceEstimator for KnnEstimator {
+    fn name(&self) -> &'static str {
+        "knn"
+    }
+
+    fn estimate(
+        &self,
+        spec: &StatisticSearchPayload,
+        comparables: &DataFrame,
+    ) -> Result<Estimate, String> {
+        let target = VehicleFeatures::from_spec(spec);
+        let mut neighbours = VehicleFeatures::from_frame(comparables)?
+            .iter()
+            .zip(column_values(comparables, "price_in_eur")?)
+            .filter_map(|(features, price)| {
+                price.map(|p| (target.distance(features, &self.config), p))
+            })
+            .collect::<Vec<(f64, f64)>>();
+        if neighbours.is_empty() {
+            return Err("No comparables found".to_string());
+        }
+        neighbours.sort_by(|a, b| a.0.total_cmp(&b.0));
+        neighbours.truncate(self.config.k.max(1));
+
+        let weights = neighbours
+            .iter()
+            .map(|(d, _)| 1.0 / (1.0 + d))
+            .collect::<Vec<f64>>();
+        let total = weights.iter().sum::<f64>();
+        let estimation = neighbours
+            .iter()
+            .zip(&weights)
+            .map(|((_, p), w)| p * w)
+            .sum::<f64>()
+            / total;
+        let variance = neighbours
+            .iter()
+            .zip(&weights)
+            .map(|((_, p), w)| w * (p - estimation).powi(2))
+            .sum::<f64>()
+            / total;
+        let std = variance.sqrt();
+        let mean_distance =
+            neighbours.iter().map(|(d, _)| d).sum::<f64>() / neighbours.len() as f64;
+        Ok(Estimate {
+            strategy: self.name().to_string(),
+            estimation,
+            lower: estimation - std,
+            upper: estimation + std,
+            confidence: confidence(neighbours.len(), std / estimation)
+                * (1.0 / (1.0 + mean_distance)),
+            count: neighbours.len(),
+            explanation: format!(
+                "Distance-weighted mean of the {} nearest comparables by year, mileage and power (mean distance {:.2})",
+                neighbours.len(),
+                mean_distance
+            ),
+        })
+    }
+}
+
+/// Log-linear regression of price on year, mileage and power fitted on the
+/// comparables and evaluated at the requested vehicle.
+pub struct RegressionEstimator {
+    pub config: RegressionConfig,
+}
+
+impl PriceEstimator for RegressionEstimator {
+    fn name(&self) -> &'static str {
+        "regression"
+    }
+
+    fn estimate(
+        &self,
+        spec: &StatisticSearchPayload,
+        comparables: &DataFrame,
+    ) -> Result<Estimate, String> {
+        let target = VehicleFeatures::from_spec(spec);
+        let observations = VehicleFeatures::from_frame(comparables)?
+            .into_iter()
+            .zip(column_values(comparables, "price_in_eur")?)
+            .filter_map(|(f, price)| match (f.year, f.mileage, f.power, price) {
+                (Some(y), Some(m), Some(p), Some(price)) if price > 0.0 => {
+                    Some(([y, m / 10_000.0, p / 100.0], price.ln()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<([f64; 3], f64)>>();
+        if observations.len() < self.config.min_observations {
+            return Err(format!(
+                "Not enough comparables for a regression: {} rows, {} required",
+                observations.len(),
+                self.config.min_observations
+            ));
+        }
+
+        // Features without variation (e.g. a fixed year in the filter) are collinear
+        // with the intercept and are left out of the model.
+        let names = ["year", "mileage", "power"];
+        let used = (0..3)
+            .filter(|i| {
+                let first = observations[0].0[*i];
+                observations.iter().any(|(x, _)| x[*i] != first)
+            })
+            .collect::<Vec<usize>>();
+        let rows = observations
+            .iter()
+            .map(|(x, _)| used.iter().map(|i| x[*i]).collect::<Vec<f64>>())
+            .collect::<Vec<_>>();
+        let targets = observations.iter().map(|(_, y)| *y).collect::<Vec<f64>>();
+        let fit = least_squares(&rows, &targets)
+            .ok_or("Regression could not be fitted on the comparables")?;
+
+        let requested = [
+            target.year,
+            target.mileage.map(|m| m / 10_000.0),
+            target.power.map(|p| p / 100.0),
+        ];
+        let point = used
+            .iter()
+            .map(|i| {
+                requested[*i].unwrap_or_else(|| {
+                    let mut values = observations.iter().map(|(x, _)| x[*i]).collect::<Vec<_>>();
+                    values.sort_by(|a, b| a.total_cmp(b));
+                    quantile(&values, 0.5)
+                })
+            })
+            .collect::<Vec<f64>>();
+        let log_value = fit.predict(&point);
+        let spread = 1.96 * fit.residual_std;
+        Ok(Estimate {
+            strategy: self.name().to_string(),
+            estimation: log_value.exp(),
+            lower: (log_value - spread).exp(),
+            upper: (log_value + spread).exp(),
+            confidence: confidence(fit.count, 1.0 - fit.r_squared.max(0.0)),
+            count: fit.count,
+            explanation: format!(
+                "Log-linear regression on {} over {} comparables (R² {:.2})",
+                used.iter()
+                    .map(|i| names[*i])
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                fit.count,
+                fit.r_squared
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::df;
+
+    use super::*;
+
+    fn comparables() -> DataFrame {
+        df!(
+            "year" => [2016, 2017, 2018, 2018, 2019, 2019, 2020, 2020, 2021, 2022, 2017, 2018],
+            "mileage" => [150_000, 130_000, 110_000, 90_000, 80_000, 70_000, 50_000, 60_000, 30_000, 10_000, 120_000, 100_000],
+            "power" => [150, 150, 184, 150, 184, 150, 184, 150, 184, 184, 184, 150],
+            "price_in_eur" => [15_000, 17_000, 21_000, 20_000, 25_000, 23_000, 29_000, 26_000, 33_000, 38_000, 19_000, 20_500]
+        )
+        .unwrap()
+    }
+
+    fn spec() -> StatisticSearchPayload {
+        StatisticSearchPayload {
+            year: Some(2019),
+            mileage: Some(75_000),
+            power: Some(184),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_default_config_matches_file() {
+        let config = load_estimator_config("resources/estimators.yml");
+        assert_eq!(config, EstimatorConfig::default());
+    }
+
+    #[test]
+    fn test_weighted_quantile_bands() {
+        let estimator = WeightedQuantileEstimator {
+            config: EstimatorConfig::default().weighted_quantile,
+        };
+        let mut stats = HashMap::new();
+        for (name, value) in [
+            ("mean", 100.0),
+            ("median", 100.0),
+            ("quantile_66", 100.0),
+            ("quantile_75", 100.0),
+            ("quantile_80", 100.0),
+            ("max", 100.0),
+            ("rsd", 0.2),
+        ] {
+            stats.insert(name.to_string(), serde_json::json!([value]));
+        }
+        let (estimation, band) = estimator.weighted_value(&stats).unwrap();
+        assert_eq!(band, 1);
+        assert!((estimation - 100.0).abs() < 1e-9);
+
+        stats.insert("rsd".to_string(), serde_json::json!([0.5]));
+        let (estimation, band) = estimator.weighted_value(&stats).unwrap();
+        assert_eq!(band, 2);
+        assert!((estimation - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_all_estimators() {
+        let config = EstimatorConfig::default();
+        for name in ["weighted_quantile", "median", "knn", "regression"] {
+            let estimate = estimator(Some(name), &config)
+                .unwrap()
+                .estimate(&spec(), &comparables())
+                .unwrap();
+            assert_eq!(estimate.strategy, name);
+            assert!(estimate.lower <= estimate.estimation);
+            assert!(estimate.estimation <= estimate.upper);
+            assert!(estimate.estimation > 15_000.0 && estimate.estimation < 38_000.0);
+            assert!((0.0..=1.0).contains(&estimate.confidence));
+        }
+        assert!(estimator(Some("unknown"), &config).is_err());
+    }
+
+    #[test]
+    fn test_knn_prefers_similar_vehicles() {
+        let config = EstimatorConfig::default().knn;
+        let knn = KnnEstimator {
+            config: KnnConfig { k: 2, ..config },
+        };
+        let estimate = knn.estimate(&spec(), &comparables()).unwrap();
+        assert_eq!(estimate.count, 2);
+        assert!(estimate.estimation > 23_000.0 && estimate.estimation < 26_000.0);
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+
+use polars::prelude::LazyFrame;
+use serde_json::{json, Value};
+
+use crate::{
+    model::{
+        AxumAPIModel::ForecastRequest,
+        TimeSeries::{Forecast, ForecastAccuracy, ForecastMethod, Period, TrendMetric},
+    },
+    VEHICLE_STATIC_DATA,
+};
+
+use super::{
+    SeasonalityService::{default_season_length, interpolate},
+    TimeSeriesService::{labels, matching_listings, series_by_group},
+    Utils::generate_colors,
+};
+
+pub const DEFAULT_HORIZON: usize = 8;
+/// Longest horizon and holdout in periods.
+const MAX_HORIZON: usize = 104;
+/// z-value of the 95% prediction interval.
+const Z_95: f64 = 1.96;
+const GRID: [f64; 9] = [0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9];
+const METRICS: [TrendMetric; 2] = [TrendMetric::NewListings, TrendMetric::MedianPrice];
+
+/// One-step errors on the history and the state after the last value.
+struct Fit {
+    errors: Vec<f64>,
+    level: f64,
+    trend: f64,
+    season: Vec<f64>,
+}
+
+fn holt_winters_fit(values: &[f64], m: usize, alpha: f64, beta: f64, gamma: f64) -> Fit {
+    let first = values[..m].iter().sum::<f64>() / m as f64;
+    let second = values[m..2 * m].iter().sum::<f64>() / m as f64;
+    let mut level = first;
+    let mut trend = (second - first) / m as f64;
+    let mut season = values[..m].iter().map(|v| v - first).collect::<Vec<_>>();
+    let mut errors = vec![];
+    for (t, value) in values.iter().enumerate().skip(m) {
+        let s = season[t % m];
+        errors.push(value - (level + trend + s));
+        let previous = level;
+        level = alpha * (value - s) + (1.0 - alpha) * (level + trend);
+        trend = beta * (level - previous) + (1.0 - beta) * trend;
+        season[t % m] = gamma * (value - level) + (1.0 - gamma) * s;
+    }
+    // Rotate so that season[0] belongs to the first period after the history.
+    let n = values.len();
+    let season = (0..m).map(|h| season[(n + h) % m]).collect();
+    Fit {
+        errors,
+        level,
+        trend,
+        season,
+    }
+}
+
+fn holt_fit(values: &[f64], alpha: f64, beta: f64) -> Fit {
+    let mut level = values[0];
+    let mut trend = values[1] - values[0];
+    let mut errors = vec![];
+    for value in values.iter().skip(1) {
+        errors.push(value - (level + trend));
+        let previous = level;
+        level = alpha * value + (1.0 - alpha) * (level + trend);
+        trend = beta * (level - previous) + (1.0 - beta) * trend;
+    }
+    Fit {
+        errors,
+        level,
+        trend,
+        season: vec![0.0],
+    }
+}
+
+fn rmse(errors: &[f64]) -> f64 {
+    if errors.is_empty() {
+        return 0.0;
+    }
+    (errors.iter().map(|e| e * e).sum::<f64>() / errors.len() as f64).sqrt()
+}
+
+/// Fits Holt-Winters (or Holt with less than two seasons of history) with the
+/// smoothing parameters that minimise the one-step error and forecasts `horizon`
+/// periods. Intervals widen with the square root of the horizon and stay non-negative.
+pub fn forecast(values: &[f64], season_length: usize, horizon: usize) -> Result<Forecast, String> {
+    if values.len() < 4 {
+        return Err(format!(
+            "At least 4 periods are required to forecast, got {}",
+            values.len()
+        ));
+    }
+    let seasonal = season_length >= 2 && values.len() >= 2 * season_length;
+    let gammas: &[f64] = if seasonal { &GRID } else { &[0.0] };
+    let mut best: Option<(f64, f64, f64, f64, Fit)> = None;
+    for alpha in GRID {
+        for beta in GRID {
+            for gamma in gammas {
+                let fit = if seasonal {
+                    holt_winters_fit(values, season_length, alpha, beta, *gamma)
+                } else {
+                    holt_fit(values, alpha, beta)
+                };
+                let error = rmse(&fit.errors);
+                if best.as_ref().is_none_or(|b| error < b.0) {
+                    best = Some((error, alpha, beta, *gamma, fit));
+                }
+            }
+        }
+    }
+    let (error, alpha, beta, gamma, fit) = best.unwrap();
+    let forecast = (1..=horizon)
+        .map(|h| fit.level + h as f64 * fit.trend + fit.season[(h - 1) % fit.season.len()])
+        .map(|v| v.max(0.0))
+        .collect::<Vec<_>>();
+    let spread = |h: usize| Z_95 * error * (h as f64).sqrt();
+    Ok(Forecast {
+        method: if seasonal {
+            ForecastMethod::HoltWinters
+        } else {
+            ForecastMethod::Holt
+        },
+        alpha,
+        beta,
+        gamma,
+        lower: forecast
+            .iter()
+            .enumerate()
+            .map(|(h, v)| (v - spread(h + 1)).max(0.0))
+            .collect(),
+        upper: forecast
+            .iter()
+            .enumerate()
+            .map(|(h, v)| v + spread(h + 1))
+            .collect(),
+        forecast,
+        rmse: error,
+    })
+}
+
+/// Fits on all but the last `holdout` values and compares the forecast with them.
+pub fn backtest(
+    values: &[f64],
+    season_length: usize,
+    holdout: usize,
+) -> Result<ForecastAccuracy, String> {
+    if holdout == 0 || holdout >= values.len() {
+        return Err(format!("Invalid holdout of {} periods", holdout));
+    }
+    let (train, test) = values.split_at(values.len() - holdout);
+    let forecast = forecast(train, season_length, holdout)?;
+    let mae = test
+        .iter()
+        .zip(forecast.forecast.iter())
+        .map(|(a, f)| (a - f).abs())
+        .sum::<f64>()
+        / holdout as f64;
+    let percentages = test
+        .iter()
+        .zip(forecast.forecast.iter())
+        .filter(|(a, _)| **a != 0.0)
+        .map(|(a, f)| ((a - f) / a).abs() * 100.0)
+        .collect::<Vec<_>>();
+    let covered = (0..holdout)
+        .filter(|i| forecast.lower[*i] <= test[*i] && test[*i] <= forecast.upper[*i])
+        .count();
+    Ok(ForecastAccuracy {
+        holdout,
+        mae,
+        mape: (!percentages.is_empty())
+            .then(|| percentages.iter().sum::<f64>() / percentages.len() as f64),
+        coverage: covered as f64 / holdout as f64,
+    })
+}
+
+/// Forecast of the listing count and the median price of the listings in `data`
+/// matching `request.filter`. The last period is usually incomplete and is left out.
+pub fn forecast_series(
+    data: LazyFrame,
+    request: &ForecastRequest,
+) -> Result<HashMap<String, Value>, String> {
+    let period = request.period.unwrap_or(Period::Week);
+    let horizon = request.horizon.unwrap_or(DEFAULT_HORIZON).max(1);
+    let season_length = request
+        .season_length
+        .unwrap_or_else(|| default_season_length(period));
+    let holdout = request.holdout.unwrap_or(horizon);
+    if horizon > MAX_HORIZON || holdout > MAX_HORIZON {
+        return Err(format!(
+            "Horizon and holdout must be at most {} periods",
+            MAX_HORIZON
+        ));
+    }
+
+    let listings = matching_listings(data, &request.filter, None)?;
+    let mut periods = vec![];
+    let mut datasets = vec![];
+    let colors = generate_colors(METRICS.len());
+    for (metric, color) in METRICS.into_iter().zip(colors) {
+        let series = series_by_group(&listings, period, metric)?;
+        periods = series.periods[..series.periods.len() - 1].to_vec();
+        let (_, values) = &series.series[0];
+        let values = interpolate(&values[..periods.len()]).ok_or("No data found")?;
+        let forecast = forecast(&values, season_length, horizon)?;
+        let accuracy = backtest(&values, season_length, holdout).ok();
+        datasets.push(json!({
+            "label": metric,
+            "data": values,
+            "method": forecast.method,
+            "forecast": forecast.forecast,
+            "lower": forecast.lower,
+            "upper": forecast.upper,
+            "rmse": forecast.rmse,
+            "backtest": accuracy,
+            "borderColor": color,
+            "backgroundColor": color,
+        }));
+    }
+
+    let mut future = vec![];
+    let mut next = period.next(*periods.last().ok_or("No data found")?);
+    for _ in 0..horizon {
+        future.push(next);
+        next = period.next(next);
+    }
+    let mut json_map = HashMap::new();
+    json_map.insert("labels".to_string(), json!(labels(&periods, period)));
+    json_map.insert("forecastLabels".to_string(), json!(labels(&future, period)));
+    json_map.insert("datasets".to_string(), Value::Array(datasets));
+    Ok(json_map)
+}
+
+pub fn market_forecast(request: ForecastRequest) -> Result<HashMap<String, Value>, String> {
+    forecast_series(VEHICLE_STATIC_DATA.clone(), &request)
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::{frame::DataFrame, prelude::IntoLazy};
+
+    use super::*;
+
+    fn seasonal_series(periods: usize) -> Vec<f64> {
+        let season = [12.0, -4.0, -8.0, 0.0];
+        (0..periods)
+            .map(|i| 200.0 + 3.0 * i as f64 + season[i % 4])
+            .collect()
+    }
+
+    #[test]
+    fn test_holt_winters_continues_trend_and_season() {
+        let values = seasonal_series(24);
+        let result = forecast(&values, 4, 4).unwrap();
+        assert_eq!(result.method, ForecastMethod::HoltWinters);
+        let expected = seasonal_series(28)[24..].to_vec();
+        for (f, e) in result.forecast.iter().zip(expected.iter()) {
+            assert!((f - e).abs() < 1.0, "{} vs {}", f, e);
+        }
+        assert!(result.lower[0] <= result.forecast[0] && result.forecast[0] <= result.upper[0]);
+        assert!(result.upper[3] - result.lower[3] >= result.upper[0] - result.lower[0]);
+    }
+
+    #[test]
+    fn test_short_history_uses_holt() {
+        let values = [10.0, 12.0, 14.0, 16.0, 18.0];
+        let result = forecast(&values, 52, 2).unwrap();
+        assert_eq!(result.method, ForecastMethod::Holt);
+        assert!((result.forecast[0] - 20.0).abs() < 1e-6);
+        assert!((result.forecast[1] - 22.0).abs() < 1e-6);
+        assert!(forecast(&values[..3], 52, 2).is_err());
+    }
+
+    #[test]
+    fn test_backtest() {
+        let values = seasonal_series(28);
+        let accuracy = backtest(&values, 4, 4).unwrap();
+        assert_eq!(accuracy.holdout, 4);
+        assert!(accuracy.mae < 1.0);
+        assert!(accuracy.mape.unwrap() < 1.0);
+        assert!(backtest(&values, 4, 28).is_err());
+    }
+
+    #[test]
+    fn test_horizon_is_limited() {
+        let request = |horizon, holdout| ForecastRequest {
+            horizon: Some(horizon),
+            holdout: Some(holdout),
+            ..Default::default()
+        };
+        let data = DataFrame::empty().lazy();
+        for request in [request(MAX_HORIZON + 1, 4), request(4, MAX_HORIZON + 1)] {
+            let error = forecast_series(data.clone(), &request).unwrap_err();
+            assert_eq!(error, "Horizon and holdout must be at most 104 periods");
+        }
+    }
+}
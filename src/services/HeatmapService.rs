@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+
+use polars::prelude::{col, len, DataType, Expr, LazyFrame};
+
+use crate::{
+    model::{
+        AxumAPIModel::HeatmapRequest,
+        Chart::{ColorScale, Heatmap, LabelOrder},
+    },
+    PRICE_DATA,
+};
+
+use super::{
+    ChartMatrix::{default_order, floats, has_column, label_positions, order_key_column, strings},
+    LiquidityService::with_liquidity_for,
+    RenderService::parse_color,
+    TopNService::with_top_n,
+    Utils::{filter_listings, get_aggregator},
+};
+
+const VALUE: &str = "heatmap_value";
+const COUNT: &str = "heatmap_count";
+const KEY: &str = "heatmap_key";
+/// White to dark blue.
+pub const DEFAULT_COLORS: [&str; 2] = ["#f7fbff", "#08306b"];
+
+/// The values of one heatmap axis in order with the aggregate over each of them.
+struct AxisTotals {
+    labels: Vec<String>,
+    totals: Vec<Option<f64>>,
+}
+
+fn axis_totals(
+    df: LazyFrame,
+    column: &str,
+    value: &Expr,
+    order: &LabelOrder,
+) -> Result<AxisTotals, String> {
+    let key = order_key_column(order, column).filter(|key| has_column(&df, key));
+    let mut aggregators = vec![value.clone().alias(VALUE)];
+    if let Some(key) = &key {
+        aggregators.push(col(key).cast(DataType::Float64).min().alias(KEY));
+    }
+    let data = df
+        .group_by([col(column)])
+        .agg(aggregators)
+        .collect()
+        .map_err(|e| e.to_string())?;
+    let labels = strings(&data, column).map_err(|e| e.to_string())?;
+    let totals = floats(&data, VALUE).map_err(|e| e.to_string())?;
+    let keys = match key {
+        Some(_) => floats(&data, KEY).map_err(|e| e.to_string())?,
+        None => vec![None; labels.len()],
+    };
+    let sums = totals.iter().map(|t| t.unwrap_or(0.0)).collect::<Vec<_>>();
+    let positions = label_positions(&labels, &sums, &keys, order);
+    Ok(AxisTotals {
+        labels: positions.iter().map(|p| labels[*p].clone()).collect(),
+        totals: positions.iter().map(|p| totals[*p]).collect(),
+    })
+}
+
+/// `#rrggbb` at `t` (0 to 1) between the evenly spread `stops`.
+fn color_at(stops: &[(u8, u8, u8)], t: f64) -> String {
+    let segment = t.clamp(0.0, 1.0) * (stops.len() - 1) as f64;
+    let index = (segment.floor() as usize).min(stops.len() - 2);
+    let fraction = segment - index as f64;
+    let (from, to) = (stops[index], stops[index + 1]);
+    let channel = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * fraction).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        channel(from.0, to.0),
+        channel(from.1, to.1),
+        channel(from.2, to.2)
+    )
+}
+
+type CellColors = Vec<Vec<Option<String>>>;
+
+/// Colour of every value between the smallest and the largest value.
+fn color_scale(
+    values: &[Vec<Option<f64>>],
+    colors: &[String],
+) -> Result<(ColorScale, CellColors), String> {
+    let colors = if colors.is_empty() {
+        DEFAULT_COLORS.iter().map(|c| c.to_string()).collect()
+    } else {
+        colors.to_vec()
+    };
+    if colors.len() < 2 {
+        return Err("A colour scale needs at least two colours".to_string());
+    }
+    let stops = colors
+        .iter()
+        .map(|c| parse_color(c).map(|c| (c.0, c.1, c.2)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let all = values.iter().flatten().flatten();
+    let min = all.clone().copied().reduce(f64::min);
+    let max = all.copied().reduce(f64::max);
+    let cells = values
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|value| {
+                    let (value, min, max) = (value.as_ref()?, min?, max?);
+                    let t = if max > min {
+                        (value - min) / (max - min)
+                    } else {
+                        0.5
+                    };
+                    Some(color_at(&stops, t))
+                })
+                .collect()
+        })
+        .collect();
+    Ok((ColorScale { min, max, colors }, cells))
+}
+
+/// Aggregates `request.value_column` of the listings in `df` per row and column value
+/// into a dense matrix with the totals of every row and column and a colour per cell.
+pub fn heatmap(df: LazyFrame, request: &HeatmapRequest) -> Result<Heatmap, String> {
+    let (row, column) = (request.row.as_str(), request.column.as_str());
+    if row == column {
+        return Err("Rows and columns need different columns".to_string());
+    }
+    let search = &request.filter;
+    let stat_column = request
+        .value_column
+        .clone()
+        .or(search.stat_column.clone())
+        .unwrap_or("price_in_eur".to_string());
+    let aggregator = request.aggregator.as_deref().unwrap_or("count");
+    let value = get_aggregator(&stat_column, aggregator)
+        .ok_or_else(|| format!("Unknown aggregator '{}'", aggregator))?;
+    let row_order = request
+        .row_order
+        .clone()
+        .unwrap_or_else(|| default_order(row));
+    let column_order = request
+        .column_order
+        .clone()
+        .unwrap_or_else(|| default_order(column));
+
+    let df = with_liquidity_for(df, [row, column, stat_column.as_str()]);
+    let group = [row.to_string(), column.to_string()];
+    let filtered = with_top_n(filter_listings(df, search), search, &group, &stat_column)?.cache();
+    let rows = axis_totals(filtered.clone(), row, &value, &row_order)?;
+    let columns = axis_totals(filtered.clone(), column, &value, &column_order)?;
+    if rows.labels.is_empty() {
+        return Err("No data found".to_string());
+    }
+
+    let cells = filtered
+        .clone()
+        .group_by([col(row), col(column)])
+        .agg([value.clone().alias(VALUE), len().alias(COUNT)])
+        .collect()
+        .map_err(|e| e.to_string())?;
+    let position = |labels: &[String]| -> HashMap<String, usize> {
+        labels
+            .iter()
+            .enumerate()
+            .map(|(i, l)| (l.clone(), i))
+            .collect()
+    };
+    let (row_positions, column_positions) = (position(&rows.labels), position(&columns.labels));
+    let mut values = vec![vec![None; columns.labels.len()]; rows.labels.len()];
+    let mut counts = vec![vec![0; columns.labels.len()]; rows.labels.len()];
+    let cell_rows = strings(&cells, row).map_err(|e| e.to_string())?;
+    let cell_columns = strings(&cells, column).map_err(|e| e.to_string())?;
+    let cell_values = floats(&cells, VALUE).map_err(|e| e.to_string())?;
+    let cell_counts = floats(&cells, COUNT).map_err(|e| e.to_string())?;
+    for i in 0..cells.height() {
+        let (r, c) = (
+            row_positions[&cell_rows[i]],
+            column_positions[&cell_columns[i]],
+        );
+        values[r][c] = cell_values[i];
+        counts[r][c] = cell_counts[i].unwrap_or(0.0) as u32;
+    }
+
+    let total = filtered
+        .select([value.alias(VALUE)])
+        .collect()
+        .map_err(|e| e.to_string())?;
+    let total = floats(&total, VALUE).map_err(|e| e.to_string())?;
+    let (scale, colors) = color_scale(&values, &request.colors)?;
+    Ok(Heatmap {
+        row_column: row.to_string(),
+        column_column: column.to_string(),
+        rows: rows.labels,
+        columns: columns.labels,
+        values,
+        counts,
+        row_totals: rows.totals,
+        column_totals: columns.totals,
+        total: total.first().copied().flatten(),
+        colors,
+        scale: Some(scale),
+    })
+}
+
+pub fn market_heatmap(request: HeatmapRequest) -> Result<Heatmap, String> {
+    heatmap(PRICE_DATA.clone(), &request)
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::{df, prelude::IntoLazy};
+
+    use super::*;
+
+    /// No listing of 2020 with more than 50k km.
+    fn listings() -> LazyFrame {
+        df!(
+            "make" => ["BMW"; 6],
+            "year" => [2019, 2019, 2019, 2020, 2020, 2021],
+            "mileage_breakdown" => ["50k+", "0-50k", "50k+", "0-50k", "0-50k", "50k+"],
+            "mileage_breakdown_order" => [2, 1, 2, 1, 1, 2],
+            "price_in_eur" => [10_000, 20_000, 12_000, 25_000, 27_000, 30_000]
+        )
+        .unwrap()
+        .lazy()
+    }
+
+    fn request(aggregator: &str) -> HeatmapRequest {
+        HeatmapRequest {
+            row: "year".to_string(),
+            column: "mileage_breakdown".to_string(),
+            aggregator: Some(aggregator.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_heatmap() {
+        let heatmap = heatmap(listings(), &request("median")).unwrap();
+        assert_eq!(heatmap.rows, vec!["2019", "2020", "2021"]);
+        assert_eq!(heatmap.columns, vec!["0-50k", "50k+"]);
+        assert_eq!(
+            heatmap.values,
+            vec![
+                vec![Some(20_000.0), Some(11_000.0)],
+                vec![Some(26_000.0), None],
+                vec![None, Some(30_000.0)],
+            ]
+        );
+        assert_eq!(heatmap.counts, vec![vec![1, 2], vec![2, 0], vec![0, 1]]);
+        // Medians of all listings of a row or column, not sums of the cells.
+        assert_eq!(
+            heatmap.row_totals,
+            vec![Some(12_000.0), Some(26_000.0), Some(30_000.0)]
+        );
+        assert_eq!(heatmap.column_totals, vec![Some(25_000.0), Some(12_000.0)]);
+        assert_eq!(heatmap.total, Some(22_500.0));
+        let scale = heatmap.scale.unwrap();
+        assert_eq!((scale.min, scale.max), (Some(11_000.0), Some(30_000.0)));
+        assert_eq!(heatmap.colors[0][1], Some("#f7fbff".to_string()));
+        assert_eq!(heatmap.colors[2][1], Some("#08306b".to_string()));
+        assert_eq!(heatmap.colors[1][1], None);
+    }
+
+    #[test]
+    fn test_heatmap_order_and_errors() {
+        let by_count = HeatmapRequest {
+            row_order: Some(LabelOrder::ValueDesc),
+            column_order: Some(LabelOrder::Custom(vec!["50k+".to_string()])),
+            ..request("count")
+        };
+        let heatmap = heatmap(listings(), &by_count).unwrap();
+        assert_eq!(heatmap.rows, vec!["2019", "2020", "2021"]);
+        assert_eq!(heatmap.columns, vec!["50k+", "0-50k"]);
+        assert_eq!(heatmap.values[0], vec![Some(2.0), Some(1.0)]);
+
+        assert!(super::heatmap(listings(), &request("mode")).is_err());
+        let same = HeatmapRequest {
+            column: "year".to_string(),
+            ..request("count")
+        };
+        assert!(super::heatmap(listings(), &same).is_err());
+        let colors = HeatmapRequest {
+            colors: vec!["#ffffff".to_string()],
+            ..request("count")
+        };
+        assert!(super::heatmap(listings(), &colors).is_err());
+    }
+
+    #[test]
+    fn test_color_at() {
+        let stops = [(0, 0, 0), (255, 255, 255), (255, 0, 0)];
+        assert_eq!(color_at(&stops, 0.0), "#000000");
+        assert_eq!(color_at(&stops, 0.25), "#808080");
+        assert_eq!(color_at(&stops, 1.0), "#ff0000");
+    }
+}
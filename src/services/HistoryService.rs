@@ -0,0 +1,349 @@
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use chrono::NaiveDate;
+use lazy_static::lazy_static;
+use log::{error, info};
+use polars::{
+    frame::DataFrame,
+    prelude::{
+        col, concat, lit, DataType, Expr, IntoLazy, JoinArgs, JoinType, LazyFrame, ParquetWriter,
+        ScanArgsParquet, SortMultipleOptions, UnionArgs, NULL,
+    },
+};
+
+use crate::model::History::{ListingHistory, PricePoint};
+
+use super::Utils::convert_days_to_date;
+
+/// Columns kept in every snapshot.
+const SNAPSHOT_COLUMNS: [&str; 7] = [
+    "advert_id",
+    "source",
+    "make",
+    "model",
+    "price",
+    "currency",
+    "price_in_eur",
+];
+
+/// Snapshot files with their modification times.
+type Snapshots = Vec<(PathBuf, Option<SystemTime>)>;
+
+lazy_static! {
+    /// The last [`listing_summary`] and the snapshots it was computed from, so that it
+    /// is only recomputed after a snapshot was written.
+    static ref SUMMARY: Mutex<Option<(Snapshots, DataFrame)>> = Mutex::new(None);
+}
+
+fn snapshot_path(dir: &Path, date: NaiveDate) -> PathBuf {
+    dir.join(format!("snapshot-{}.parquet", date.format("%Y-%m-%d")))
+}
+
+/// Writes the prices of `listings` as the snapshot of `date`, replacing an earlier
+/// snapshot of the same day.
+pub fn write_snapshot(
+    listings: &LazyFrame,
+    dir: &Path,
+    date: NaiveDate,
+) -> Result<PathBuf, String> {
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let mut snapshot = listings
+        .clone()
+        .select(SNAPSHOT_COLUMNS.iter().map(|c| col(*c)).collect::<Vec<_>>())
+        .filter(col("advert_id").is_not_null())
+        .unique(
+            Some(vec!["advert_id".into()]),
+            polars::frame::UniqueKeepStrategy::Last,
+        )
+        .with_column(lit(date).cast(DataType::Date).alias("snapshot_date"))
+        .collect()
+        .map_err(|e| e.to_string())?;
+    let path = snapshot_path(dir, date);
+    let file = File::create(&path).map_err(|e| e.to_string())?;
+    ParquetWriter::new(file)
+        .finish(&mut snapshot)
+        .map_err(|e| e.to_string())?;
+    info!(
+        "Snapshot of {} listings written to {:?}",
+        snapshot.height(),
+        path
+    );
+    Ok(path)
+}
+
+/// The snapshot files in `dir` in date order.
+fn snapshot_paths(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut paths = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "parquet"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+fn scan_snapshots(paths: &[PathBuf]) -> Result<LazyFrame, String> {
+    let snapshots = paths
+        .iter()
+        .map(|p| LazyFrame::scan_parquet(p, ScanArgsParquet::default()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    concat(snapshots, UnionArgs::default()).map_err(|e| e.to_string())
+}
+
+/// All snapshots in `dir`, or `None` when no snapshot was written yet.
+pub fn load_history(dir: &Path) -> Result<Option<LazyFrame>, String> {
+    let paths = snapshot_paths(dir);
+    if paths.is_empty() {
+        return Ok(None);
+    }
+    scan_snapshots(&paths).map(Some)
+}
+
+/// Snapshots ordered by listing and date with the price change to the previous snapshot.
+fn with_price_changes(history: LazyFrame) -> LazyFrame {
+    history
+        .sort(
+            ["advert_id", "snapshot_date"],
+            SortMultipleOptions::new().with_order_descending(false),
+        )
+        .with_column(
+            (col("price_in_eur") - col("price_in_eur").shift(lit(1)).over([col("advert_id")]))
+                .alias("price_change"),
+        )
+}
+
+/// One row per listing with `first_seen`, `last_seen`, `first_price`, `current_price`,
+/// `price_changes` and `last_price_drop`.
+pub fn listing_summary(history: LazyFrame) -> LazyFrame {
+    with_price_changes(history)
+        .group_by([col("advert_id")])
+        .agg([
+            col("snapshot_date").min().alias("first_seen"),
+            col("snapshot_date").max().alias("last_seen"),
+            col("price_in_eur").first().alias("first_price"),
+            col("price_in_eur").last().alias("current_price"),
+            col("price_change")
+                .neq(lit(0))
+                .cast(DataType::UInt32)
+                .sum()
+                .alias("price_changes"),
+            col("snapshot_date")
+                .filter(col("price_change").lt(lit(0)))
+                .max()
+                .alias("last_price_drop"),
+        ])
+}
+
+/// The [`listing_summary`] of the snapshots in `dir`, computed again only when a
+/// snapshot was added or rewritten since the last call.
+fn cached_summary(dir: &Path) -> Result<Option<DataFrame>, String> {
+    let snapshots = snapshot_paths(dir)
+        .into_iter()
+        .map(|p| {
+            let modified = fs::metadata(&p).and_then(|m| m.modified()).ok();
+            (p, modified)
+        })
+        .collect::<Snapshots>();
+    if snapshots.is_empty() {
+        return Ok(None);
+    }
+    let mut cache = SUMMARY.lock().map_err(|e| e.to_string())?;
+    if let Some((cached, summary)) = cache.as_ref() {
+        if *cached == snapshots {
+            return Ok(Some(summary.clone()));
+        }
+    }
+    let paths = snapshots.iter().map(|(p, _)| p.clone()).collect::<Vec<_>>();
+    let summary = listing_summary(scan_snapshots(&paths)?)
+        .collect()
+        .map_err(|e| e.to_string())?;
+    *cache = Some((snapshots, summary.clone()));
+    Ok(Some(summary))
+}
+
+/// Adds the [`listing_summary`] columns to `listings`. Listings without snapshots,
+/// or all of them when none is readable, have 0 `price_changes` and no
+/// `last_price_drop`.
+pub fn with_history(listings: LazyFrame, dir: &Path) -> LazyFrame {
+    match cached_summary(dir) {
+        Ok(Some(summary)) => {
+            return listings
+                .join(
+                    summary.lazy(),
+                    [col("advert_id")],
+                    [col("advert_id")],
+                    JoinArgs::new(JoinType::Left),
+                )
+                .with_column(col("price_changes").fill_null(lit(0u32)))
+        }
+        Ok(None) => {}
+        Err(err) => error!("Listing history not available: {}", err),
+    }
+    listings.with_columns([
+        lit(NULL).cast(DataType::Date).alias("first_seen"),
+        lit(NULL).cast(DataType::Date).alias("last_seen"),
+        lit(NULL).cast(DataType::Int32).alias("first_price"),
+        lit(NULL).cast(DataType::Int32).alias("current_price"),
+        lit(0u32).alias("price_changes"),
+        lit(NULL).cast(DataType::Date).alias("last_price_drop"),
+    ])
+}
+
+/// Listings whose price dropped within the last `days` days.
+pub fn price_dropped_since(days: i64) -> Expr {
+    col("last_price_drop").gt_eq(lit(convert_days_to_date(days)))
+}
+
+pub fn listing_history(dir: &Path, advert_id: &str) -> Result<ListingHistory, String> {
+    let Some(history) = load_history(dir)? else {
+        return Err("No snapshots recorded".to_string());
+    };
+    let rows = with_price_changes(history.filter(col("advert_id").eq(lit(advert_id))))
+        .collect()
+        .map_err(|e| e.to_string())?;
+    if rows.height() == 0 {
+        return Err(format!("No history for advert {}", advert_id));
+    }
+    let dates = rows
+        .column("snapshot_date")
+        .and_then(|c| c.date().map(|d| d.as_date_iter().collect::<Vec<_>>()))
+        .map_err(|e| e.to_string())?;
+    let int = |name: &str| -> Result<Vec<Option<i32>>, String> {
+        rows.column(name)
+            .and_then(|c| c.cast(&DataType::Int32))
+            .map(|c| c.i32().unwrap().to_vec())
+            .map_err(|e| e.to_string())
+    };
+    let prices = int("price")?;
+    let prices_in_eur = int("price_in_eur")?;
+    let changes = int("price_change")?;
+    let currencies = rows
+        .column("currency")
+        .and_then(|c| c.str().map(|s| s.into_iter().collect::<Vec<_>>()))
+        .map_err(|e| e.to_string())?;
+
+    let mut history = vec![];
+    for i in 0..rows.height() {
+        if i > 0 && changes[i].unwrap_or_default() == 0 {
+            continue;
+        }
+        history.push(PricePoint {
+            date: dates[i],
+            price: prices[i],
+            currency: currencies[i].unwrap_or_default().to_string(),
+            price_in_eur: prices_in_eur[i],
+            change: if i == 0 { None } else { changes[i] },
+        });
+    }
+    let drops = (1..rows.height())
+        .filter(|i| changes[*i].is_some_and(|c| c < 0))
+        .collect::<Vec<_>>();
+    Ok(ListingHistory {
+        advert_id: advert_id.to_string(),
+        first_seen: dates.first().copied().flatten(),
+        last_seen: dates.last().copied().flatten(),
+        first_price: prices_in_eur.first().copied().flatten(),
+        current_price: prices_in_eur.last().copied().flatten(),
+        price_changes: history.len() - 1,
+        last_price_drop: drops.last().and_then(|i| dates[*i]),
+        history,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::df;
+
+    use super::*;
+
+    fn listings(prices: [i32; 2]) -> LazyFrame {
+        df!(
+            "advert_id" => ["a1", "a2"],
+            "source" => ["mobile.bg"; 2],
+            "make" => ["BMW"; 2],
+            "model" => ["320"; 2],
+            "price" => prices,
+            "currency" => ["EUR"; 2],
+            "price_in_eur" => prices
+        )
+        .unwrap()
+        .lazy()
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 9, day).unwrap()
+    }
+
+    #[test]
+    fn test_history_across_snapshots() {
+        let dir = std::env::temp_dir().join(format!("history-{}", uuid::Uuid::new_v4()));
+        write_snapshot(&listings([20_000, 15_000]), &dir, date(1)).unwrap();
+        write_snapshot(&listings([20_000, 15_000]), &dir, date(2)).unwrap();
+        write_snapshot(&listings([19_000, 15_500]), &dir, date(3)).unwrap();
+        write_snapshot(&listings([18_500, 15_500]), &dir, date(4)).unwrap();
+        // A second snapshot on the same day replaces the first one.
+        write_snapshot(&listings([18_000, 15_500]), &dir, date(4)).unwrap();
+
+        let history = listing_history(&dir, "a1").unwrap();
+        assert_eq!(history.first_seen, Some(date(1)));
+        assert_eq!(history.last_seen, Some(date(4)));
+        assert_eq!(history.price_changes, 2);
+        assert_eq!(history.current_price, Some(18_000));
+        assert_eq!(history.last_price_drop, Some(date(4)));
+        assert_eq!(
+            history.history.iter().map(|p| p.change).collect::<Vec<_>>(),
+            vec![None, Some(-1_000), Some(-1_000)]
+        );
+        assert!(listing_history(&dir, "missing").is_err());
+
+        let summary: DataFrame = listing_summary(load_history(&dir).unwrap().unwrap())
+            .sort(["advert_id"], Default::default())
+            .collect()
+            .unwrap();
+        let changes = summary.column("price_changes").unwrap().u32().unwrap();
+        assert_eq!(changes.to_vec(), vec![Some(2), Some(1)]);
+        let drops = summary.column("last_price_drop").unwrap().null_count();
+        assert_eq!(drops, 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_without_snapshots() {
+        let dir = std::env::temp_dir().join(format!("history-{}", uuid::Uuid::new_v4()));
+        assert!(load_history(&dir).unwrap().is_none());
+        let listings = with_history(listings([1, 2]), &dir)
+            .filter(price_dropped_since(7))
+            .collect()
+            .unwrap();
+        assert_eq!(listings.height(), 0);
+    }
+
+    #[test]
+    fn test_with_history() {
+        let dir = std::env::temp_dir().join(format!("history-{}", uuid::Uuid::new_v4()));
+        write_snapshot(&listings([20_000, 15_000]), &dir, date(1)).unwrap();
+        let current = df!("advert_id" => ["a1", "a2", "a3"]).unwrap().lazy();
+        let changes = |listings: LazyFrame| {
+            let df = with_history(listings, &dir)
+                .sort(["advert_id"], Default::default())
+                .collect()
+                .unwrap();
+            df.column("price_changes").unwrap().u32().unwrap().to_vec()
+        };
+        // a3 was never part of a snapshot.
+        assert_eq!(changes(current.clone()), vec![Some(0), Some(0), Some(0)]);
+
+        // The cached summary is replaced once another snapshot is written.
+        write_snapshot(&listings([19_000, 15_000]), &dir, date(2)).unwrap();
+        assert_eq!(changes(current), vec![Some(1), Some(0), Some(0)]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
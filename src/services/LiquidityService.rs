@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+
+use polars::prelude::{
+    col, len, lit, when, DataType, Expr, JoinArgs, JoinType, LazyFrame, QuantileMethod, RankMethod,
+    RankOptions, SortMultipleOptions, NULL,
+};
+use serde_json::Value;
+
+use crate::{
+    model::{
+        AxumAPIModel::{LiquidityRequest, StatisticSearchPayload},
+        Valuation::LiquidityConfig,
+    },
+    PRICE_DATA, VEHICLE_STATIC_DATA,
+};
+
+use super::{
+    EstimatorService::ESTIMATOR_CONFIG, Utils::filter_listings, VehicleService::to_generic_json,
+};
+
+/// The index is computed per make, model and year.
+pub const SEGMENT: [&str; 3] = ["make", "model", "year"];
+/// Columns added by [`with_liquidity`].
+pub const LIQUIDITY_COLUMNS: [&str; 2] = ["liquidity_index", "liquidity_band"];
+const DEFAULT_LIMIT: usize = 50;
+
+fn segment() -> Vec<Expr> {
+    SEGMENT.iter().map(|c| col(*c)).collect()
+}
+
+fn day(name: &str) -> Expr {
+    col(name).cast(DataType::Int32)
+}
+
+/// Percentile rank (0-100) among the segments; `descending` ranks small values high.
+fn percentile(expr: Expr, descending: bool) -> Expr {
+    let rank = expr
+        .clone()
+        .rank(
+            RankOptions {
+                method: RankMethod::Average,
+                descending,
+            },
+            None,
+        )
+        .cast(DataType::Float64);
+    (rank - lit(0.5)) * lit(100.0) / expr.count().cast(DataType::Float64)
+}
+
+/// Sell-through, median days on the market of the sold listings and recent sales per
+/// recent new listing of every segment in `statistic` with enough listings.
+fn market_stats(statistic: LazyFrame, config: &LiquidityConfig) -> LazyFrame {
+    let sold = col("sold_date").is_not_null();
+    let days_on_market = when(col("days_in_sale").is_not_null())
+        .then(col("days_in_sale"))
+        .otherwise(day("sold_date") - day("created_on"));
+    let from = col("latest_day") - lit(config.window_days);
+    let (last_listing, last_sale) = (day("created_on").max(), day("sold_date").max());
+    statistic
+        .with_column(
+            when(last_sale.clone().gt(last_listing.clone()))
+                .then(last_sale)
+                .otherwise(last_listing)
+                .alias("latest_day"),
+        )
+        .group_by(segment())
+        .agg([
+            len().cast(DataType::Int32).alias("listings"),
+            sold.clone().cast(DataType::Int32).sum().alias("sold"),
+            days_on_market
+                .filter(sold)
+                .median()
+                .cast(DataType::Float64)
+                .alias("median_days"),
+            day("created_on")
+                .gt(from.clone())
+                .cast(DataType::Int32)
+                .sum()
+                .alias("recent_listings"),
+            day("sold_date")
+                .gt(from)
+                .cast(DataType::Int32)
+                .sum()
+                .alias("recent_sold"),
+        ])
+        .filter(col("listings").gt_eq(lit(config.min_segment_size as i32)))
+        .with_columns([
+            (col("sold").cast(DataType::Float64) / col("listings").cast(DataType::Float64))
+                .alias("sell_through"),
+            when(col("recent_listings").gt(lit(0)))
+                .then(
+                    col("recent_sold").cast(DataType::Float64)
+                        / col("recent_listings").cast(DataType::Float64),
+                )
+                .otherwise(lit(NULL).cast(DataType::Float64))
+                .alias("demand_supply"),
+        ])
+}
+
+/// Interquartile range of the asking prices relative to their median.
+fn price_dispersion(prices: LazyFrame) -> LazyFrame {
+    let price = col("price_in_eur").cast(DataType::Float64);
+    let median = price.clone().median();
+    prices
+        .filter(col("price_in_eur").gt(lit(0)))
+        .group_by(segment())
+        .agg([when(median.clone().gt(lit(0.0)))
+            .then(
+                (price.clone().quantile(lit(0.75), QuantileMethod::Linear)
+                    - price.quantile(lit(0.25), QuantileMethod::Linear))
+                    / median,
+            )
+            .otherwise(lit(NULL).cast(DataType::Float64))
+            .alias("price_dispersion")])
+}
+
+/// One row per segment with the components of the index, `liquidity_index` (0-100)
+/// and `liquidity_band` (hot, warm or cold). Missing components are left out of the
+/// weighted mean.
+pub fn segment_liquidity(
+    statistic: LazyFrame,
+    prices: LazyFrame,
+    config: &LiquidityConfig,
+) -> LazyFrame {
+    let components = [
+        (
+            percentile(col("sell_through"), false),
+            config.sell_through_weight,
+        ),
+        (
+            percentile(col("median_days"), true),
+            config.days_on_market_weight,
+        ),
+        (
+            percentile(col("demand_supply"), false),
+            config.demand_supply_weight,
+        ),
+        (
+            percentile(col("price_dispersion"), true),
+            config.price_dispersion_weight,
+        ),
+    ];
+    let mut weighted = lit(0.0);
+    let mut weights = lit(0.0);
+    for (score, weight) in components {
+        weighted = weighted + score.clone().fill_null(lit(0.0)) * lit(weight);
+        weights = weights
+            + when(score.is_not_null())
+                .then(lit(weight))
+                .otherwise(lit(0.0));
+    }
+    let index = col("liquidity_index");
+    market_stats(statistic, config)
+        .join(
+            price_dispersion(prices),
+            segment(),
+            segment(),
+            JoinArgs::new(JoinType::Left),
+        )
+        .with_column(
+            when(weights.clone().gt(lit(0.0)))
+                .then(weighted / weights)
+                .otherwise(lit(NULL).cast(DataType::Float64))
+                .alias("liquidity_index"),
+        )
+        .with_column(
+            when(index.clone().is_null())
+                .then(lit(NULL).cast(DataType::String))
+                .when(index.clone().gt_eq(lit(config.hot)))
+                .then(lit("hot"))
+                .when(index.lt(lit(config.cold)))
+                .then(lit("cold"))
+                .otherwise(lit("warm"))
+                .alias("liquidity_band"),
+        )
+}
+
+/// Adds `liquidity_index` and `liquidity_band` of the listing's segment to `df`, so
+/// that they can be grouped and aggregated like any other column.
+pub fn with_liquidity(df: LazyFrame) -> LazyFrame {
+    let liquidity = segment_liquidity(
+        VEHICLE_STATIC_DATA.clone(),
+        PRICE_DATA.clone(),
+        &ESTIMATOR_CONFIG.liquidity,
+    )
+    .select([
+        col("make"),
+        col("model"),
+        col("year"),
+        col("liquidity_index"),
+        col("liquidity_band"),
+    ]);
+    df.join(
+        liquidity,
+        segment(),
+        segment(),
+        JoinArgs::new(JoinType::Left),
+    )
+}
+
+/// [`with_liquidity`] when one of `columns` is one of [`LIQUIDITY_COLUMNS`].
+pub fn with_liquidity_for<'a>(
+    df: LazyFrame,
+    columns: impl IntoIterator<Item = &'a str>,
+) -> LazyFrame {
+    if columns.into_iter().any(|c| LIQUIDITY_COLUMNS.contains(&c)) {
+        with_liquidity(df)
+    } else {
+        df
+    }
+}
+
+/// [`with_liquidity`] when `search` groups by or aggregates one of [`LIQUIDITY_COLUMNS`].
+pub fn with_requested_liquidity(df: LazyFrame, search: &StatisticSearchPayload) -> LazyFrame {
+    let columns = search
+        .group
+        .iter()
+        .flatten()
+        .chain(search.stat_column.iter())
+        .map(|c| c.as_str());
+    with_liquidity_for(df, columns)
+}
+
+/// Segments matching `request.filter`, the most liquid first.
+pub fn liquidity_ranking(request: LiquidityRequest) -> Result<HashMap<String, Value>, String> {
+    let segments = segment_liquidity(
+        filter_listings(VEHICLE_STATIC_DATA.clone(), &request.filter),
+        filter_listings(PRICE_DATA.clone(), &request.filter),
+        &ESTIMATOR_CONFIG.liquidity,
+    );
+    let result = segments
+        .filter(col("liquidity_index").is_not_null())
+        .sort(
+            ["liquidity_index"],
+            SortMultipleOptions::new().with_order_descending(!request.ascending),
+        )
+        .limit(request.limit.unwrap_or(DEFAULT_LIMIT) as u32)
+        .collect()
+        .map_err(|e| e.to_string())?;
+    if result.height() == 0 {
+        return Err("No data found".to_string());
+    }
+    Ok(to_generic_json(&result))
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::{
+        df,
+        prelude::{IntoLazy, NamedFrom, Series},
+    };
+
+    use super::*;
+    use crate::services::TestUtils::{date, rounded_column};
+
+    /// Golf: 4 of 5 sold within 10 days; Passat: 1 of 5 sold after 60 days.
+    fn statistic() -> LazyFrame {
+        let created = [
+            "2024-03-01",
+            "2024-03-05",
+            "2024-03-10",
+            "2024-03-20",
+            "2024-03-25",
+            "2024-01-01",
+            "2024-01-10",
+            "2024-02-01",
+            "2024-03-01",
+            "2024-03-20",
+        ];
+        let sold = [
+            Some("2024-03-08"),
+            Some("2024-03-12"),
+            Some("2024-03-20"),
+            Some("2024-03-28"),
+            None,
+            Some("2024-03-01"),
+            None,
+            None,
+            None,
+            None,
+        ];
+        let mut listings = df!(
+            "make" => ["VW"; 10],
+            "model" => ["Golf", "Golf", "Golf", "Golf", "Golf",
+                        "Passat", "Passat", "Passat", "Passat", "Passat"],
+            "year" => [2018; 10],
+            "days_in_sale" => [None::<i32>; 10]
+        )
+        .unwrap();
+        listings
+            .with_column(Series::new(
+                "created_on".into(),
+                created.iter().map(|d| date(d)).collect::<Vec<_>>(),
+            ))
+            .unwrap();
+        listings
+            .with_column(Series::new(
+                "sold_date".into(),
+                sold.iter().map(|d| d.map(date)).collect::<Vec<_>>(),
+            ))
+            .unwrap();
+        listings.lazy()
+    }
+
+    fn prices() -> LazyFrame {
+        df!(
+            "make" => ["VW"; 8],
+            "model" => ["Golf", "Golf", "Golf", "Golf", "Passat", "Passat", "Passat", "Passat"],
+            "year" => [2018; 8],
+            "price_in_eur" => [10_000, 10_500, 11_000, 11_500, 8_000, 12_000, 16_000, 20_000]
+        )
+        .unwrap()
+        .lazy()
+    }
+
+    #[test]
+    fn test_segment_liquidity() {
+        let result = segment_liquidity(statistic(), prices(), &LiquidityConfig::default())
+            .sort(["model"], Default::default())
+            .collect()
+            .unwrap();
+        assert_eq!(
+            rounded_column(&result, "sell_through"),
+            vec![Some(0.8), Some(0.2)]
+        );
+        assert_eq!(
+            rounded_column(&result, "median_days"),
+            vec![Some(7.5), Some(60.0)]
+        );
+        // Since 2024-02-29, 28 days before the latest sale: Golf 4 sold of 5 listed,
+        // Passat 1 of 2.
+        assert_eq!(
+            rounded_column(&result, "demand_supply"),
+            vec![Some(0.8), Some(0.5)]
+        );
+        let dispersion = rounded_column(&result, "price_dispersion");
+        assert!(dispersion[0].unwrap() < dispersion[1].unwrap());
+        assert_eq!(
+            rounded_column(&result, "liquidity_index"),
+            vec![Some(75.0), Some(25.0)]
+        );
+        let bands = result.column("liquidity_band").unwrap();
+        assert_eq!(bands.str().unwrap().get(0), Some("hot"));
+        assert_eq!(bands.str().unwrap().get(1), Some("cold"));
+    }
+
+    #[test]
+    fn test_small_segments_and_missing_components() {
+        let config = LiquidityConfig {
+            min_segment_size: 6,
+            ..Default::default()
+        };
+        let result = segment_liquidity(statistic(), prices(), &config)
+            .collect()
+            .unwrap();
+        assert_eq!(result.height(), 0);
+
+        // Without prices the index is the weighted mean of the other components.
+        let no_prices = prices().filter(col("price_in_eur").lt(lit(0)));
+        let result = segment_liquidity(statistic(), no_prices, &LiquidityConfig::default())
+            .sort(["model"], Default::default())
+            .collect()
+            .unwrap();
+        assert_eq!(
+            rounded_column(&result, "price_dispersion"),
+            vec![None, None]
+        );
+        assert_eq!(
+            rounded_column(&result, "liquidity_index"),
+            vec![Some(75.0), Some(25.0)]
+        );
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+
+use polars::prelude::{col, lit, when, DataType, Expr, LazyFrame, QuantileMethod, NULL};
+use serde_json::Value;
+
+use crate::{
+    model::{
+        AxumAPIModel::StatisticSearchPayload,
+        Valuation::{OutlierConfig, OutlierMethod},
+    },
+    VEHICLES_DATA,
+};
+
+use super::{
+    EstimatorService::ESTIMATOR_CONFIG, Utils::to_predicate, VehicleService::to_generic_json,
+};
+
+/// Outliers are detected relative to the listings of the same make, model and year.
+pub const SEGMENT: [&str; 3] = ["make", "model", "year"];
+
+fn median_column(column: &str) -> String {
+    format!("{}_segment_median", column)
+}
+
+/// Lower and upper fence of `column` within its segment and the spread they are
+/// derived from.
+fn fences(column: &str, method: OutlierMethod, config: &OutlierConfig) -> (Expr, Expr, Expr) {
+    let partition = SEGMENT.iter().map(|c| col(*c)).collect::<Vec<_>>();
+    let x = col(column).cast(DataType::Float64);
+    match method {
+        OutlierMethod::Iqr => {
+            let q1 = x
+                .clone()
+                .quantile(lit(0.25), QuantileMethod::Linear)
+                .over(&partition);
+            let q3 = x
+                .quantile(lit(0.75), QuantileMethod::Linear)
+                .over(&partition);
+            let iqr = q3.clone() - q1.clone();
+            let limit = iqr.clone() * lit(config.iqr_factor);
+            (q1 - limit.clone(), q3 + limit, iqr)
+        }
+        OutlierMethod::Mad => {
+            // The segment median is added by `with_outliers`; windows cannot be nested.
+            let median = col(median_column(column));
+            let deviation = x - median.clone();
+            let mad = when(deviation.clone().lt(lit(0.0)))
+                .then(lit(0.0) - deviation.clone())
+                .otherwise(deviation)
+                .median()
+                .over(&partition);
+            // 0.6745 scales the MAD to the standard deviation of a normal distribution.
+            let limit = mad.clone() * lit(config.mad_threshold / 0.6745);
+            (median.clone() - limit.clone(), median + limit, mad)
+        }
+        OutlierMethod::Zscore => {
+            let mean = x.clone().mean().over(&partition);
+            let std = x.std(1).over(&partition);
+            let limit = std.clone() * lit(config.z_threshold);
+            (mean.clone() - limit.clone(), mean + limit, std)
+        }
+    }
+}
+
+/// Adds `outlier_reason` (null for regular listings) and `outlier` to `df`.
+pub fn with_outliers(df: LazyFrame, config: &OutlierConfig, method: OutlierMethod) -> LazyFrame {
+    let partition = SEGMENT.iter().map(|c| col(*c)).collect::<Vec<_>>();
+    let checked = col(SEGMENT[0])
+        .count()
+        .over(&partition)
+        .gt_eq(lit(config.min_segment_size as u32));
+    let mut reason = when(col("price_in_eur").lt(lit(config.min_price)))
+        .then(lit(format!("price below {}", config.min_price)))
+        .when(col("mileage").gt(lit(config.max_mileage)))
+        .then(lit(format!("mileage above {}", config.max_mileage)));
+    for column in config.columns.iter() {
+        let (lower, upper, spread) = fences(column, method, config);
+        // Without any spread every value off the median would fall outside the fences.
+        let checked = checked.clone().and(spread.gt(lit(0.0)));
+        let x = col(column.as_str()).cast(DataType::Float64);
+        reason = reason
+            .when(checked.clone().and(x.clone().lt(lower)))
+            .then(lit(format!("{} below segment ({})", column, method.name())))
+            .when(checked.and(x.gt(upper)))
+            .then(lit(format!("{} above segment ({})", column, method.name())));
+    }
+    let medians = config
+        .columns
+        .iter()
+        .map(|c| median_column(c))
+        .collect::<Vec<_>>();
+    df.with_columns(
+        config
+            .columns
+            .iter()
+            .map(|c| {
+                col(c.as_str())
+                    .cast(DataType::Float64)
+                    .median()
+                    .over(&partition)
+                    .alias(median_column(c))
+            })
+            .collect::<Vec<_>>(),
+    )
+    .with_column(
+        reason
+            .otherwise(lit(NULL).cast(DataType::String))
+            .alias("outlier_reason"),
+    )
+    .with_column(col("outlier_reason").is_not_null().alias("outlier"))
+    .drop(medians)
+}
+
+/// `df` without the listings [`with_outliers`] tags.
+pub fn without_outliers(df: LazyFrame, config: &OutlierConfig, method: OutlierMethod) -> LazyFrame {
+    with_outliers(df, config, method)
+        .filter(col("outlier").not())
+        .drop(["outlier", "outlier_reason"])
+}
+
+/// Listings matching `search` that are tagged as outliers, with the reason.
+pub fn suspicious_listings(
+    search: StatisticSearchPayload,
+) -> Result<HashMap<String, Value>, String> {
+    let config = &ESTIMATOR_CONFIG.outliers;
+    let method = search.outlierMethod.unwrap_or(config.method);
+    let result = with_outliers(VEHICLES_DATA.clone(), config, method)
+        .filter(to_predicate(search).and(col("outlier")))
+        .select([
+            col("source"),
+            col("title"),
+            col("make"),
+            col("model"),
+            col("year"),
+            col("engine"),
+            col("gearbox"),
+            col("power"),
+            col("mileage"),
+            col("price_in_eur"),
+            col("url"),
+            col("outlier_reason"),
+        ])
+        .limit(100)
+        .collect()
+        .map_err(|e| e.to_string())?;
+    Ok(to_generic_json(&result))
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::{df, prelude::IntoLazy};
+
+    use super::*;
+
+    fn listings() -> LazyFrame {
+        df!(
+            "make" => ["BMW"; 10],
+            "model" => ["320"; 10],
+            "year" => [2018; 10],
+            "mileage" => [
+                110_000, 120_000, 125_000, 130_000, 115_000, 1_000_000, 122_000, 118_000, 128_000, 20_000,
+            ],
+            "price_in_eur" => [20_000, 21_000, 19_500, 20_500, 22_000, 20_000, 1, 60_000, 21_500, 20_800]
+        )
+        .unwrap()
+        .lazy()
+    }
+
+    fn reasons(method: OutlierMethod) -> Vec<Option<String>> {
+        let tagged = with_outliers(listings(), &OutlierConfig::default(), method)
+            .collect()
+            .unwrap();
+        tagged
+            .column("outlier_reason")
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_iter()
+            .map(|r| r.map(|r| r.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_iqr_outliers() {
+        let reasons = reasons(OutlierMethod::Iqr);
+        assert_eq!(reasons[5].as_deref(), Some("mileage above 800000"));
+        assert_eq!(reasons[6].as_deref(), Some("price below 500"));
+        assert_eq!(
+            reasons[7].as_deref(),
+            Some("price_in_eur above segment (iqr)")
+        );
+        assert_eq!(reasons[9].as_deref(), Some("mileage below segment (iqr)"));
+        assert_eq!(reasons.iter().filter(|r| r.is_some()).count(), 4);
+    }
+
+    #[test]
+    fn test_mad_and_zscore() {
+        let mad = reasons(OutlierMethod::Mad);
+        assert_eq!(mad[7].as_deref(), Some("price_in_eur above segment (mad)"));
+        assert_eq!(mad[0], None);
+        // The mean and deviation are pulled by the extreme values themselves, so the
+        // z-score only catches the listings beyond the fixed limits here.
+        let zscore = reasons(OutlierMethod::Zscore);
+        assert_eq!(zscore[7], None);
+        assert_eq!(zscore.iter().filter(|r| r.is_some()).count(), 2);
+    }
+
+    #[test]
+    fn test_segments_without_spread() {
+        // Most listings share one price, so the IQR and the MAD of the price are 0.
+        let listings = df!(
+            "make" => ["BMW"; 8],
+            "model" => ["320"; 8],
+            "year" => [2018; 8],
+            "mileage" => [110_000, 120_000, 125_000, 130_000, 115_000, 122_000, 118_000, 128_000],
+            "price_in_eur" => [20_000, 20_000, 20_000, 20_000, 20_000, 20_000, 20_000, 20_500]
+        )
+        .unwrap()
+        .lazy();
+        for method in [OutlierMethod::Iqr, OutlierMethod::Mad] {
+            let kept = without_outliers(listings.clone(), &OutlierConfig::default(), method)
+                .collect()
+                .unwrap();
+            assert_eq!(kept.height(), 8);
+        }
+    }
+
+    #[test]
+    fn test_small_segments_use_fixed_limits_only() {
+        let config = OutlierConfig {
+            min_segment_size: 11,
+            ..Default::default()
+        };
+        let kept = without_outliers(listings(), &config, OutlierMethod::Iqr)
+            .collect()
+            .unwrap();
+        assert_eq!(kept.height(), 8);
+        assert!(kept.column("outlier").is_err());
+        let search = |method: &str| {
+            serde_json::from_value::<StatisticSearchPayload>(
+                serde_json::json!({ "outlierMethod": method }),
+            )
+        };
+        assert_eq!(
+            search("mad").unwrap().outlierMethod,
+            Some(OutlierMethod::Mad)
+        );
+        assert!(search("sigma").is_err());
+    }
+}
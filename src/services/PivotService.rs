@@ -1,31 +1,53 @@
-use std::{
-    collections::{HashMap, HashSet},
-    vec,
-};
+use std::collections::{HashMap, HashSet};
 
 use log::{error, info};
 use polars::{
     error::PolarsError,
     frame::DataFrame,
-    prelude::{col, lit, pivot::pivot, AnyValue, IntoLazy, LazyFrame, SortMultipleOptions},
+    prelude::{all, col, IntoLazy, LazyFrame, SortMultipleOptions},
 };
 use serde_json::{json, Value};
 
 use crate::{
-    model::AxumAPIModel::PivotData,
+    model::{
+        AxumAPIModel::PivotData,
+        Chart::{ChartIntent, LabelOrder, Mark},
+    },
     services::{
-        extract_labels, process_datasets,
-        Utils::{generate_colors, to_aggregator, to_predicate},
+        ChartMatrix::{default_order, has_column, order_key_column, ChartMatrix, MatrixSpec},
+        LiquidityService::{with_liquidity_for, with_requested_liquidity},
+        TopNService::with_top_n,
+        Utils::{filter_listings, get_aggregator, to_aggregator},
+        VegaLiteService::vega_lite,
     },
     PRICE_DATA,
 };
 
+/// Listings drawn at most by a box plot or scatter chart.
+pub const MAX_CHART_POINTS: usize = 5_000;
+
 pub fn pivot_chart(pivot_request: PivotData) -> HashMap<String, Value> {
-    let df: LazyFrame = PRICE_DATA.clone();
+    if let Some(intent) = pivot_request
+        .vega_lite
+        .as_ref()
+        .filter(|i| i.mark.uses_listings())
+    {
+        return listing_chart(PRICE_DATA.clone(), &pivot_request, intent)
+            .map(spec_map)
+            .unwrap_or_else(error_map);
+    }
+    if !pivot_request.series.is_empty() {
+        return series_chart(PRICE_DATA.clone(), &pivot_request).unwrap_or_else(|err| {
+            error!("{}", err);
+            let mut error_map = HashMap::new();
+            error_map.insert("error".to_string(), json!(err));
+            error_map
+        });
+    }
     let search = pivot_request.filter.clone();
+    let df: LazyFrame = with_requested_liquidity(PRICE_DATA.clone(), &search);
     // Group by the required columns and calculate the required statistics
     info!("Payload: {:?}", search);
-    let filterConditions = to_predicate(search.clone());
     let group: Vec<String>;
     let aggregators: Vec<String>;
     if search.group.is_none() {
@@ -58,9 +80,14 @@ pub fn pivot_chart(pivot_request: PivotData) -> HashMap<String, Value> {
         .stat_column
         .clone()
         .unwrap_or("price_in_eur".to_string());
-    let aggregators = to_aggregator(aggregators.clone(), &stat_column);
-    let filtered = df
-        .with_columns(&[
+    let mut aggregators = to_aggregator(aggregators.clone(), &stat_column);
+    if let Some(column) = order_column(&pivot_request) {
+        if !group.contains(&column) && has_column(&df, &column) {
+            aggregators.push(col(&column).min().alias(&column));
+        }
+    }
+    let filtered = filter_listings(
+        df.with_columns(&[
             col("make"),
             col("model"),
             col("year"),
@@ -77,8 +104,17 @@ pub fn pivot_chart(pivot_request: PivotData) -> HashMap<String, Value> {
             col("extra_charge_in_eur"),
             col("discount"),
             col("increase"),
-        ])
-        .filter(filterConditions);
+        ]),
+        &search,
+    );
+    let filtered = match with_top_n(filtered, &search, &group, &stat_column) {
+        Ok(filtered) => filtered,
+        Err(err) => {
+            let mut error_map = HashMap::new();
+            error_map.insert("error".to_string(), json!(err));
+            return error_map;
+        }
+    };
 
     let data_aggregated = filtered.clone().group_by(by.as_slice()).agg(&aggregators);
     let result = if !search.order.is_empty() {
@@ -102,26 +138,260 @@ pub fn pivot_chart(pivot_request: PivotData) -> HashMap<String, Value> {
     to_pivot_json(&result, &pivot_request).unwrap()
 }
 
-pub fn to_pivot_json(
-    data: &DataFrame,
-    pivot_data: &PivotData, // Whether to sort pivoted columns
-) -> Result<HashMap<String, Value>, PolarsError> {
-    let mut json_map = HashMap::new();
-    let mut group_cols = vec![pivot_data.x_column.clone()];
-    info!("Pivot data: {:?}", pivot_data);
-    if let Some(pivot_column) = &pivot_data.pivot_column {
-        group_cols.push(pivot_column.clone());
+/// Aggregates every series of `pivot_data` per `x_column` (and `second_x_column`) and,
+/// with a `pivot_column`, per pivot value. Every dataset has a value, or null, for
+/// every label, so that bars and lines of different series line up.
+pub fn series_chart(
+    df: LazyFrame,
+    pivot_data: &PivotData,
+) -> Result<HashMap<String, Value>, String> {
+    let search = &pivot_data.filter;
+    let stat_column = search
+        .stat_column
+        .clone()
+        .unwrap_or("price_in_eur".to_string());
+    let mut base_cols = vec![pivot_data.x_column.clone()];
+    base_cols.extend(pivot_data.second_x_column.clone());
+    let mut keys = base_cols.clone();
+    keys.extend(pivot_data.pivot_column.clone());
+
+    let mut series_labels = HashSet::new();
+    let mut aggregators = vec![];
+    for series in pivot_data.series.iter() {
+        let label = series.label_or(&stat_column);
+        if keys.contains(&label) || !series_labels.insert(label.clone()) {
+            return Err(format!("Duplicate series label '{}'", label));
+        }
+        let aggregator = get_aggregator(series.column_or(&stat_column), &series.aggregator)
+            .ok_or_else(|| format!("Unknown aggregator '{}'", series.aggregator))?;
+        aggregators.push(aggregator.alias(label));
+    }
+    let order_column = order_column(pivot_data);
+    if let Some(column) = &order_column {
+        if !keys.contains(column) && has_column(&df, column) {
+            aggregators.push(col(column).min().alias(column));
+        }
+    }
+
+    let used = keys
+        .iter()
+        .map(|c| c.as_str())
+        .chain(pivot_data.series.iter().map(|s| s.column_or(&stat_column)));
+    let df = with_liquidity_for(df, used);
+    let (columns, descending): (Vec<_>, Vec<_>) = if search.order.is_empty() {
+        keys.iter().map(|c| (c.clone(), false)).unzip()
+    } else {
+        search
+            .order
+            .iter()
+            .map(|sort| (sort.column.clone(), !sort.asc))
+            .unzip()
+    };
+    let data = with_top_n(filter_listings(df, search), search, &keys, &stat_column)?
+        .group_by(keys.iter().map(col).collect::<Vec<_>>())
+        .agg(aggregators)
+        .sort(
+            columns,
+            SortMultipleOptions::new()
+                .with_order_descending_multi(descending)
+                .with_nulls_last(true),
+        )
+        .collect()
+        .map_err(|e| e.to_string())?;
+    let series_cols = pivot_data.pivot_column.iter().cloned().collect::<Vec<_>>();
+    let mut matrix = ChartMatrix::default();
+    for series in pivot_data.series.iter() {
+        let label = series.label_or(&stat_column);
+        let mut series_matrix = ChartMatrix::from_rows(
+            &data,
+            &MatrixSpec {
+                labels: &base_cols,
+                series: &series_cols,
+                value: &label,
+                name: Some(&label),
+                order_column: order_column.as_deref().filter(|c| data.column(c).is_ok()),
+            },
+        )
+        .map_err(|e| e.to_string())?;
+        for dataset in series_matrix.series.iter_mut() {
+            dataset
+                .extra
+                .insert("type".to_string(), json!(series.chart_type));
+            dataset
+                .extra
+                .insert("yAxisID".to_string(), json!(series.axis.id()));
+        }
+        matrix = matrix.merge(series_matrix);
     }
+    Ok(finish(matrix, pivot_data))
+}
+
+/// Columns whose values make the labels.
+fn label_columns(pivot_data: &PivotData) -> Vec<String> {
+    let mut columns = vec![pivot_data.x_column.clone()];
+    columns.extend(pivot_data.second_x_column.clone());
+    columns
+}
 
-    if let Some(second_x_column) = &pivot_data.second_x_column {
-        group_cols.push(second_x_column.clone());
+/// The requested label order; breakdown columns follow their buckets and without a
+/// `label_order` a sort `order` of the filter is kept as it is.
+fn label_order(pivot_data: &PivotData) -> Option<LabelOrder> {
+    match &pivot_data.label_order {
+        Some(order) => Some(order.clone()),
+        None if !pivot_data.filter.order.is_empty() => None,
+        None => Some(default_order(&pivot_data.x_column)),
     }
+}
+
+/// Column that has to be aggregated for the label order.
+fn order_column(pivot_data: &PivotData) -> Option<String> {
+    order_key_column(&label_order(pivot_data)?, &pivot_data.x_column)
+}
 
+fn finish(mut matrix: ChartMatrix, pivot_data: &PivotData) -> HashMap<String, Value> {
+    let order = label_order(pivot_data);
+    if let Some(order) = &order {
+        matrix.order_labels(order);
+    }
+    // The bucket of the remaining values goes last unless it is placed explicitly.
+    if !matches!(order, Some(LabelOrder::Custom(_))) {
+        for top in pivot_data.filter.topN.iter() {
+            matrix.move_last(top.other_label_or());
+        }
+    }
+    // Filled first so that the transforms see the values the chart shows, e.g. `diff`
+    // drops to 0 at a gap, and again for the values they leave unknown.
+    matrix.fill(pivot_data.fill);
+    if let Err(err) = matrix.transform(&pivot_data.transforms) {
+        return error_map(err);
+    }
+    matrix.fill(pivot_data.fill);
+    match &pivot_data.vega_lite {
+        Some(intent) => matrix_vega_lite(&matrix, pivot_data, intent)
+            .map(spec_map)
+            .unwrap_or_else(error_map),
+        None => matrix.to_json(),
+    }
+}
+
+fn error_map(err: String) -> HashMap<String, Value> {
+    error!("{}", err);
+    HashMap::from([("error".to_string(), json!(err))])
+}
+
+fn spec_map(spec: Value) -> HashMap<String, Value> {
+    match spec {
+        Value::Object(spec) => spec.into_iter().collect(),
+        _ => HashMap::new(),
+    }
+}
+
+/// Vega-Lite chart of the finished matrix: one row per label (named after the label
+/// columns), dataset (`series`, or the pivot column) and value (`value`, or `y_function`).
+fn matrix_vega_lite(
+    matrix: &ChartMatrix,
+    pivot_data: &PivotData,
+    intent: &ChartIntent,
+) -> Result<Value, String> {
+    let label = label_columns(pivot_data).join("_");
+    let series = pivot_data
+        .pivot_column
+        .clone()
+        .unwrap_or("series".to_string());
+    let value = if pivot_data.series.is_empty() {
+        pivot_data.y_function.clone()
+    } else {
+        "value".to_string()
+    };
+    let data = matrix
+        .to_frame(&label, &series, &value)
+        .map_err(|e| e.to_string())?;
+    let several = matrix.series.len() > 1;
+    let intent = match intent.mark {
+        Mark::Heatmap => ChartIntent {
+            x: intent.x.clone().or(Some(label)),
+            y: intent.y.clone().or(Some(series)),
+            color: intent.color.clone().or(Some(value)),
+            ..intent.clone()
+        },
+        _ => ChartIntent {
+            x: intent.x.clone().or(Some(label)),
+            y: intent.y.clone().or(Some(value)),
+            color: intent.color.clone().or(Some(series).filter(|_| several)),
+            ..intent.clone()
+        },
+    };
+    vega_lite(&data, &intent)
+}
+
+/// Every n-th row of `data`, so that at most `max` rows spread evenly over it remain.
+fn sample_evenly(data: DataFrame, max: usize) -> Result<DataFrame, String> {
+    if data.height() <= max {
+        return Ok(data);
+    }
+    let n = data.height().div_ceil(max);
+    data.lazy()
+        .select([all().gather_every(n, 0)])
+        .collect()
+        .map_err(|e| e.to_string())
+}
+
+/// Vega-Lite box plot or scatter chart of at most [`MAX_CHART_POINTS`] filtered listings:
+/// `y` (default `y_column`, `stat_column` or `price_in_eur`) per `x_column`, coloured by
+/// the `pivot_column`.
+fn listing_chart(
+    df: LazyFrame,
+    pivot_data: &PivotData,
+    intent: &ChartIntent,
+) -> Result<Value, String> {
+    let search = &pivot_data.filter;
+    let y = [
+        intent.y.clone(),
+        Some(pivot_data.y_column.clone()).filter(|c| !c.is_empty()),
+        search.stat_column.clone(),
+    ]
+    .into_iter()
+    .flatten()
+    .next()
+    .unwrap_or("price_in_eur".to_string());
+    let intent = ChartIntent {
+        x: intent.x.clone().or(Some(pivot_data.x_column.clone())),
+        y: Some(y),
+        color: intent.color.clone().or(pivot_data.pivot_column.clone()),
+        ..intent.clone()
+    };
+    let mut columns = vec![];
+    for column in [&intent.x, &intent.y, &intent.color, &intent.size]
+        .into_iter()
+        .flatten()
+    {
+        if !columns.contains(column) {
+            columns.push(column.clone());
+        }
+    }
+    let df = with_liquidity_for(df, columns.iter().map(|c| c.as_str()));
+    let data = filter_listings(df, search)
+        .select(columns.iter().map(col).collect::<Vec<_>>())
+        .collect()
+        .map_err(|e| e.to_string())?;
+    vega_lite(&sample_evenly(data, MAX_CHART_POINTS)?, &intent)
+}
+
+/// Chart.js answer of the aggregated rows in `data`: the labels are the values of
+/// `x_column` (and `second_x_column`), the datasets the values of `y_function` per
+/// `pivot_column` value or, without one, per value of the other group columns.
+pub fn to_pivot_json(
+    data: &DataFrame,
+    pivot_data: &PivotData,
+) -> Result<HashMap<String, Value>, PolarsError> {
+    let mut json_map = HashMap::new();
+    let base_cols = label_columns(pivot_data);
+    let mut group_cols = base_cols.clone();
+    group_cols.extend(pivot_data.pivot_column.clone());
     info!("Group columns: {:?}", group_cols);
 
     // Ensure all required columns exist
-
-    for col in &group_cols {
+    for col in group_cols.iter().chain([&pivot_data.y_function]) {
         if data.column(col).is_err() {
             json_map.insert(
                 "error".to_string(),
@@ -132,186 +402,266 @@ pub fn to_pivot_json(
         }
     }
 
-    let grouped_ok = data.clone().lazy().collect();
-    let grouped = match grouped_ok {
-        Ok(grouped) => {
-            info!("Grouped data: {:?}", grouped);
-            grouped
-        }
-        Err(e) => {
-            error!("Error grouping data: {}", e);
-            json_map.insert("error".to_string(), json!(format!("{}", e)));
-            return Ok(json_map);
-        }
+    let series_cols = match &pivot_data.pivot_column {
+        Some(pivot_column) => vec![pivot_column.clone()],
+        None => pivot_data
+            .filter
+            .group
+            .iter()
+            .flatten()
+            .filter(|c| !base_cols.contains(c))
+            .cloned()
+            .collect(),
     };
+    let order_column = order_column(pivot_data);
+    let matrix = ChartMatrix::from_rows(
+        data,
+        &MatrixSpec {
+            labels: &base_cols,
+            series: &series_cols,
+            value: &pivot_data.y_function,
+            name: None,
+            order_column: order_column.as_deref().filter(|c| data.column(c).is_ok()),
+        },
+    )?;
+    Ok(finish(matrix, pivot_data))
+}
 
-    // ------------------------------------------------------------------
-    // 3) Perform Pivot
-    // ------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use polars::{df, prelude::IntoLazy};
 
-    let base_cols = if let Some(second_x_col) = &pivot_data.second_x_column {
-        vec![pivot_data.x_column.clone(), second_x_col.clone()]
-    } else {
-        vec![pivot_data.x_column.clone()]
+    use crate::model::{
+        AxumAPIModel::StatisticSearchPayload,
+        Chart::{Axis, ChartType, GapFill, PivotSeries, Transform},
     };
+    use crate::services::VegaLiteService::VEGA_LITE_SCHEMA;
 
-    let pivoted = if let Some(pivot_column) = &pivot_data.pivot_column {
-        info!("Pivoting data...");
-        info!("Grouped data: {:?}", &grouped);
-        info!("Base columns: {:?}", base_cols);
-        info!("Pivot column: {:?}", &pivot_column);
-        info!("Y function: {:?}", &pivot_data.y_function);
-        pivot(
-            &grouped,
-            vec![pivot_column],                    // Pivot column
-            Some(base_cols.clone()),               // Index columns
-            Some([pivot_data.y_function.clone()]), // Values column
-            true,                                  // Sort pivoted columns
-            Some(polars::prelude::Expr::sum(col(&pivot_data.y_function))), // No additional aggregation needed
-            None,                                                          // No separator
-        )?
-    } else {
-        info!("No pivot column specified. Data set: {:?}", &grouped);
-        grouped
-    };
-    info!("Pivoted data: {:?}", &pivoted);
-    let filled_pivoted = pivoted.lazy().fill_null(lit(0)).collect()?;
-    info!("Pivoted data: {:?}", &filled_pivoted);
-    // ------------------------------------------------------------------
-    // 4) Extract Labels and Datasets
-    // ------------------------------------------------------------------
-
-    let labels = extract_labels(&filled_pivoted, &pivot_data.x_column)?;
-
-    let datasets = if pivot_data.pivot_column.is_none() {
-        let columns = if let Some(groups) = pivot_data.filter.group.clone() {
-            groups
-                .iter()
-                .filter(|col| col.as_str() != pivot_data.x_column)
-                .cloned()
-                .collect::<Vec<String>>()
-        } else {
-            vec![]
-        };
+    use super::*;
 
-        process_datasets_non_pivoted(
-            &filled_pivoted,
-            &pivot_data.x_column,
-            &pivot_data.y_function,
-            columns.iter().map(|s| s.as_str()).collect::<Vec<&str>>(),
-            generate_colors,
-        )?
-    } else {
-        process_datasets(&filled_pivoted, &base_cols, generate_colors)?
-    };
-    info!("Datasets: {:?}", datasets);
-    info!("Labels: {:?}", labels);
-    // ------------------------------------------------------------------
-    // 5) Build Final JSON
-    // ------------------------------------------------------------------
-    json_map.insert("labels".to_string(), json!(labels));
-    json_map.insert("datasets".to_string(), Value::Array(datasets));
-
-    Ok(json_map)
-}
+    fn listings() -> LazyFrame {
+        df!(
+            "make" => ["BMW", "BMW", "BMW", "Audi", "Audi"],
+            "year" => [2019, 2019, 2020, 2020, 2021],
+            "engine" => ["Diesel", "Petrol", "Diesel", "Diesel", "Petrol"],
+            "price_in_eur" => [20_000, 22_000, 25_000, 24_000, 30_000]
+        )
+        .unwrap()
+        .lazy()
+    }
 
-pub fn process_datasets_non_pivoted(
-    df: &DataFrame,
-    label_col: &str,          // Column for labels (e.g., years)
-    value_col: &str,          // Column for count/values
-    grouping_cols: Vec<&str>, // Grouping columns (can be empty for no grouping)
-    generate_colors: impl Fn(usize) -> Vec<String>,
-) -> Result<Vec<Value>, PolarsError> {
-    let unique_groups: HashSet<String> = (0..df.height())
-        .map(|row_idx| {
-            grouping_cols
-                .iter()
-                .map(|col| {
-                    df.column(col).map(|series| {
-                        series
-                            .get(row_idx)
-                            .unwrap_or(polars::prelude::AnyValue::String("Unknown"))
-                            .to_string()
-                    })
-                })
-                .collect::<Result<Vec<_>, PolarsError>>()
-                .map(|vec| vec.join(", "))
-        })
-        .collect::<Result<HashSet<_>, PolarsError>>()?;
-
-    // Generate colors based on the number of unique groups
-    let colors = generate_colors(unique_groups.len());
-
-    let mut datasets_map: HashMap<String, Vec<u32>> = HashMap::new();
-    let mut labels = HashSet::new();
-
-    // Iterate through the rows of the DataFrame
-    info!("Grouping columns: {:?}", grouping_cols);
-    info!("Unique groups: {:?}", unique_groups);
-    info!("Number of rows: {}", df.height());
-    for row_idx in 0..df.height() {
-        // Get the label (x-axis value)
-        let label = df
-            .column(label_col)?
-            .get(row_idx)
-            .unwrap_or(AnyValue::String("Unknown"))
-            .to_string();
-        if label == "Unknown" {
-            error!("Unknown label found in row: {}", row_idx);
-        } else {
-            labels.insert(label.clone());
+    fn series(aggregator: &str, chart_type: ChartType, axis: Axis) -> PivotSeries {
+        PivotSeries {
+            aggregator: aggregator.to_string(),
+            chart_type,
+            axis,
+            ..Default::default()
         }
+    }
 
-        // Get the grouping key by concatenating all grouping column values
-        let group_key = grouping_cols
-            .iter()
-            .map(|col| {
-                df.column(col)
-                    .map(|series| {
-                        series
-                            .get(row_idx)
-                            .unwrap_or(polars::prelude::AnyValue::String("Unknown"))
-                            .to_string()
-                    })
-                    .unwrap_or_else(|_| "Unknown".to_string())
-            })
-            .collect::<Vec<_>>()
-            .join(", ");
-        info!("Group key: {:?}", group_key);
-        // Get the value for this row
-        let value = match df.column(value_col)?.get(row_idx) {
-            Ok(AnyValue::Float64(val)) => val as u32,
-            Ok(AnyValue::Float32(val)) => val as u32,
-            Ok(AnyValue::Int64(val)) => val as u32,
-            Ok(AnyValue::Int32(val)) => val as u32,
-            Ok(AnyValue::UInt64(val)) => val as u32,
-            Ok(AnyValue::UInt32(val)) => val,
-            _ => 0,
-        };
-        if value == 0 {
-            error!("Zero value found in row: {}", row_idx);
+    fn request(pivot_column: Option<&str>) -> PivotData {
+        PivotData {
+            x_column: "year".to_string(),
+            pivot_column: pivot_column.map(|c| c.to_string()),
+            filter: StatisticSearchPayload::default(),
+            series: vec![
+                series("count", ChartType::Bar, Axis::Left),
+                series("median", ChartType::Line, Axis::Right),
+            ],
+            ..Default::default()
         }
-        // Add the label to the label set
+    }
 
-        // Update the dataset for the group
-        datasets_map.entry(group_key).or_default().push(value);
+    #[test]
+    fn test_series_chart() {
+        let chart = series_chart(listings(), &request(None)).unwrap();
+        assert_eq!(chart["labels"], json!(["2019", "2020", "2021"]));
+        let datasets = chart["datasets"].as_array().unwrap();
+        assert_eq!(datasets[0]["label"], "count_price_in_eur");
+        assert_eq!(datasets[0]["data"], json!([2, 2, 1]));
+        assert_eq!(datasets[0]["type"], "bar");
+        assert_eq!(datasets[1]["data"], json!([21_000.0, 24_500.0, 30_000.0]));
+        assert_eq!(datasets[1]["type"], "line");
+        assert_eq!(datasets[1]["yAxisID"], "y1");
     }
 
-    info!("Labels: {:?}, count: {}", labels, labels.len());
+    #[test]
+    fn test_series_chart_by_pivot_column() {
+        let chart = series_chart(listings(), &request(Some("make"))).unwrap();
+        let datasets = chart["datasets"].as_array().unwrap();
+        assert_eq!(datasets.len(), 4);
+        assert_eq!(datasets[0]["label"], "count_price_in_eur - Audi");
+        // Audi has no listing of 2019, BMW none of 2021.
+        assert_eq!(datasets[0]["data"], json!([0, 1, 1]));
+        assert_eq!(datasets[1]["label"], "count_price_in_eur - BMW");
+        assert_eq!(datasets[1]["data"], json!([2, 1, 0]));
 
-    // Build the datasets
-    let datasets: Vec<Value> = datasets_map
-        .into_iter()
-        .enumerate()
-        .map(|(idx, (label, data))| {
-            json!({
-                "label": label,
-                "data": data,
-                "backgroundColor": colors[idx]
-            })
-        })
-        .collect();
-
-    // Return the result
-    Ok(datasets)
+        let gaps = PivotData {
+            fill: GapFill::Null,
+            ..request(Some("make"))
+        };
+        let chart = series_chart(listings(), &gaps).unwrap();
+        assert_eq!(chart["datasets"][0]["data"], json!([null, 1, 1]));
+
+        let mut duplicate = request(None);
+        duplicate
+            .series
+            .push(series("count", ChartType::Line, Axis::Left));
+        assert!(series_chart(listings(), &duplicate).is_err());
+    }
+
+    #[test]
+    fn test_series_chart_shares() {
+        let shares = PivotData {
+            series: vec![series("count", ChartType::Bar, Axis::Left)],
+            transforms: vec![Transform::PercentOfRow],
+            ..request(Some("make"))
+        };
+        let chart = series_chart(listings(), &shares).unwrap();
+        let datasets = chart["datasets"].as_array().unwrap();
+        assert_eq!(datasets[0]["data"], json!([0.0, 50.0, 100.0]));
+        assert_eq!(datasets[1]["data"], json!([100.0, 50.0, 0.0]));
+
+        let unknown = PivotData {
+            transforms: vec![Transform::IndexToBase(Some("2018".to_string()))],
+            ..request(None)
+        };
+        let chart = series_chart(listings(), &unknown).unwrap();
+        assert_eq!(chart["error"], "Unknown base label '2018'");
+    }
+
+    #[test]
+    fn test_series_chart_vega_lite() {
+        let counts = PivotData {
+            series: vec![series("count", ChartType::Bar, Axis::Left)],
+            vega_lite: Some(ChartIntent {
+                title: Some("Listings".to_string()),
+                ..Default::default()
+            }),
+            fill: GapFill::Null,
+            ..request(Some("make"))
+        };
+        let spec = series_chart(listings(), &counts).unwrap();
+        assert_eq!(spec["$schema"], VEGA_LITE_SCHEMA);
+        assert_eq!(spec["title"], "Listings");
+        assert_eq!(
+            spec["data"]["values"][0],
+            json!({ "year": "2019", "make": "count_price_in_eur - BMW", "value": 2 })
+        );
+        assert_eq!(spec["encoding"]["color"]["field"], "make");
+
+        let heatmap = PivotData {
+            vega_lite: Some(ChartIntent {
+                mark: Mark::Heatmap,
+                ..Default::default()
+            }),
+            ..counts
+        };
+        let spec = series_chart(listings(), &heatmap).unwrap();
+        assert_eq!(spec["encoding"]["y"]["field"], "make");
+        assert_eq!(spec["encoding"]["color"]["field"], "value");
+    }
+
+    #[test]
+    fn test_listing_chart() {
+        let boxplot = ChartIntent {
+            mark: Mark::Boxplot,
+            ..Default::default()
+        };
+        let spec = listing_chart(listings(), &request(Some("make")), &boxplot).unwrap();
+        assert_eq!(spec["mark"]["type"], "boxplot");
+        assert_eq!(spec["data"]["values"].as_array().unwrap().len(), 5);
+        assert_eq!(
+            spec["data"]["values"][0],
+            json!({ "year": 2019, "price_in_eur": 20_000, "make": "BMW" })
+        );
+
+        let data = df!("price_in_eur" => (0..12).collect::<Vec<i32>>()).unwrap();
+        let sampled = sample_evenly(data, 5).unwrap();
+        let prices = sampled.column("price_in_eur").unwrap().i32().unwrap();
+        assert_eq!(prices.to_vec(), vec![Some(0), Some(3), Some(6), Some(9)]);
+    }
+
+    #[test]
+    fn test_to_pivot_json_aligns_labels() {
+        // Aggregated rows in no particular order, BMW has no 2021 and Audi no 2019.
+        let rows = df!(
+            "year" => [2021, 2019, 2020, 2020],
+            "make" => ["Audi", "BMW", "BMW", "Audi"],
+            "median" => [30_000.5, 21_000.0, 25_000.0, -24_000.0]
+        )
+        .unwrap();
+        let pivot_data = PivotData {
+            x_column: "year".to_string(),
+            y_function: "median".to_string(),
+            pivot_column: Some("make".to_string()),
+            ..Default::default()
+        };
+        let chart = to_pivot_json(&rows, &pivot_data).unwrap();
+        assert_eq!(chart["labels"], json!(["2019", "2020", "2021"]));
+        let datasets = chart["datasets"].as_array().unwrap();
+        assert_eq!(datasets[0]["label"], "Audi");
+        assert_eq!(datasets[0]["data"], json!([0.0, -24_000.0, 30_000.5]));
+        assert_eq!(datasets[1]["data"], json!([21_000.0, 25_000.0, 0.0]));
+
+        let gaps = PivotData {
+            fill: GapFill::Null,
+            ..pivot_data.clone()
+        };
+        let chart = to_pivot_json(&rows, &gaps).unwrap();
+        assert_eq!(
+            chart["datasets"][0]["data"],
+            json!([null, -24_000.0, 30_000.5])
+        );
+
+        let filled = PivotData {
+            label_order: Some(LabelOrder::Custom(vec!["2021".to_string()])),
+            ..pivot_data.clone()
+        };
+        let chart = to_pivot_json(&rows, &filled).unwrap();
+        assert_eq!(chart["labels"], json!(["2021", "2019", "2020"]));
+        assert_eq!(
+            chart["datasets"][1]["data"],
+            json!([0.0, 21_000.0, 25_000.0])
+        );
+
+        // The gaps are filled before the transforms, so BMW's 2021 gap is a drop to 0.
+        let diff = |fill| PivotData {
+            fill,
+            transforms: vec![Transform::Diff],
+            ..pivot_data.clone()
+        };
+        let chart = to_pivot_json(&rows, &diff(GapFill::Zero)).unwrap();
+        assert_eq!(
+            chart["datasets"][0]["data"],
+            json!([0.0, -24_000.0, 54_000.5])
+        );
+        assert_eq!(
+            chart["datasets"][1]["data"],
+            json!([0.0, 4_000.0, -25_000.0])
+        );
+        let chart = to_pivot_json(&rows, &diff(GapFill::Null)).unwrap();
+        assert_eq!(chart["datasets"][0]["data"], json!([null, null, 54_000.5]));
+        assert_eq!(chart["datasets"][1]["data"], json!([null, 4_000.0, null]));
+    }
+
+    #[test]
+    fn test_breakdown_labels_follow_buckets() {
+        let data = df!(
+            "make" => ["BMW"; 4],
+            "mileage_breakdown" => ["100-150k", "0-50k", "50-100k", "0-50k"],
+            "mileage_breakdown_order" => [3, 1, 2, 1],
+            "price_in_eur" => [10_000, 20_000, 15_000, 22_000]
+        )
+        .unwrap()
+        .lazy();
+        let pivot_data = PivotData {
+            x_column: "mileage_breakdown".to_string(),
+            series: vec![series("count", ChartType::Bar, Axis::Left)],
+            ..Default::default()
+        };
+        let chart = series_chart(data, &pivot_data).unwrap();
+        assert_eq!(chart["labels"], json!(["0-50k", "50-100k", "100-150k"]));
+        assert_eq!(chart["datasets"][0]["data"], json!([2, 1, 1]));
+    }
 }
@@ -1,39 +1,34 @@
 use std::collections::HashMap;
 
-use log::info;
-use polars::prelude::{col, lit};
+use log::{error, info};
+use polars::{frame::DataFrame, prelude::LazyFrame};
 use serde_json::{json, Value};
 
-use crate::{model::AxumAPIModel::StatisticSearchPayload, ESTIMATED_PRICES_DATA};
-
-use super::{Utils::to_predicate, VehicleService::to_generic_json};
-
-const MILEAGE: [(i32, i32); 8] = [
-    (0, 20000),
-    (20001, 40000),
-    (40001, 60000),
-    (60001, 80000),
-    (80001, 100000),
-    (100001, 120000),
-    (120001, 150000),
-    (150001, 999999),
-];
-
-const POWER: [(i32, i32); 10] = [
-    (0, 90),
-    (91, 130),
-    (131, 150),
-    (151, 200),
-    (201, 252),
-    (253, 303),
-    (304, 358),
-    (359, 404),
-    (405, 454),
-    (455, 9999),
-];
+use crate::{
+    model::{
+        AxumAPIModel::StatisticSearchPayload,
+        Valuation::{Relaxation, RelaxationConfig, RelaxationStep},
+    },
+    ESTIMATED_PRICES_DATA, VEHICLES_DATA,
+};
+
+use super::{
+    BucketService::ranges,
+    ComparableService::similar_listings,
+    EstimatorService::{comparable_statistics, estimator, ESTIMATOR_CONFIG},
+    OutlierService::without_outliers,
+    PriceGapService::sold_correction,
+    Utils::{family_token, to_predicate},
+};
 
 pub fn calculateStatistic(filter: StatisticSearchPayload) -> HashMap<String, Value> {
-    let result = calculate(filter);
+    let spec = filter;
+    let comparable_set = match comparables(&spec, &ESTIMATED_PRICES_DATA) {
+        Ok(comparable_set) => comparable_set,
+        Err(err) => return error_response(&err),
+    };
+    let comparables = &comparable_set.data;
+    let result = comparable_statistics(comparables).unwrap();
     let rsd = result.get("rsd").unwrap().as_array().unwrap();
     let mean = result.get("mean").unwrap().as_array().unwrap();
     let median = result.get("median").unwrap().as_array().unwrap();
@@ -46,7 +41,7 @@ pub fn calculateStatistic(filter: StatisticSearchPayload) -> HashMap<String, Val
 
     // Converting to f64
     let count = count[0].as_i64().unwrap();
-    let rsd = rsd[0].as_f64().unwrap();
+    let rsd = rsd[0].as_f64().unwrap_or_default();
     let mean = mean[0].as_f64().unwrap();
     let median = median[0].as_f64().unwrap();
     let q66 = quantile_66[0].as_f64().unwrap();
@@ -54,8 +49,25 @@ pub fn calculateStatistic(filter: StatisticSearchPayload) -> HashMap<String, Val
     let q80 = quantile_80[0].as_f64().unwrap();
     let q85 = quantile_85[0].as_f64().unwrap();
     let max = max[0].as_f64().unwrap();
-    let estimation = calculate_estimated_value(&result);
+    let mut estimate = match estimator(spec.estimator.as_deref(), &ESTIMATOR_CONFIG)
+        .and_then(|e| e.estimate(&spec, comparables))
+    {
+        Ok(estimate) => estimate,
+        Err(err) => return error_response(&err),
+    };
     let mut response = HashMap::new();
+    if spec.soldCorrection.unwrap_or_default() {
+        match sold_correction(&spec) {
+            Ok(Some(correction)) => {
+                estimate.estimation *= correction.factor;
+                estimate.lower *= correction.factor;
+                estimate.upper *= correction.factor;
+                response.insert("correction".to_string(), json!(correction));
+            }
+            Ok(None) => info!("Too few sold listings for a price correction"),
+            Err(err) => error!("Price correction is not available: {}", err),
+        }
+    }
     response.insert("rsd".to_string(), json!((rsd * 100.0).round() as i32));
     response.insert("count".to_string(), json!(count));
     response.insert("mean".to_string(), json!(mean.round() as i32));
@@ -65,200 +77,308 @@ pub fn calculateStatistic(filter: StatisticSearchPayload) -> HashMap<String, Val
     response.insert("quantile_80".to_string(), json!(q80.round() as i32));
     response.insert("quantile_85".to_string(), json!(q85.round() as i32));
     response.insert("max".to_string(), json!(max.round() as i32));
-    response.insert("estimation".to_string(), json!(estimation.round() as i32));
+    response.insert(
+        "estimation".to_string(),
+        json!(estimate.estimation.round() as i32),
+    );
+    response.insert("estimator".to_string(), json!(estimate.strategy));
+    response.insert("lower".to_string(), json!(estimate.lower.round() as i32));
+    response.insert("upper".to_string(), json!(estimate.upper.round() as i32));
+    response.insert("confidence".to_string(), json!(estimate.confidence));
+    response.insert("explanation".to_string(), json!(estimate.explanation));
+    response.insert("relaxation".to_string(), json!(comparable_set.relaxation));
+    if let Some(limit) = spec.comparables {
+        match similar_listings(&spec, &comparable_set.filter, &VEHICLES_DATA, limit) {
+            Ok(listings) => {
+                response.insert("comparables".to_string(), json!(listings));
+            }
+            Err(err) => error!("Comparable listings are not available: {}", err),
+        }
+    }
     response
 }
 
+fn error_response(message: &str) -> HashMap<String, Value> {
+    let mut error = HashMap::new();
+    error.insert("error".to_string(), json!(message));
+    error
+}
+
+/// Listings the calculator compares a vehicle against, together with the
+/// filter that selected them and how it was relaxed from the request.
+pub struct ComparableSet {
+    pub data: DataFrame,
+    pub filter: StatisticSearchPayload,
+    pub relaxation: Vec<RelaxationStep>,
+}
+
+/// Listings of `vehicles` the calculator compares `spec` against.
+pub fn comparables(
+    spec: &StatisticSearchPayload,
+    vehicles: &LazyFrame,
+) -> Result<ComparableSet, String> {
+    let vehicles = &if spec.excludeOutliers.unwrap_or_default() {
+        let config = &ESTIMATOR_CONFIG.outliers;
+        without_outliers(
+            vehicles.clone(),
+            config,
+            spec.outlierMethod.unwrap_or(config.method),
+        )
+    } else {
+        vehicles.clone()
+    };
+    let mut reduced = spec.clone();
+    let config = if spec.relax.unwrap_or_default() {
+        ESTIMATOR_CONFIG.relaxation.clone()
+    } else {
+        RelaxationConfig {
+            min_sample_size: 0,
+            ladder: vec![],
+        }
+    };
+    let (filterConditions, count, relaxation) = relax(&mut reduced, vehicles, &config);
+    if count == 0 {
+        return Err("No data found".to_string());
+    }
+    let data = vehicles
+        .clone()
+        .filter(filterConditions)
+        .collect()
+        .map_err(|e| e.to_string())?;
+    Ok(ComparableSet {
+        data,
+        filter: reduced,
+        relaxation,
+    })
+}
+
+#[cfg(test)]
 fn calculate(filter: StatisticSearchPayload) -> HashMap<String, Value> {
-    let column = "price_in_eur".to_string();
     let vehicles = ESTIMATED_PRICES_DATA.clone();
-
-    // Group by the required columns and calculate the required statistics
     let mut reduced = filter;
 
     let (filterConditions, count) = getCount(&mut reduced, &vehicles);
     if count == 0 {
-        let mut error = HashMap::new();
-        error.insert("error".to_string(), json!("No data found"));
-        error
+        error_response("No data found")
     } else {
-        let data = vehicles
-            .with_columns(vec![
-                col("make"),
-                col("model"),
-                col("year"),
-                col("engine"),
-                col("gearbox"),
-                col("power"),
-                col("mileage"),
-                col("cc"),
-                col("price_in_eur"),
-                lit(1).alias("GroupBy"),
-            ])
-            .filter(filterConditions)
-            .group_by(vec![col("GroupBy")])
-            .agg(vec![
-                col(&column).count().alias("count"),
-                col(&column).min().alias("min"),
-                col(&column).mean().alias("mean"),
-                col(&column).median().alias("median"),
-                col(&column)
-                    .quantile(0.66.into(), polars::prelude::QuantileMethod::Nearest)
-                    .alias("quantile_66"),
-                col(&column)
-                    .quantile(0.75.into(), polars::prelude::QuantileMethod::Nearest)
-                    .alias("quantile_75"),
-                col(&column)
-                    .quantile(0.8.into(), polars::prelude::QuantileMethod::Nearest)
-                    .alias("quantile_80"),
-                col(&column)
-                    .quantile(0.85.into(), polars::prelude::QuantileMethod::Nearest)
-                    .alias("quantile_85"),
-                col(&column).max().alias("max"),
-                col(&column).std(1).alias("std"),
-                (col(&column).std(1) / col(&column).mean()).alias("rsd"),
-            ])
-            .collect()
-            .unwrap();
-        to_generic_json(&data)
+        let comparables = vehicles.filter(filterConditions).collect().unwrap();
+        comparable_statistics(&comparables).unwrap()
     }
 }
 
+#[cfg(test)]
 fn getCount(
     reduced: &mut StatisticSearchPayload,
     vehicles: &polars::prelude::LazyFrame,
 ) -> (polars::prelude::Expr, usize) {
-    for (from, to) in MILEAGE.iter() {
+    let no_ladder = RelaxationConfig {
+        min_sample_size: 0,
+        ladder: vec![],
+    };
+    let (filterConditions, count, _) = relax(reduced, vehicles, &no_ladder);
+    (filterConditions, count)
+}
+
+fn count_matching(reduced: &StatisticSearchPayload, vehicles: &LazyFrame) -> usize {
+    vehicles
+        .clone()
+        .filter(to_predicate(reduced.clone()))
+        .collect()
+        .unwrap()
+        .height()
+}
+
+fn relax(
+    reduced: &mut StatisticSearchPayload,
+    vehicles: &polars::prelude::LazyFrame,
+    config: &RelaxationConfig,
+) -> (polars::prelude::Expr, usize, Vec<RelaxationStep>) {
+    let mut steps = vec![];
+    // Counting after each bucket step means another pass over the listings, so the
+    // buckets are only reported when the request opted into relaxation.
+    let report_buckets = !config.ladder.is_empty();
+    for (from, to) in ranges("mileage").iter() {
         if let Some(mileage) = reduced.mileage {
             if mileage >= *from && mileage <= *to {
                 reduced.mileageFrom = Some(*from);
                 reduced.mileageTo = Some(*to);
+                reduced.mileage = None;
+                if report_buckets {
+                    steps.push(RelaxationStep {
+                        step: "mileage_bucket".to_string(),
+                        description: format!(
+                            "Mileage {} km widened to the {}-{} km bucket",
+                            mileage, from, to
+                        ),
+                        count: count_matching(reduced, vehicles),
+                    });
+                }
                 break;
             }
         }
     }
     reduced.mileage = None;
-    for (from, to) in POWER.iter() {
+    for (from, to) in ranges("power").iter() {
         if let Some(power) = reduced.power {
             if power >= *from && power <= *to {
                 reduced.powerFrom = Some(*from);
                 reduced.powerTo = Some(*to);
+                reduced.power = None;
+                if report_buckets {
+                    steps.push(RelaxationStep {
+                        step: "power_bucket".to_string(),
+                        description: format!(
+                            "Power {} hp widened to the {}-{} hp bucket",
+                            power, from, to
+                        ),
+                        count: count_matching(reduced, vehicles),
+                    });
+                }
                 break;
             }
         }
     }
     reduced.power = None;
 
-    let filterConditions = to_predicate(reduced.clone());
-    let filtered = vehicles
-        .clone()
-        .filter(filterConditions.clone())
-        .collect()
-        .unwrap();
-    let count = filtered.height();
+    let mut count = count_matching(reduced, vehicles);
     if count == 0 {
         info!("No data found for the given search criteria");
-        reduced.search = None;
-        reduced.cc = None;
+        let dropped = [
+            reduced
+                .search
+                .take()
+                .map(|search| format!("search {}", search)),
+            reduced.cc.take().map(|cc| format!("cc {}", cc)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        if !dropped.is_empty() {
+            count = count_matching(reduced, vehicles);
+            steps.push(RelaxationStep {
+                step: "drop_search_cc".to_string(),
+                description: format!("No listings matched; dropped {}", dropped.join(" and ")),
+                count,
+            });
+        }
     }
 
-    let filterConditions = to_predicate(reduced.clone());
-    let result = vehicles
-        .clone()
-        .filter(filterConditions.clone())
-        .collect()
-        .unwrap();
-    let count = result.height();
-    (filterConditions, count)
-}
+    for relaxation in config.ladder.iter() {
+        if count >= config.min_sample_size {
+            break;
+        }
+        if let Some(description) = apply_relaxation(*relaxation, reduced) {
+            count = count_matching(reduced, vehicles);
+            info!("{}: {} comparables", description, count);
+            steps.push(RelaxationStep {
+                step: relaxation.name().to_string(),
+                description,
+                count,
+            });
+        }
+    }
 
-fn calculate_estimated_value(result: &HashMap<String, Value>) -> f64 {
-    // Extracting values from the HashMap
-    let rsd = result.get("rsd").unwrap().as_array().unwrap();
-    let mean = result.get("mean").unwrap().as_array().unwrap();
-    let median = result.get("median").unwrap().as_array().unwrap();
-    let quantile_66 = result.get("quantile_66").unwrap().as_array().unwrap();
-    let quantile_75 = result.get("quantile_75").unwrap().as_array().unwrap();
-    let quantile_80 = result.get("quantile_80").unwrap().as_array().unwrap();
-    let quantile_85 = result.get("quantile_85").unwrap().as_array().unwrap();
-    let max = result.get("max").unwrap().as_array().unwrap();
+    (to_predicate(reduced.clone()), count, steps)
+}
 
-    // Converting to f64
-    let rsd = rsd[0].as_f64().unwrap();
-    let mean = mean[0].as_f64().unwrap();
-    let median = median[0].as_f64().unwrap();
-    let q66 = quantile_66[0].as_f64().unwrap();
-    let q75 = quantile_75[0].as_f64().unwrap();
-    let q80 = quantile_80[0].as_f64().unwrap();
-    let q85 = quantile_85[0].as_f64().unwrap();
-    let max = max[0].as_f64().unwrap();
+/// Loosens `reduced` by one rung of the ladder. Returns what was changed, or `None`
+/// when the rung does not apply to the request.
+fn apply_relaxation(
+    relaxation: Relaxation,
+    reduced: &mut StatisticSearchPayload,
+) -> Option<String> {
+    match relaxation {
+        Relaxation::WidenMileage => {
+            let (from, to) = widen(&ranges("mileage"), reduced.mileageFrom, reduced.mileageTo)?;
+            reduced.mileageFrom = Some(from);
+            reduced.mileageTo = Some(to);
+            Some(format!("Mileage widened to {}-{} km", from, to))
+        }
+        Relaxation::WidenPower => {
+            let (from, to) = widen(&ranges("power"), reduced.powerFrom, reduced.powerTo)?;
+            reduced.powerFrom = Some(from);
+            reduced.powerTo = Some(to);
+            Some(format!("Power widened to {}-{} hp", from, to))
+        }
+        Relaxation::YearRange => {
+            let year = reduced.year.take()?;
+            reduced.yearFrom = Some(year - 1);
+            reduced.yearTo = Some(year + 1);
+            Some(format!(
+                "Year {} widened to {}-{}",
+                year,
+                year - 1,
+                year + 1
+            ))
+        }
+        Relaxation::DropGearbox => {
+            let gearbox = reduced.gearbox.take()?;
+            Some(format!("Dropped gearbox {}", gearbox))
+        }
+        Relaxation::DropEngine => {
+            let engine = reduced.engine.take()?;
+            Some(format!("Dropped engine {}", engine.join(", ")))
+        }
+        Relaxation::ModelFamily => {
+            let family = model_family(reduced.model.as_ref()?)?;
+            let model = reduced.model.take()?;
+            reduced.modelFamily = Some(family.clone());
+            Some(format!(
+                "Model {} widened to the models of the {} family",
+                model, family
+            ))
+        }
+    }
+}
 
-    info!(
-        "RSD: {}, Mean: {}, Median: {}, Q66: {}, Q75: {}, Q80: {}, Q85: {}, Max: {}",
-        rsd, mean, median, q66, q75, q80, q85, max
+/// Extends `from..to` by the buckets on either side of it.
+fn widen(buckets: &[(i32, i32)], from: Option<i32>, to: Option<i32>) -> Option<(i32, i32)> {
+    let (from, to) = (from?, to?);
+    let first = buckets.iter().position(|(_, upper)| from <= *upper)?;
+    let last = buckets.iter().position(|(_, upper)| to <= *upper)?;
+    let widened = (
+        buckets[first.saturating_sub(1)].0,
+        buckets[(last + 1).min(buckets.len() - 1)].1,
     );
-
-    let mut estimation;
-    if rsd <= 0.1 {
-        let w_mean = (1.0 - rsd) * 0.4;
-        let w_median = (1.0 - rsd) * 0.4;
-        let w_q66 = (1.0 - rsd) * 0.12;
-        let w_q80 = (1.0 - rsd) * 0.1;
-        let w_max = rsd;
-
-        // Logging weights (optional)
-        println!(
-            "Weights: {}, {}, {}, {}, {}",
-            w_mean, w_median, w_q66, w_q80, w_max
-        );
-
-        // Calculating estimation
-        estimation = w_mean * mean + w_median * median + w_q66 * q66 + w_q80 * q80 + w_max * max;
-    } else if rsd > 0.1 && rsd <= 0.3 {
-        let w_mean = (1.0 - rsd) * 0.2;
-        let w_median = (1.0 - rsd) * 0.2;
-        let w_66 = (1.0 - rsd) * 0.3;
-        let w_75 = 0.15;
-        let w_80 = (1.0 - rsd) * 0.3;
-
-        // Logging weights (optional)
-        println!(
-            "Weights: {}, {}, {}, {}, {}",
-            w_mean, w_median, w_75, w_66, w_80
-        );
-
-        let w_total = w_mean + w_median + w_66 + w_75 + w_80;
-
-        // Calculating estimation
-        estimation = w_mean * mean + w_median * median + w_66 * q66 + w_75 * q75 + w_80 * q80;
-
-        if (w_total - 1.0).abs() > 0.0001 {
-            estimation *= 1.0 / w_total;
-        }
+    if widened == (from, to) {
+        None
     } else {
-        let w_mean = 0.2;
-        let w_median = 0.2;
-        let w_66 = 0.25;
-        let w_75 = 0.15;
-        let w_80 = 0.10;
-        let w_max = 0.10;
-
-        // Logging weights (optional)
-
-        // Calculating estimation
-        estimation =
-            w_mean * mean + w_median * median + w_66 * q66 + w_75 * q75 + w_80 * q80 + w_max * max;
+        Some(widened)
     }
+}
 
-    estimation
+/// The leading word of the model; for numeric model names such as BMW's 320 only
+/// the series digit is kept. `None` when the model is its own family.
+fn model_family(model: &str) -> Option<String> {
+    let family = family_token(model)?;
+    if family == model {
+        None
+    } else {
+        Some(family)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{configure_log4rs, services::PriceCalculatorService::calculate};
+    use crate::{
+        configure_log4rs,
+        services::{
+            EstimatorService::WeightedQuantileEstimator, PriceCalculatorService::calculate,
+        },
+    };
     use serde_json::json;
 
+    /// Estimation of the default weighted-quantile strategy from the output of `calculate`.
+    fn calculate_estimated_value(result: &HashMap<String, Value>) -> f64 {
+        let estimator = WeightedQuantileEstimator {
+            config: ESTIMATOR_CONFIG.weighted_quantile.clone(),
+        };
+        let (estimation, band) = estimator.weighted_value(result).unwrap();
+        info!("Weight band: {}, Estimation: {}", band, estimation);
+        estimation
+    }
+
     #[test]
     fn test_getCount() {
         configure_log4rs("resources/log4rs.yml");
@@ -281,6 +401,162 @@ mod tests {
         assert_eq!(result.height(), 12);
     }
 
+    fn sparse_listings() -> LazyFrame {
+        use polars::{df, prelude::IntoLazy};
+        df!(
+            "make" => ["BMW"; 5],
+            "model" => ["320", "320", "320", "320", "318"],
+            "year" => [2018, 2018, 2018, 2019, 2018],
+            "title" => ["BMW 320d"; 5],
+            "equipment" => ["Navigation"; 5],
+            "engine" => ["Diesel"; 5],
+            "gearbox" => ["Automatic", "Manual", "Automatic", "Automatic", "Automatic"],
+            "cc" => [1995; 5],
+            "mileage" => [65_000, 72_000, 130_000, 70_000, 70_000],
+            "power" => [190, 190, 190, 190, 150],
+            "price_in_eur" => [21_000, 20_000, 15_000, 23_000, 19_000]
+        )
+        .unwrap()
+        .lazy()
+    }
+
+    fn sparse_spec() -> StatisticSearchPayload {
+        StatisticSearchPayload {
+            make: Some("BMW".to_string()),
+            model: Some("320".to_string()),
+            year: Some(2018),
+            engine: Some(vec!["Diesel".to_string()]),
+            gearbox: Some("Automatic".to_string()),
+            mileage: Some(61234),
+            power: Some(190),
+            search: Some("xdrive".to_string()),
+            relax: Some(true),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_comparables_reports_relaxation() {
+        let set = comparables(&sparse_spec(), &sparse_listings()).unwrap();
+        assert_eq!(set.data.height(), 4);
+        let steps = set
+            .relaxation
+            .iter()
+            .map(|s| (s.step.as_str(), s.count))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            steps,
+            vec![
+                ("mileage_bucket", 0),
+                ("power_bucket", 0),
+                ("drop_search_cc", 1),
+                ("widen_mileage", 1),
+                ("widen_power", 1),
+                ("year_range", 2),
+                ("drop_gearbox", 3),
+                ("drop_engine", 3),
+                ("model_family", 4),
+            ]
+        );
+        assert_eq!(set.filter.search, None);
+        assert_eq!(set.filter.mileageFrom, Some(40001));
+        assert_eq!(set.filter.modelFamily, Some("3".to_string()));
+    }
+
+    #[test]
+    fn test_relaxation_is_opt_in() {
+        let spec = StatisticSearchPayload {
+            relax: None,
+            ..sparse_spec()
+        };
+        let set = comparables(&spec, &sparse_listings()).unwrap();
+        let steps = set
+            .relaxation
+            .iter()
+            .map(|s| s.step.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(steps, vec!["drop_search_cc"]);
+        assert_eq!(set.data.height(), 1);
+
+        // Nothing to drop, so no step is reported.
+        let spec = StatisticSearchPayload {
+            search: None,
+            gearbox: Some("Manual".to_string()),
+            mileage: None,
+            power: None,
+            ..spec
+        };
+        assert!(comparables(&spec, &sparse_listings()).is_ok());
+        let mut reduced = StatisticSearchPayload {
+            model: Some("M3".to_string()),
+            ..spec
+        };
+        let no_ladder = RelaxationConfig {
+            min_sample_size: 0,
+            ladder: vec![],
+        };
+        let (_, count, steps) = relax(&mut reduced, &sparse_listings(), &no_ladder);
+        assert_eq!(count, 0);
+        assert!(steps.is_empty());
+    }
+
+    #[test]
+    fn test_model_family_matches_whole_tokens() {
+        use polars::{df, prelude::IntoLazy};
+        let models = df!("model" => ["C 180", "C", "CLA 200", "CLS", "320", "330d", "3", "M3"])
+            .unwrap()
+            .lazy();
+        let family = |family: &str| {
+            let search = StatisticSearchPayload {
+                modelFamily: Some(family.to_string()),
+                ..Default::default()
+            };
+            let matching = models
+                .clone()
+                .filter(to_predicate(search))
+                .collect()
+                .unwrap();
+            matching
+                .column("model")
+                .unwrap()
+                .str()
+                .unwrap()
+                .into_no_null_iter()
+                .map(|m| m.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(family("C"), vec!["C 180", "C"]);
+        assert_eq!(family("3"), vec!["320", "330d"]);
+        assert_eq!(model_family("C 220"), Some("C".to_string()));
+    }
+
+    #[test]
+    fn test_relaxation_stops_at_min_sample_size() {
+        let config = RelaxationConfig {
+            min_sample_size: 2,
+            ..Default::default()
+        };
+        let mut reduced = sparse_spec();
+        let (_, count, steps) = relax(&mut reduced, &sparse_listings(), &config);
+        assert_eq!(count, 2);
+        assert_eq!(steps.last().unwrap().step, "year_range");
+        assert_eq!(reduced.gearbox, Some("Automatic".to_string()));
+        assert_eq!((reduced.yearFrom, reduced.yearTo), (Some(2017), Some(2019)));
+    }
+
+    #[test]
+    fn test_widen_and_model_family() {
+        assert_eq!(
+            widen(&ranges("mileage"), Some(60001), Some(80000)),
+            Some((40001, 100000))
+        );
+        assert_eq!(widen(&ranges("power"), Some(0), Some(9999)), None);
+        assert_eq!(widen(&ranges("power"), None, Some(90)), None);
+        assert_eq!(model_family("320"), Some("3".to_string()));
+        assert_eq!(model_family("A4 Allroad"), Some("A4".to_string()));
+        assert_eq!(model_family("Golf"), None);
+    }
+
     #[test]
     fn test_calculate_rsd_02() {
         configure_log4rs("resources/log4rs.yml");
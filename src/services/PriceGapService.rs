@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+
+use polars::{
+    frame::UniqueKeepStrategy,
+    prelude::{
+        col, len, lit, when, DataType, Expr, IntoLazy, JoinArgs, JoinType, LazyFrame,
+        QuantileMethod, SortMultipleOptions, NULL,
+    },
+};
+use serde_json::Value;
+
+use crate::{
+    model::{
+        AxumAPIModel::{PriceGapRequest, StatisticSearchPayload},
+        Valuation::{PriceCorrection, PriceGapConfig},
+    },
+    PRICE_DATA, VEHICLES_DATA, VEHICLE_STATIC_DATA,
+};
+
+use super::{
+    EstimatorService::ESTIMATOR_CONFIG, Utils::filter_listings, VehicleService::to_generic_json,
+};
+
+/// Gaps are compared with the median asking price of the same make, model and year.
+pub const SEGMENT: [&str; 3] = ["make", "model", "year"];
+
+fn segment() -> Vec<Expr> {
+    SEGMENT.iter().map(|c| col(*c)).collect()
+}
+
+/// `price / reference - 1`, unknown without a positive reference.
+fn gap(price: Expr, reference: Expr) -> Expr {
+    when(reference.clone().gt(lit(0.0)))
+        .then(price / reference - lit(1.0))
+        .otherwise(lit(NULL).cast(DataType::Float64))
+}
+
+/// Sold listings of `statistic` with their last `asking_price`, the `estimate` of the
+/// listing in `vehicles`, the `segment_median` asking price in `prices` and the
+/// relative `estimate_gap` and `segment_gap` to them.
+pub fn sold_gaps(statistic: LazyFrame, vehicles: LazyFrame, prices: LazyFrame) -> LazyFrame {
+    let estimates = vehicles
+        .filter(col("estimated_price_in_eur").gt(lit(0)))
+        .select([
+            col("advert_id"),
+            col("estimated_price_in_eur")
+                .cast(DataType::Float64)
+                .alias("estimate"),
+        ])
+        .unique(Some(vec!["advert_id".into()]), UniqueKeepStrategy::First);
+    let medians = prices
+        .filter(col("price_in_eur").gt(lit(0)))
+        .group_by(segment())
+        .agg([col("price_in_eur")
+            .cast(DataType::Float64)
+            .median()
+            .alias("segment_median")]);
+    statistic
+        .filter(
+            col("sold_date")
+                .is_not_null()
+                .and(col("price_in_eur").gt(lit(0))),
+        )
+        .with_column(
+            col("price_in_eur")
+                .cast(DataType::Float64)
+                .alias("asking_price"),
+        )
+        .join(
+            estimates,
+            [col("advert_id")],
+            [col("advert_id")],
+            JoinArgs::new(JoinType::Left),
+        )
+        .join(medians, segment(), segment(), JoinArgs::new(JoinType::Left))
+        .with_columns([
+            gap(col("asking_price"), col("estimate")).alias("estimate_gap"),
+            gap(col("asking_price"), col("segment_median")).alias("segment_gap"),
+        ])
+}
+
+fn percent(expr: Expr) -> Expr {
+    expr * lit(100.0)
+}
+
+/// Typical gaps (in percent) of the sold listings per `group`, the largest groups
+/// first, with the correction factor of the groups with enough estimated listings.
+pub fn gap_report(gaps: LazyFrame, group: &[String], config: &PriceGapConfig) -> LazyFrame {
+    let quantile = |q: f64| percent(col("estimate_gap").quantile(lit(q), QuantileMethod::Linear));
+    gaps.group_by(group.iter().map(|c| col(c.as_str())).collect::<Vec<_>>())
+        .agg([
+            len().cast(DataType::Int32).alias("sold"),
+            col("estimate_gap")
+                .count()
+                .cast(DataType::Int32)
+                .alias("estimated"),
+            percent(col("estimate_gap").median()).alias("median_estimate_gap"),
+            quantile(0.25).alias("estimate_gap_q25"),
+            quantile(0.75).alias("estimate_gap_q75"),
+            percent(col("segment_gap").median()).alias("median_segment_gap"),
+        ])
+        .with_column(
+            when(col("estimated").gt_eq(lit(config.min_sold as i32)))
+                .then(correction(col("median_estimate_gap") / lit(100.0), config))
+                .otherwise(lit(NULL).cast(DataType::Float64))
+                .alias("correction_factor"),
+        )
+        .sort(
+            ["sold"],
+            SortMultipleOptions::new().with_order_descending(true),
+        )
+}
+
+/// `1 + gap`, limited to `1 ± max_correction`.
+fn correction(gap: Expr, config: &PriceGapConfig) -> Expr {
+    let limit = config.max_correction;
+    when(gap.clone().gt(lit(limit)))
+        .then(lit(1.0 + limit))
+        .when(gap.clone().lt(lit(-limit)))
+        .then(lit(1.0 - limit))
+        .otherwise(lit(1.0) + gap)
+}
+
+pub fn price_gaps(request: PriceGapRequest) -> Result<HashMap<String, Value>, String> {
+    let group = if request.group.is_empty() {
+        SEGMENT.iter().map(|c| c.to_string()).collect()
+    } else {
+        request.group
+    };
+    let gaps = sold_gaps(
+        filter_listings(VEHICLE_STATIC_DATA.clone(), &request.filter),
+        VEHICLES_DATA.clone(),
+        PRICE_DATA.clone(),
+    );
+    let result = gap_report(gaps, &group, &ESTIMATOR_CONFIG.price_gap)
+        .collect()
+        .map_err(|e| e.to_string())?;
+    if result.height() == 0 {
+        return Err("No sold listings found".to_string());
+    }
+    Ok(to_generic_json(&result))
+}
+
+/// Correction of the make, model and year of `spec`, or of its make and model when
+/// too few of its year were sold. `None` without enough sold listings.
+pub fn correction_for(
+    gaps: LazyFrame,
+    spec: &StatisticSearchPayload,
+    config: &PriceGapConfig,
+) -> Result<Option<PriceCorrection>, String> {
+    let (Some(make), Some(model)) = (spec.make.as_deref(), spec.model.as_deref()) else {
+        return Ok(None);
+    };
+    let mut segments = vec![];
+    if let Some(year) = spec.year {
+        segments.push((
+            format!("make={}, model={}, year={}", make, model, year),
+            col("year").eq(lit(year)),
+        ));
+    }
+    segments.push((format!("make={}, model={}", make, model), lit(true)));
+    let gaps = gaps
+        .filter(
+            col("make")
+                .eq(lit(make))
+                .and(col("model").eq(lit(model)))
+                .and(col("estimate_gap").is_not_null()),
+        )
+        .collect()
+        .map_err(|e| e.to_string())?
+        .lazy();
+    for (segment, predicate) in segments {
+        let result = gaps
+            .clone()
+            .filter(predicate)
+            .select([
+                len().cast(DataType::Int32).alias("sold"),
+                col("estimate_gap").median().alias("median_gap"),
+            ])
+            .with_column(correction(col("median_gap"), config).alias("factor"))
+            .collect()
+            .map_err(|e| e.to_string())?;
+        let sold = result.column("sold").map_err(|e| e.to_string())?;
+        let sold = sold.i32().map_err(|e| e.to_string())?.get(0).unwrap_or(0) as usize;
+        if sold < config.min_sold {
+            continue;
+        }
+        let value = |name: &str| -> Result<f64, String> {
+            result
+                .column(name)
+                .and_then(|c| c.f64().map(|c| c.get(0).unwrap_or(0.0)))
+                .map_err(|e| e.to_string())
+        };
+        return Ok(Some(PriceCorrection {
+            segment,
+            sold,
+            median_gap: value("median_gap")? * 100.0,
+            factor: value("factor")?,
+        }));
+    }
+    Ok(None)
+}
+
+/// [`correction_for`] over all sold listings.
+pub fn sold_correction(spec: &StatisticSearchPayload) -> Result<Option<PriceCorrection>, String> {
+    let gaps = sold_gaps(
+        VEHICLE_STATIC_DATA.clone(),
+        VEHICLES_DATA.clone(),
+        PRICE_DATA.clone(),
+    );
+    correction_for(gaps, spec, &ESTIMATOR_CONFIG.price_gap)
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::{
+        df,
+        prelude::{NamedFrom, Series},
+    };
+
+    use super::*;
+    use crate::services::TestUtils::{date, rounded_column};
+
+    /// Six sold Golfs of 2018 asking 10% (four) or 20% (two) below their estimate,
+    /// one unsold Golf and one sold Passat.
+    fn gaps() -> LazyFrame {
+        let sold = Some(date("2024-03-01"));
+        let mut statistic = df!(
+            "advert_id" => ["1", "2", "3", "4", "5", "6", "7", "8"],
+            "make" => ["VW"; 8],
+            "model" => ["Golf", "Golf", "Golf", "Golf", "Golf", "Golf", "Golf", "Passat"],
+            "year" => [2018, 2018, 2018, 2018, 2018, 2018, 2018, 2018],
+            "price_in_eur" => [9_000, 9_000, 9_000, 9_000, 8_000, 8_000, 5_000, 15_000]
+        )
+        .unwrap();
+        statistic
+            .with_column(Series::new(
+                "sold_date".into(),
+                [sold, sold, sold, sold, sold, sold, None, sold],
+            ))
+            .unwrap();
+        let vehicles = df!(
+            "advert_id" => ["1", "2", "3", "4", "5", "6", "7", "8"],
+            "estimated_price_in_eur" => [10_000, 10_000, 10_000, 10_000, 10_000, 10_000, 10_000, 0]
+        )
+        .unwrap();
+        let prices = df!(
+            "make" => ["VW"; 3],
+            "model" => ["Golf", "Golf", "Passat"],
+            "year" => [2018; 3],
+            "price_in_eur" => [9_500, 10_500, 15_000]
+        )
+        .unwrap();
+        sold_gaps(statistic.lazy(), vehicles.lazy(), prices.lazy())
+    }
+
+    #[test]
+    fn test_gap_report() {
+        let group = SEGMENT.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        let report = gap_report(gaps(), &group, &PriceGapConfig::default())
+            .collect()
+            .unwrap();
+        assert_eq!(rounded_column(&report, "sold"), vec![Some(6.0), Some(1.0)]);
+        assert_eq!(
+            rounded_column(&report, "estimated"),
+            vec![Some(6.0), Some(0.0)]
+        );
+        assert_eq!(
+            rounded_column(&report, "median_estimate_gap"),
+            vec![Some(-10.0), None]
+        );
+        assert_eq!(
+            rounded_column(&report, "median_segment_gap"),
+            vec![Some(-10.0), Some(0.0)]
+        );
+        assert_eq!(
+            rounded_column(&report, "correction_factor"),
+            vec![Some(0.9), None]
+        );
+    }
+
+    #[test]
+    fn test_correction_for() {
+        let spec = |year| StatisticSearchPayload {
+            make: Some("VW".to_string()),
+            model: Some("Golf".to_string()),
+            year,
+            ..Default::default()
+        };
+        let config = PriceGapConfig {
+            min_sold: 5,
+            max_correction: 0.05,
+        };
+        let correction = correction_for(gaps(), &spec(Some(2018)), &config)
+            .unwrap()
+            .unwrap();
+        assert_eq!(correction.sold, 6);
+        assert_eq!(correction.segment, "make=VW, model=Golf, year=2018");
+        assert!((correction.median_gap + 10.0).abs() < 1e-9);
+        assert!((correction.factor - 0.95).abs() < 1e-9);
+
+        // Too few sold of 2019: falls back to make and model.
+        let correction = correction_for(gaps(), &spec(Some(2019)), &config)
+            .unwrap()
+            .unwrap();
+        assert_eq!(correction.segment, "make=VW, model=Golf");
+
+        let passat = StatisticSearchPayload {
+            model: Some("Passat".to_string()),
+            ..spec(None)
+        };
+        assert_eq!(correction_for(gaps(), &passat, &config).unwrap(), None);
+    }
+}
@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+
+/// Result of an ordinary least squares fit `y = b0 + b1 * x1 + ... + bn * xn`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct LinearFit {
+    /// Intercept first, followed by one coefficient per feature.
+    pub coefficients: Vec<f64>,
+    pub r_squared: f64,
+    /// Standard deviation of the residuals (n - p degrees of freedom).
+    pub residual_std: f64,
+    pub count: usize,
+}
+
+impl LinearFit {
+    pub fn predict(&self, features: &[f64]) -> f64 {
+        self.coefficients[0]
+            + self.coefficients[1..]
+                .iter()
+                .zip(features)
+                .map(|(b, x)| b * x)
+                .sum::<f64>()
+    }
+}
+
+/// Fits `y` against `rows` (one feature vector per observation, without the
+/// intercept) by solving the normal equations.
+///
+/// Returns `None` when there are not more observations than parameters or the
+/// features are collinear.
+pub fn least_squares(rows: &[Vec<f64>], y: &[f64]) -> Option<LinearFit> {
+    if rows.is_empty() || rows.len() != y.len() {
+        return None;
+    }
+    let p = rows[0].len() + 1;
+    let n = rows.len();
+    if n <= p {
+        return None;
+    }
+
+    // Build X'X and X'y with the intercept column prepended.
+    let mut xtx = vec![vec![0.0; p]; p];
+    let mut xty = vec![0.0; p];
+    for (row, target) in rows.iter().zip(y) {
+        let x = std::iter::once(1.0)
+            .chain(row.iter().cloned())
+            .collect::<Vec<f64>>();
+        for i in 0..p {
+            xty[i] += x[i] * target;
+            for j in 0..p {
+                xtx[i][j] += x[i] * x[j];
+            }
+        }
+    }
+
+    let coefficients = solve(xtx, xty)?;
+    let mut fit = LinearFit {
+        coefficients,
+        count: n,
+        ..Default::default()
+    };
+
+    let mean = y.iter().sum::<f64>() / n as f64;
+    let (mut ss_res, mut ss_tot) = (0.0, 0.0);
+    for (row, target) in rows.iter().zip(y) {
+        let residual = target - fit.predict(row);
+        ss_res += residual * residual;
+        ss_tot += (target - mean) * (target - mean);
+    }
+    fit.r_squared = if ss_tot > 0.0 {
+        1.0 - ss_res / ss_tot
+    } else {
+        1.0
+    };
+    fit.residual_std = (ss_res / (n - p) as f64).sqrt();
+    Some(fit)
+}
+
+/// Gaussian elimination with partial pivoting.
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for k in 0..n {
+        let pivot = (k..n).max_by(|i, j| a[*i][k].abs().total_cmp(&a[*j][k].abs()))?;
+        if a[pivot][k].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(k, pivot);
+        b.swap(k, pivot);
+        let pivot_row = a[k].clone();
+        for i in k + 1..n {
+            let factor = a[i][k] / pivot_row[k];
+            for (value, p) in a[i][k..].iter_mut().zip(&pivot_row[k..]) {
+                *value -= factor * p;
+            }
+            b[i] -= factor * b[k];
+        }
+    }
+
+    let mut x = vec![0.0; n];
+    for i in (0..n).rev() {
+        let sum = (i + 1..n).map(|j| a[i][j] * x[j]).sum::<f64>();
+        x[i] = (b[i] - sum) / a[i][i];
+    }
+    Some(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_least_squares_exact_fit() {
+        let rows = vec![
+            vec![1.0, 2.0],
+            vec![2.0, 1.0],
+            vec![3.0, 5.0],
+            vec![4.0, 3.0],
+            vec![5.0, 8.0],
+        ];
+        let y = rows
+            .iter()
+            .map(|r| 10.0 - 2.0 * r[0] + 0.5 * r[1])
+            .collect::<Vec<_>>();
+        let fit = least_squares(&rows, &y).unwrap();
+        assert!((fit.coefficients[0] - 10.0).abs() < 1e-9);
+        assert!((fit.coefficients[1] + 2.0).abs() < 1e-9);
+        assert!((fit.coefficients[2] - 0.5).abs() < 1e-9);
+        assert!((fit.r_squared - 1.0).abs() < 1e-9);
+        assert_eq!(fit.count, 5);
+    }
+
+    #[test]
+    fn test_least_squares_collinear() {
+        let rows = vec![
+            vec![1.0, 2.0],
+            vec![2.0, 4.0],
+            vec![3.0, 6.0],
+            vec![4.0, 8.0],
+        ];
+        let y = vec![1.0, 2.0, 3.0, 4.0];
+        assert!(least_squares(&rows, &y).is_none());
+    }
+}
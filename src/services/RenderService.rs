@@ -0,0 +1,445 @@
+use std::{collections::HashMap, io::Cursor};
+
+use lazy_static::lazy_static;
+use plotters::{
+    coord::Shift,
+    prelude::*,
+    style::{register_font, FontStyle, RGBAColor},
+};
+use serde_json::Value;
+
+use crate::model::{
+    AxumAPIModel::{DataToBinsRequest, PivotData, RenderRequest, TimeSeriesRequest},
+    Chart::{ImageFormat, RenderOptions},
+    DistributionChartData,
+};
+
+use super::{
+    ChartServices::data_to_bins, PivotService::pivot_chart, TimeSeriesService::market_trend,
+    Utils::generate_colors,
+};
+
+const FONT: &str = "sans-serif";
+const FONT_BYTES: &[u8] = include_bytes!("../../resources/fonts/DejaVuSans.ttf");
+const MAX_SIZE: u32 = 4_000;
+/// Labels written below the x axis at most; the others are skipped evenly.
+const MAX_X_LABELS: usize = 20;
+
+lazy_static! {
+    static ref FONT_REGISTERED: Result<(), String> =
+        register_font(FONT, FontStyle::Normal, FONT_BYTES)
+            .map_err(|_| "The bundled font cannot be read".to_string());
+}
+
+/// One dataset of a [`Plot`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlotSeries {
+    pub label: String,
+    /// One value per label of the plot.
+    pub values: Vec<Option<f64>>,
+    /// Drawn as a line instead of bars.
+    pub line: bool,
+    /// Drawn against the right y axis.
+    pub right: bool,
+}
+
+/// Labels and datasets of a chart to render.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Plot {
+    pub labels: Vec<String>,
+    pub series: Vec<PlotSeries>,
+}
+
+fn label(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+impl Plot {
+    /// Plot of a Chart.js answer of `/pivot-chart` or `/time-series`: datasets of `type`
+    /// `line` (or all datasets when `lines`) are drawn as lines, those of `yAxisID` `y1`
+    /// against the right axis.
+    pub fn from_chart(chart: &HashMap<String, Value>, lines: bool) -> Result<Plot, String> {
+        if let Some(error) = chart.get("error") {
+            return Err(label(error));
+        }
+        let labels = chart
+            .get("labels")
+            .and_then(|l| l.as_array())
+            .ok_or("The chart has no labels")?
+            .iter()
+            .map(label)
+            .collect::<Vec<_>>();
+        let datasets = chart
+            .get("datasets")
+            .and_then(|d| d.as_array())
+            .ok_or("The chart has no datasets")?;
+        let series = datasets
+            .iter()
+            .map(|dataset| {
+                let mut values = dataset["data"]
+                    .as_array()
+                    .map(|data| data.iter().map(|v| v.as_f64()).collect::<Vec<_>>())
+                    .unwrap_or_default();
+                values.resize(labels.len(), None);
+                PlotSeries {
+                    label: label(&dataset["label"]),
+                    values,
+                    line: lines || dataset["type"] == "line",
+                    right: dataset["yAxisID"] == "y1",
+                }
+            })
+            .collect();
+        Ok(Plot { labels, series })
+    }
+
+    /// Plot of the listings per bin of `/data-distribution`.
+    pub fn from_distribution(data: &DistributionChartData<i32>) -> Plot {
+        Plot {
+            labels: data
+                .data
+                .iter()
+                .map(|b| format!("{} - {}", b.min, b.max))
+                .collect(),
+            series: vec![PlotSeries {
+                label: "count".to_string(),
+                values: data.data.iter().map(|b| Some(b.count as f64)).collect(),
+                line: false,
+                right: false,
+            }],
+        }
+    }
+}
+
+/// Parses `#rrggbb`, `rgb(r, g, b)` or `rgba(r, g, b, a)`.
+pub fn parse_color(color: &str) -> Result<RGBAColor, String> {
+    let invalid = || format!("Invalid colour '{}'", color);
+    let color = color.trim();
+    if let Some(hex) = color.strip_prefix('#') {
+        // Checked byte by byte so that the channels below are sliced at character bounds.
+        if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+        return Ok(RGBAColor(channel(0)?, channel(2)?, channel(4)?, 1.0));
+    }
+    let inner = color
+        .strip_prefix("rgba(")
+        .or_else(|| color.strip_prefix("rgb("))
+        .and_then(|c| c.strip_suffix(')'))
+        .ok_or_else(invalid)?;
+    let parts = inner.split(',').map(|p| p.trim()).collect::<Vec<_>>();
+    if parts.len() < 3 || parts.len() > 4 {
+        return Err(invalid());
+    }
+    let channel = |i: usize| parts[i].parse::<u8>().map_err(|_| invalid());
+    let alpha = match parts.get(3) {
+        Some(alpha) => alpha.parse::<f64>().map_err(|_| invalid())?,
+        None => 1.0,
+    };
+    Ok(RGBAColor(channel(0)?, channel(1)?, channel(2)?, alpha))
+}
+
+/// `count` colours of `palette`, repeated as needed, or the Chart.js colours.
+fn colors(palette: &[String], count: usize) -> Result<Vec<RGBAColor>, String> {
+    let palette = if palette.is_empty() {
+        generate_colors(count)
+    } else {
+        palette.to_vec()
+    };
+    let palette = palette
+        .iter()
+        .map(|c| parse_color(c))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((0..count).map(|i| palette[i % palette.len()]).collect())
+}
+
+/// Value range of the datasets on one axis, always with 0 and some room above.
+fn value_range<'a>(series: impl Iterator<Item = &'a PlotSeries>) -> (f64, f64) {
+    let (min, max) = series
+        .flat_map(|s| s.values.iter().flatten())
+        .fold((0.0f64, 0.0f64), |(min, max), v| (min.min(*v), max.max(*v)));
+    let padding = ((max - min) * 0.05).max(f64::EPSILON);
+    (if min < 0.0 { min - padding } else { 0.0 }, max + padding)
+}
+
+/// Line pieces between the labels without a value.
+fn segments(values: &[Option<f64>]) -> Vec<Vec<(f64, f64)>> {
+    let mut segments = vec![vec![]];
+    for (i, value) in values.iter().enumerate() {
+        match value {
+            Some(v) => segments.last_mut().unwrap().push((i as f64, *v)),
+            None => segments.push(vec![]),
+        }
+    }
+    segments.retain(|s| !s.is_empty());
+    segments
+}
+
+fn draw<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    plot: &Plot,
+    options: &RenderOptions,
+) -> Result<(), String> {
+    let err = |e: DrawingAreaErrorKind<DB::ErrorType>| e.to_string();
+    root.fill(&WHITE).map_err(err)?;
+    let colors = colors(&options.palette, plot.series.len())?;
+    let count = plot.labels.len().max(1);
+    let x_range = || -0.5..count as f64 - 0.5;
+    let (left_min, left_max) = value_range(plot.series.iter().filter(|s| !s.right));
+    let (right_min, right_max) = value_range(plot.series.iter().filter(|s| s.right));
+    let has_right = plot.series.iter().any(|s| s.right);
+
+    let mut builder = ChartBuilder::on(root);
+    builder
+        .margin(15)
+        .x_label_area_size(40)
+        .y_label_area_size(70)
+        .right_y_label_area_size(if has_right { 70 } else { 0 });
+    if let Some(title) = &options.title {
+        builder.caption(title, (FONT, 22));
+    }
+    let mut chart = builder
+        .build_cartesian_2d(x_range(), left_min..left_max)
+        .map_err(err)?
+        .set_secondary_coord(x_range(), right_min..right_max);
+    let x_label = |x: &f64| {
+        let position = x.round();
+        if (x - position).abs() < 1e-6 && position >= 0.0 {
+            plot.labels
+                .get(position as usize)
+                .cloned()
+                .unwrap_or_default()
+        } else {
+            String::new()
+        }
+    };
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .x_labels(count.min(MAX_X_LABELS))
+        .x_label_formatter(&x_label)
+        .x_label_style((FONT, 12))
+        .y_label_style((FONT, 12))
+        .x_desc(options.x_label.clone().unwrap_or_default())
+        .y_desc(options.y_label.clone().unwrap_or_default())
+        .axis_desc_style((FONT, 14))
+        .draw()
+        .map_err(err)?;
+    if has_right {
+        chart
+            .configure_secondary_axes()
+            .label_style((FONT, 12))
+            .draw()
+            .map_err(err)?;
+    }
+
+    let bars = plot.series.iter().filter(|s| !s.line).count();
+    let width = 0.8 / bars.max(1) as f64;
+    let mut bar = 0;
+    for (series, color) in plot.series.iter().zip(colors) {
+        let legend =
+            move |(x, y): (i32, i32)| Rectangle::new([(x, y - 5), (x + 12, y + 5)], color.filled());
+        if series.line {
+            let lines = segments(&series.values)
+                .into_iter()
+                .map(|points| PathElement::new(points, color.stroke_width(2)));
+            let points = series
+                .values
+                .iter()
+                .enumerate()
+                .filter_map(|(i, v)| v.map(|v| Circle::new((i as f64, v), 3, color.filled())));
+            let annotation = if series.right {
+                chart.draw_secondary_series(lines).map_err(err)?;
+                chart.draw_secondary_series(points).map_err(err)?
+            } else {
+                chart.draw_series(lines).map_err(err)?;
+                chart.draw_series(points).map_err(err)?
+            };
+            annotation.label(&series.label).legend(legend);
+        } else {
+            let offset = -0.4 + bar as f64 * width;
+            bar += 1;
+            let rectangles = series.values.iter().enumerate().filter_map(|(i, v)| {
+                v.map(|v| {
+                    let x = i as f64 + offset;
+                    Rectangle::new([(x, 0.0), (x + width, v)], color.filled())
+                })
+            });
+            let annotation = if series.right {
+                chart.draw_secondary_series(rectangles).map_err(err)?
+            } else {
+                chart.draw_series(rectangles).map_err(err)?
+            };
+            annotation.label(&series.label).legend(legend);
+        }
+    }
+    if plot.series.len() > 1 {
+        chart
+            .configure_series_labels()
+            .label_font((FONT, 12))
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .position(SeriesLabelPosition::UpperRight)
+            .draw()
+            .map_err(err)?;
+    }
+    root.present().map_err(err)
+}
+
+/// `plot` drawn as an SVG document or a PNG image, as `options` say.
+pub fn render(plot: &Plot, options: &RenderOptions) -> Result<Vec<u8>, String> {
+    FONT_REGISTERED.clone()?;
+    let width = options.width.unwrap_or(800);
+    let height = options.height.unwrap_or(450);
+    if !(100..=MAX_SIZE).contains(&width) || !(100..=MAX_SIZE).contains(&height) {
+        return Err(format!(
+            "Width and height must be between 100 and {} pixels",
+            MAX_SIZE
+        ));
+    }
+    if plot.labels.is_empty() {
+        return Err("Nothing to render".to_string());
+    }
+    match options.format {
+        ImageFormat::Svg => {
+            let mut svg = String::new();
+            {
+                let root = SVGBackend::with_string(&mut svg, (width, height)).into_drawing_area();
+                draw(&root, plot, options)?;
+            }
+            Ok(svg.into_bytes())
+        }
+        ImageFormat::Png => {
+            let mut pixels = vec![0u8; (width * height * 3) as usize];
+            {
+                let root =
+                    BitMapBackend::with_buffer(&mut pixels, (width, height)).into_drawing_area();
+                draw(&root, plot, options)?;
+            }
+            let image = image::RgbImage::from_raw(width, height, pixels)
+                .ok_or("The image buffer has the wrong size")?;
+            let mut png = Cursor::new(vec![]);
+            image
+                .write_to(&mut png, image::ImageOutputFormat::Png)
+                .map_err(|e| e.to_string())?;
+            Ok(png.into_inner())
+        }
+    }
+}
+
+/// Image of the `/pivot-chart` answer to `request.chart`.
+pub fn render_pivot_chart(request: RenderRequest<PivotData>) -> Result<Vec<u8>, String> {
+    let mut chart = request.chart;
+    chart.vega_lite = None;
+    let x_label = chart.x_column.clone();
+    let plot = Plot::from_chart(&pivot_chart(chart), false)?;
+    render(&plot, &with_x_label(request.render, x_label))
+}
+
+/// Image of the `/time-series` answer to `request.chart`, every dataset as a line.
+pub fn render_time_series(request: RenderRequest<TimeSeriesRequest>) -> Result<Vec<u8>, String> {
+    let plot = Plot::from_chart(&market_trend(request.chart)?, true)?;
+    render(&plot, &request.render)
+}
+
+/// Image of the bins of the `/data-distribution` answer to `request.chart`.
+pub fn render_distribution(request: RenderRequest<DataToBinsRequest>) -> Result<Vec<u8>, String> {
+    let chart = request.chart;
+    let data = data_to_bins(
+        &chart.column,
+        chart.filter,
+        chart.all,
+        chart.distribution_type,
+        chart.number_of_bins,
+    )?;
+    let plot = Plot::from_distribution(&data);
+    render(&plot, &with_x_label(request.render, chart.column))
+}
+
+fn with_x_label(options: RenderOptions, x_label: String) -> RenderOptions {
+    RenderOptions {
+        x_label: options.x_label.or(Some(x_label)),
+        ..options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn chart() -> HashMap<String, Value> {
+        HashMap::from([
+            ("labels".to_string(), json!([2019, "2020", "2021"])),
+            (
+                "datasets".to_string(),
+                json!([
+                    { "label": "Listings", "data": [2, 3, null], "type": "bar" },
+                    { "label": "Median", "data": [21_000.5, null, 30_000.0], "type": "line", "yAxisID": "y1" },
+                ]),
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_plot_from_chart() {
+        let plot = Plot::from_chart(&chart(), false).unwrap();
+        assert_eq!(plot.labels, vec!["2019", "2020", "2021"]);
+        assert_eq!(plot.series[0].values, vec![Some(2.0), Some(3.0), None]);
+        assert!(!plot.series[0].line && !plot.series[0].right);
+        assert!(plot.series[1].line && plot.series[1].right);
+        assert!(Plot::from_chart(&chart(), true).unwrap().series[0].line);
+
+        let error = HashMap::from([("error".to_string(), json!("Group is required"))]);
+        assert_eq!(
+            Plot::from_chart(&error, false).unwrap_err(),
+            "Group is required"
+        );
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#ff8000").unwrap(), RGBAColor(255, 128, 0, 1.0));
+        assert_eq!(
+            parse_color("rgba(75, 192, 192, 0.8)").unwrap(),
+            RGBAColor(75, 192, 192, 0.8)
+        );
+        assert!(parse_color("teal").is_err());
+        assert!(parse_color("#aééb").is_err());
+        assert!(parse_color("#+f+f+f").is_err());
+        assert_eq!(segments(&[Some(1.0), None, Some(2.0), Some(3.0)]).len(), 2);
+    }
+
+    #[test]
+    fn test_render() {
+        let plot = Plot::from_chart(&chart(), false).unwrap();
+        let options = RenderOptions {
+            title: Some("Median price".to_string()),
+            ..Default::default()
+        };
+        let svg = String::from_utf8(render(&plot, &options).unwrap()).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("Median price"));
+        assert!(svg.contains("2020"));
+
+        let png = RenderOptions {
+            format: ImageFormat::Png,
+            width: Some(200),
+            height: Some(100),
+            palette: vec!["#000000".to_string()],
+            ..Default::default()
+        };
+        let png = render(&plot, &png).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+
+        let small = RenderOptions {
+            width: Some(10),
+            ..Default::default()
+        };
+        assert!(render(&plot, &small).is_err());
+    }
+}
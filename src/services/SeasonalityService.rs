@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+
+use polars::prelude::LazyFrame;
+use serde_json::{json, Value};
+
+use crate::{
+    model::{AxumAPIModel::TimeSeriesRequest, TimeSeries::Period},
+    VEHICLE_STATIC_DATA,
+};
+
+use super::{
+    TimeSeriesService::{labels, metric_by_group},
+    Utils::generate_colors,
+};
+
+/// Additive decomposition `observed = trend + seasonal + residual`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decomposition {
+    pub observed: Vec<f64>,
+    /// Centred moving average over one season; unknown at both ends.
+    pub trend: Vec<Option<f64>>,
+    pub seasonal: Vec<f64>,
+    pub residual: Vec<Option<f64>>,
+    /// `1 - var(residual) / var(seasonal + residual)`, between 0 (no seasonality) and 1.
+    pub seasonal_strength: f64,
+}
+
+pub fn default_season_length(period: Period) -> usize {
+    match period {
+        Period::Week => 52,
+        Period::Month => 12,
+    }
+}
+
+/// Fills the gaps linearly between the known neighbours and with the nearest known
+/// value at both ends. `None` when no value is known.
+pub fn interpolate(values: &[Option<f64>]) -> Option<Vec<f64>> {
+    let known = values
+        .iter()
+        .enumerate()
+        .filter_map(|(i, v)| v.map(|v| (i, v)))
+        .collect::<Vec<_>>();
+    let (first, last) = (known.first()?, known.last()?);
+    Some(
+        (0..values.len())
+            .map(|i| {
+                if let Some(v) = values[i] {
+                    return v;
+                }
+                if i < first.0 {
+                    return first.1;
+                }
+                if i > last.0 {
+                    return last.1;
+                }
+                let after = known.iter().position(|(k, _)| *k > i).unwrap();
+                let ((i0, v0), (i1, v1)) = (known[after - 1], known[after]);
+                v0 + (v1 - v0) * (i - i0) as f64 / (i1 - i0) as f64
+            })
+            .collect(),
+    )
+}
+
+/// Centred moving average over `length` values; a 2×`length` average for even lengths.
+fn centred_moving_average(values: &[f64], length: usize) -> Vec<Option<f64>> {
+    let half = length / 2;
+    (0..values.len())
+        .map(|i| {
+            if i < half || i + half >= values.len() {
+                return None;
+            }
+            if !length.is_multiple_of(2) {
+                let window = &values[i - half..=i + half];
+                return Some(window.iter().sum::<f64>() / length as f64);
+            }
+            let inner = values[i - half + 1..i + half].iter().sum::<f64>();
+            let outer = (values[i - half] + values[i + half]) / 2.0;
+            Some((inner + outer) / length as f64)
+        })
+        .collect()
+}
+
+fn variance(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64
+}
+
+/// Classical additive decomposition; at least two full seasons are required.
+pub fn decompose(values: &[Option<f64>], season_length: usize) -> Result<Decomposition, String> {
+    if season_length < 2 {
+        return Err("Season length must be at least 2".to_string());
+    }
+    if values.len() < 2 * season_length {
+        return Err(format!(
+            "At least {} periods are required for a season of {}, got {}",
+            2 * season_length,
+            season_length,
+            values.len()
+        ));
+    }
+    let observed = interpolate(values).ok_or("No data found")?;
+    let trend = centred_moving_average(&observed, season_length);
+
+    let mut sums = vec![0.0; season_length];
+    let mut counts = vec![0usize; season_length];
+    for (i, t) in trend.iter().enumerate() {
+        if let Some(t) = t {
+            sums[i % season_length] += observed[i] - t;
+            counts[i % season_length] += 1;
+        }
+    }
+    let mut indices = sums
+        .iter()
+        .zip(counts.iter())
+        .map(|(sum, count)| if *count > 0 { sum / *count as f64 } else { 0.0 })
+        .collect::<Vec<_>>();
+    // Seasonal indices sum to zero so that the trend keeps the level.
+    let mean = indices.iter().sum::<f64>() / season_length as f64;
+    indices.iter_mut().for_each(|index| *index -= mean);
+
+    let seasonal = (0..observed.len())
+        .map(|i| indices[i % season_length])
+        .collect::<Vec<_>>();
+    let residual = (0..observed.len())
+        .map(|i| trend[i].map(|t| observed[i] - t - seasonal[i]))
+        .collect::<Vec<_>>();
+
+    let (residuals, detrended): (Vec<f64>, Vec<f64>) = residual
+        .iter()
+        .enumerate()
+        .filter_map(|(i, r)| r.map(|r| (r, r + seasonal[i])))
+        .unzip();
+    let detrended_variance = variance(&detrended);
+    let seasonal_strength = if detrended_variance > 0.0 {
+        (1.0 - variance(&residuals) / detrended_variance).max(0.0)
+    } else {
+        0.0
+    };
+    Ok(Decomposition {
+        observed,
+        trend,
+        seasonal,
+        residual,
+        seasonal_strength,
+    })
+}
+
+/// Trend, seasonal and residual component of `request.metric` for every group.
+/// Groups with too few periods report an `error` instead of the components.
+pub fn decomposition_series(
+    data: LazyFrame,
+    request: &TimeSeriesRequest,
+) -> Result<HashMap<String, Value>, String> {
+    let series = metric_by_group(data, request)?;
+    let season_length = request
+        .season_length
+        .unwrap_or_else(|| default_season_length(request.period));
+    let colors = generate_colors(series.series.len());
+    let datasets = series
+        .series
+        .iter()
+        .zip(colors)
+        .map(
+            |((group, values), color)| match decompose(values, season_length) {
+                Ok(decomposition) => json!({
+                    "label": group,
+                    "data": decomposition.observed,
+                    "trend": decomposition.trend,
+                    "seasonal": decomposition.seasonal,
+                    "residual": decomposition.residual,
+                    "seasonalStrength": decomposition.seasonal_strength,
+                    "borderColor": color,
+                    "backgroundColor": color,
+                }),
+                Err(err) => json!({
+                    "label": group,
+                    "data": values,
+                    "error": err,
+                    "borderColor": color,
+                    "backgroundColor": color,
+                }),
+            },
+        )
+        .collect::<Vec<_>>();
+
+    let mut json_map = HashMap::new();
+    json_map.insert(
+        "labels".to_string(),
+        json!(labels(&series.periods, request.period)),
+    );
+    json_map.insert("seasonLength".to_string(), json!(season_length));
+    json_map.insert("datasets".to_string(), Value::Array(datasets));
+    Ok(json_map)
+}
+
+pub fn seasonal_decomposition(
+    request: TimeSeriesRequest,
+) -> Result<HashMap<String, Value>, String> {
+    decomposition_series(VEHICLE_STATIC_DATA.clone(), &request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpolate() {
+        assert_eq!(
+            interpolate(&[None, Some(2.0), None, None, Some(8.0), None]),
+            Some(vec![2.0, 2.0, 4.0, 6.0, 8.0, 8.0])
+        );
+        assert_eq!(interpolate(&[None, None]), None);
+    }
+
+    #[test]
+    fn test_centred_moving_average() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        assert_eq!(
+            centred_moving_average(&values, 3),
+            vec![None, Some(2.0), Some(3.0), Some(4.0), Some(5.0), None]
+        );
+        // 2x4 average: (1/2 + 2 + 3 + 4 + 5/2) / 4
+        assert_eq!(
+            centred_moving_average(&values, 4),
+            vec![None, None, Some(3.0), Some(4.0), None, None]
+        );
+    }
+
+    #[test]
+    fn test_decompose_recovers_season() {
+        let season = [10.0, -5.0, -5.0, 0.0];
+        let values = (0..16)
+            .map(|i| Some(100.0 + 2.0 * i as f64 + season[i % 4]))
+            .collect::<Vec<_>>();
+        let decomposition = decompose(&values, 4).unwrap();
+        for (i, s) in season.iter().enumerate() {
+            assert!((decomposition.seasonal[i] - s).abs() < 1e-9);
+        }
+        assert!((decomposition.trend[5].unwrap() - 110.0).abs() < 1e-9);
+        assert!(decomposition.residual[5].unwrap().abs() < 1e-9);
+        assert_eq!(decomposition.residual[0], None);
+        assert!(decomposition.seasonal_strength > 0.99);
+
+        assert!(decompose(&values[..7], 4).is_err());
+    }
+
+    #[test]
+    fn test_no_seasonality() {
+        // A spike every 5 periods does not repeat with a season of 12.
+        let values = (0..120)
+            .map(|i| Some(if i % 5 == 0 { 3.0 } else { 1.0 } + i as f64))
+            .collect::<Vec<_>>();
+        let decomposition = decompose(&values, 12).unwrap();
+        assert!(decomposition.seasonal_strength < 0.5);
+    }
+}
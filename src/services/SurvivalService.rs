@@ -0,0 +1,335 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{NaiveDate, Utc};
+use polars::{
+    frame::DataFrame,
+    prelude::{col, DataType, LazyFrame},
+};
+
+use crate::{
+    model::{
+        AxumAPIModel::TimeOnMarketRequest,
+        Survival::{SurvivalCurve, SurvivalPoint, TimeOnMarket},
+    },
+    VEHICLE_STATIC_DATA,
+};
+
+use super::Utils::{filter_listings, generate_colors};
+
+/// Splits the curves by the price bands of the request.
+pub const PRICE_BAND: &str = "price_band";
+pub const DEFAULT_PRICE_BANDS: [i32; 5] = [5_000, 10_000, 20_000, 30_000, 50_000];
+/// Curves beyond this many segments (the smallest ones) are left out.
+const MAX_CURVES: usize = 12;
+
+/// Days on the market and whether the listing was sold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Observation {
+    pub days: i32,
+    pub sold: bool,
+}
+
+/// Kaplan–Meier estimate of the share of listings still unsold; unsold listings are
+/// censored at their current time on the market.
+pub fn kaplan_meier(observations: &[Observation]) -> (Vec<SurvivalPoint>, Option<i32>) {
+    let mut days: BTreeMap<i32, (usize, usize)> = BTreeMap::new();
+    for observation in observations {
+        let (sold, censored) = days.entry(observation.days).or_default();
+        if observation.sold {
+            *sold += 1;
+        } else {
+            *censored += 1;
+        }
+    }
+    let mut at_risk = observations.len();
+    let mut survival = 1.0;
+    let mut median = None;
+    let mut points = vec![];
+    for (day, (sold, censored)) in days {
+        if sold > 0 {
+            survival *= 1.0 - sold as f64 / at_risk as f64;
+        }
+        if median.is_none() && survival <= 0.5 {
+            median = Some(day);
+        }
+        points.push(SurvivalPoint {
+            day,
+            at_risk,
+            sold,
+            censored,
+            survival,
+        });
+        at_risk -= sold + censored;
+    }
+    (points, median)
+}
+
+/// Survival on `day`: the estimate of the last point on or before it.
+fn survival_at(points: &[SurvivalPoint], day: i32) -> f64 {
+    points
+        .iter()
+        .take_while(|p| p.day <= day)
+        .last()
+        .map(|p| p.survival)
+        .unwrap_or(1.0)
+}
+
+fn price_band(price: Option<i32>, bands: &[i32]) -> String {
+    let Some(price) = price else {
+        return "Unknown".to_string();
+    };
+    let mut from = 0;
+    for to in bands {
+        if price < *to {
+            return format!("{}-{}", from, to);
+        }
+        from = *to;
+    }
+    format!("{}+", from)
+}
+
+fn observations(
+    data: &DataFrame,
+    request: &TimeOnMarketRequest,
+    today: NaiveDate,
+) -> Result<Vec<(String, Observation)>, String> {
+    let ints = |name: &str| -> Result<Vec<Option<i32>>, String> {
+        data.column(name)
+            .and_then(|c| c.cast(&DataType::Int32))
+            .map(|c| c.i32().unwrap().to_vec())
+            .map_err(|e| e.to_string())
+    };
+    let dates = |name: &str| -> Result<Vec<Option<NaiveDate>>, String> {
+        data.column(name)
+            .and_then(|c| c.date().map(|d| d.as_date_iter().collect()))
+            .map_err(|e| e.to_string())
+    };
+    let days_in_sale = ints("days_in_sale")?;
+    let created_on = dates("created_on")?;
+    let sold_date = dates("sold_date")?;
+    let labels = match request.group.as_deref() {
+        None => vec![Some("All".to_string()); data.height()],
+        Some(PRICE_BAND) => {
+            let bands = if request.price_bands.is_empty() {
+                DEFAULT_PRICE_BANDS.to_vec()
+            } else {
+                request.price_bands.clone()
+            };
+            ints("price_in_eur")?
+                .into_iter()
+                .map(|p| Some(price_band(p, &bands)))
+                .collect()
+        }
+        Some(group) => data
+            .column(group)
+            .and_then(|c| c.cast(&DataType::String))
+            .map(|c| {
+                c.str()
+                    .unwrap()
+                    .into_iter()
+                    .map(|v| v.map(|v| v.to_string()))
+                    .collect()
+            })
+            .map_err(|e| e.to_string())?,
+    };
+
+    let mut result = vec![];
+    for i in 0..data.height() {
+        let sold = sold_date[i].is_some();
+        let days = match (days_in_sale[i], created_on[i], sold_date[i]) {
+            (Some(days), _, _) => days,
+            (None, Some(created), Some(sold)) => (sold - created).num_days() as i32,
+            (None, Some(created), None) => (today - created).num_days() as i32,
+            _ => continue,
+        };
+        let Some(label) = labels[i].clone() else {
+            continue;
+        };
+        if days < 0 {
+            continue;
+        }
+        let observation = match request.max_days {
+            Some(max) if days > max => Observation {
+                days: max,
+                sold: false,
+            },
+            _ => Observation { days, sold },
+        };
+        result.push((label, observation));
+    }
+    Ok(result)
+}
+
+/// Kaplan–Meier time-to-sale curves of the listings in `data`, one per segment.
+pub fn survival_curves(
+    data: LazyFrame,
+    request: &TimeOnMarketRequest,
+    today: NaiveDate,
+) -> Result<TimeOnMarket, String> {
+    let mut columns = vec![
+        col("days_in_sale"),
+        col("created_on"),
+        col("sold_date"),
+        col("price_in_eur"),
+    ];
+    if let Some(group) = request.group.as_deref().filter(|g| *g != PRICE_BAND) {
+        columns.push(col(group));
+    }
+    let data = filter_listings(data, &request.filter)
+        .select(columns)
+        .collect()
+        .map_err(|e| e.to_string())?;
+
+    let mut segments: BTreeMap<String, Vec<Observation>> = BTreeMap::new();
+    for (label, observation) in observations(&data, request, today)? {
+        segments.entry(label).or_default().push(observation);
+    }
+    if segments.is_empty() {
+        return Err("No data found".to_string());
+    }
+    let mut segments = segments.into_iter().collect::<Vec<_>>();
+    if request.group.as_deref() == Some(PRICE_BAND) {
+        segments.sort_by_key(|(label, _)| {
+            label
+                .split(['-', '+'])
+                .next()
+                .and_then(|from| from.parse::<i32>().ok())
+                .unwrap_or(i32::MAX)
+        });
+    } else {
+        segments.sort_by_key(|(_, listings)| std::cmp::Reverse(listings.len()));
+    }
+    segments.truncate(MAX_CURVES);
+
+    let colors = generate_colors(segments.len());
+    let mut curves = vec![];
+    let mut days = BTreeSet::from([0]);
+    for ((label, observations), color) in segments.into_iter().zip(colors) {
+        let (points, median_days) = kaplan_meier(&observations);
+        days.extend(points.iter().filter(|p| p.sold > 0).map(|p| p.day));
+        let sold = observations.iter().filter(|o| o.sold).count();
+        curves.push(SurvivalCurve {
+            label,
+            border_color: color,
+            count: observations.len(),
+            sold,
+            censored: observations.len() - sold,
+            median_days,
+            points,
+            ..Default::default()
+        });
+    }
+    let labels = days.into_iter().collect::<Vec<_>>();
+    for curve in curves.iter_mut() {
+        curve.data = labels
+            .iter()
+            .map(|day| survival_at(&curve.points, *day))
+            .collect();
+    }
+    Ok(TimeOnMarket {
+        labels,
+        datasets: curves,
+    })
+}
+
+pub fn time_on_market(request: TimeOnMarketRequest) -> Result<TimeOnMarket, String> {
+    survival_curves(
+        VEHICLE_STATIC_DATA.clone(),
+        &request,
+        Utc::now().date_naive(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::{
+        df,
+        prelude::{IntoLazy, NamedFrom, Series},
+    };
+
+    use super::*;
+    use crate::services::TestUtils::date;
+
+    fn observation(days: i32, sold: bool) -> Observation {
+        Observation { days, sold }
+    }
+
+    #[test]
+    fn test_kaplan_meier() {
+        // Sold after 5, 10, 10 and 30 days; unsold after 8 and 40 days.
+        let observations = [
+            observation(5, true),
+            observation(8, false),
+            observation(10, true),
+            observation(10, true),
+            observation(30, true),
+            observation(40, false),
+        ];
+        let (points, median) = kaplan_meier(&observations);
+        let survival = points.iter().map(|p| p.survival).collect::<Vec<_>>();
+        // 5/6, unchanged at the censoring, 5/6 * 2/4, 5/6 * 2/4 * 1/2.
+        assert_eq!(
+            points.iter().map(|p| p.day).collect::<Vec<_>>(),
+            vec![5, 8, 10, 30, 40]
+        );
+        assert!((survival[0] - 5.0 / 6.0).abs() < 1e-9);
+        assert!((survival[1] - 5.0 / 6.0).abs() < 1e-9);
+        assert!((survival[2] - 5.0 / 12.0).abs() < 1e-9);
+        assert!((survival[3] - 5.0 / 24.0).abs() < 1e-9);
+        assert_eq!(points[2].at_risk, 4);
+        assert_eq!(median, Some(10));
+        assert_eq!(survival_at(&points, 20), survival[2]);
+        assert_eq!(survival_at(&points, 1), 1.0);
+    }
+
+    #[test]
+    fn test_survival_curves_by_price_band() {
+        let sold = Series::new(
+            "sold_date".into(),
+            [
+                Some(date("2024-01-11")),
+                Some(date("2024-01-21")),
+                None,
+                Some(date("2024-01-06")),
+                None,
+            ],
+        );
+        let mut listings = df!(
+            "make" => ["BMW"; 5],
+            "model" => ["320"; 5],
+            "title" => [""; 5],
+            "equipment" => [""; 5],
+            "engine" => ["Diesel"; 5],
+            "days_in_sale" => [Some(10), None, None, Some(5), Some(3)],
+            "created_on" => [date("2024-01-01"); 5],
+            "price_in_eur" => [8_000, 9_000, 15_000, 25_000, 26_000]
+        )
+        .unwrap();
+        listings.with_column(sold).unwrap();
+        let request = TimeOnMarketRequest {
+            group: Some(PRICE_BAND.to_string()),
+            price_bands: vec![10_000, 20_000],
+            max_days: Some(60),
+            ..Default::default()
+        };
+        let result = survival_curves(listings.lazy(), &request, date("2024-03-01")).unwrap();
+        let labels = result
+            .datasets
+            .iter()
+            .map(|c| c.label.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec!["0-10000", "10000-20000", "20000+"]);
+        assert_eq!(result.labels, vec![0, 5, 10, 20]);
+        let cheap = &result.datasets[0];
+        assert_eq!((cheap.sold, cheap.censored), (2, 0));
+        assert_eq!(cheap.median_days, Some(10));
+        assert_eq!(cheap.data, vec![1.0, 1.0, 0.5, 0.0]);
+        // Unsold for 60 days: censored at max_days.
+        let middle = &result.datasets[1];
+        assert_eq!(middle.points[0].day, 60);
+        assert_eq!(middle.median_days, None);
+        // The listing still for sale after 3 days leaves one at risk when the other sells.
+        assert_eq!(result.datasets[2].data, vec![1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(result.datasets[2].points[0].censored, 1);
+    }
+}
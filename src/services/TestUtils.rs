@@ -0,0 +1,24 @@
+use chrono::NaiveDate;
+use polars::{frame::DataFrame, prelude::DataType};
+
+pub fn date(d: &str) -> NaiveDate {
+    NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap()
+}
+
+/// `value` rounded to six decimals, so that computed ratios compare equal.
+pub fn rounded(value: f64) -> f64 {
+    (value * 1e6).round() / 1e6
+}
+
+/// The values of the column `name` as floats rounded to six decimals.
+pub fn rounded_column(df: &DataFrame, name: &str) -> Vec<Option<f64>> {
+    df.column(name)
+        .unwrap()
+        .cast(&DataType::Float64)
+        .unwrap()
+        .f64()
+        .unwrap()
+        .into_iter()
+        .map(|v| v.map(rounded))
+        .collect()
+}
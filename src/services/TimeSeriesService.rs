@@ -0,0 +1,368 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::NaiveDate;
+use polars::{
+    frame::DataFrame,
+    prelude::{col, DataType, LazyFrame},
+};
+use serde_json::{json, Value};
+
+use crate::{
+    model::{
+        AxumAPIModel::{StatisticSearchPayload, TimeSeriesRequest},
+        TimeSeries::{Period, TrendMetric},
+    },
+    VEHICLE_STATIC_DATA,
+};
+
+use super::{
+    ChartTransform::apply_all,
+    Utils::{filter_listings, generate_colors},
+};
+
+/// Series beyond this many groups (the smallest ones) are left out.
+const MAX_SERIES: usize = 10;
+
+/// A listing reduced to what the trends need.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Listing {
+    pub group: String,
+    pub created_on: NaiveDate,
+    pub sold_date: Option<NaiveDate>,
+    pub price: Option<i32>,
+}
+
+fn median(values: &mut [i32]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable();
+    let middle = values.len() / 2;
+    Some(if values.len().is_multiple_of(2) {
+        (values[middle - 1] as f64 + values[middle] as f64) / 2.0
+    } else {
+        values[middle] as f64
+    })
+}
+
+/// Period starts from the first listing to the last creation or sale.
+pub fn periods(listings: &[Listing], period: Period) -> Vec<NaiveDate> {
+    let first = listings.iter().map(|l| l.created_on).min();
+    let last = listings
+        .iter()
+        .flat_map(|l| [Some(l.created_on), l.sold_date])
+        .flatten()
+        .max();
+    let (Some(first), Some(last)) = (first, last) else {
+        return vec![];
+    };
+    let mut periods = vec![];
+    let mut start = period.start(first);
+    while start <= last {
+        periods.push(start);
+        start = period.next(start);
+    }
+    periods
+}
+
+/// Value of `metric` in every period of `periods`.
+pub fn metric_series(
+    listings: &[&Listing],
+    periods: &[NaiveDate],
+    period: Period,
+    metric: TrendMetric,
+) -> Vec<Option<f64>> {
+    let index: HashMap<NaiveDate, usize> =
+        periods.iter().enumerate().map(|(i, p)| (*p, i)).collect();
+    let position = |date: NaiveDate| index.get(&period.start(date)).copied();
+    let mut counts = vec![0i64; periods.len()];
+    match metric {
+        TrendMetric::NewListings => {
+            for i in listings.iter().filter_map(|l| position(l.created_on)) {
+                counts[i] += 1;
+            }
+        }
+        TrendMetric::Sold => {
+            for i in listings
+                .iter()
+                .filter_map(|l| l.sold_date.and_then(position))
+            {
+                counts[i] += 1;
+            }
+        }
+        TrendMetric::MedianPrice => {
+            let mut prices = vec![vec![]; periods.len()];
+            for listing in listings {
+                if let (Some(i), Some(price)) = (position(listing.created_on), listing.price) {
+                    prices[i].push(price);
+                }
+            }
+            return prices.iter_mut().map(|p| median(p)).collect();
+        }
+        TrendMetric::ActiveInventory => {
+            // +1 in the period of the listing, -1 in the period it was sold in.
+            for listing in listings {
+                if let Some(i) = position(listing.created_on) {
+                    counts[i] += 1;
+                }
+                if let Some(i) = listing.sold_date.and_then(position) {
+                    counts[i] -= 1;
+                }
+            }
+            let mut active = 0;
+            for count in counts.iter_mut() {
+                active += *count;
+                *count = active;
+            }
+        }
+    }
+    counts.into_iter().map(|c| Some(c as f64)).collect()
+}
+
+/// Trailing mean over `window` periods of the known values.
+pub fn rolling_mean(values: &[Option<f64>], window: usize) -> Vec<Option<f64>> {
+    (0..values.len())
+        .map(|i| {
+            let from = (i + 1).saturating_sub(window.max(1));
+            let known = values[from..=i].iter().flatten().collect::<Vec<_>>();
+            if known.is_empty() {
+                None
+            } else {
+                Some(known.iter().copied().sum::<f64>() / known.len() as f64)
+            }
+        })
+        .collect()
+}
+
+/// Change to the previous period in percent.
+pub fn period_change(values: &[Option<f64>]) -> Vec<Option<f64>> {
+    (0..values.len())
+        .map(
+            |i| match (i.checked_sub(1).and_then(|p| values[p]), values[i]) {
+                (Some(previous), Some(value)) if previous != 0.0 => {
+                    Some((value - previous) * 100.0 / previous)
+                }
+                _ => None,
+            },
+        )
+        .collect()
+}
+
+fn listings(data: &DataFrame, group: Option<&str>) -> Result<Vec<Listing>, String> {
+    let dates = |name: &str| -> Result<Vec<Option<NaiveDate>>, String> {
+        data.column(name)
+            .and_then(|c| c.date().map(|d| d.as_date_iter().collect()))
+            .map_err(|e| e.to_string())
+    };
+    let created_on = dates("created_on")?;
+    let sold_date = dates("sold_date")?;
+    let prices = data
+        .column("price_in_eur")
+        .and_then(|c| c.cast(&DataType::Int32))
+        .map(|c| c.i32().unwrap().to_vec())
+        .map_err(|e| e.to_string())?;
+    let groups: Vec<Option<String>> = match group {
+        Some(group) => data
+            .column(group)
+            .and_then(|c| c.cast(&DataType::String))
+            .map(|c| {
+                c.str()
+                    .unwrap()
+                    .into_iter()
+                    .map(|v| v.map(|v| v.to_string()))
+                    .collect()
+            })
+            .map_err(|e| e.to_string())?,
+        None => vec![Some("All".to_string()); data.height()],
+    };
+    Ok((0..data.height())
+        .filter_map(|i| {
+            Some(Listing {
+                group: groups[i].clone()?,
+                created_on: created_on[i]?,
+                sold_date: sold_date[i],
+                price: prices[i].filter(|p| *p > 0),
+            })
+        })
+        .collect())
+}
+
+/// Values of `request.metric` per period for every group (largest groups first).
+pub struct TrendSeries {
+    pub periods: Vec<NaiveDate>,
+    pub series: Vec<(String, Vec<Option<f64>>)>,
+}
+
+/// The listings in `data` matching `filter`, split by the `group` column.
+pub fn matching_listings(
+    data: LazyFrame,
+    filter: &StatisticSearchPayload,
+    group: Option<&str>,
+) -> Result<Vec<Listing>, String> {
+    let mut columns = vec![col("created_on"), col("sold_date"), col("price_in_eur")];
+    if let Some(group) = group {
+        columns.push(col(group));
+    }
+    let data = filter_listings(data, filter)
+        .select(columns)
+        .collect()
+        .map_err(|e| e.to_string())?;
+    listings(&data, group)
+}
+
+/// Computes the series of `metric` per `period` of `listings`, one per group.
+pub fn series_by_group(
+    listings: &[Listing],
+    period: Period,
+    metric: TrendMetric,
+) -> Result<TrendSeries, String> {
+    let periods = periods(listings, period);
+    if periods.is_empty() {
+        return Err("No data found".to_string());
+    }
+
+    let mut groups: BTreeMap<&str, Vec<&Listing>> = BTreeMap::new();
+    for listing in listings.iter() {
+        groups.entry(&listing.group).or_default().push(listing);
+    }
+    let mut groups = groups.into_iter().collect::<Vec<_>>();
+    groups.sort_by_key(|(_, listings)| std::cmp::Reverse(listings.len()));
+    groups.truncate(MAX_SERIES);
+    let series = groups
+        .into_iter()
+        .map(|(group, listings)| {
+            let values = metric_series(&listings, &periods, period, metric);
+            (group.to_string(), values)
+        })
+        .collect();
+    Ok(TrendSeries { periods, series })
+}
+
+/// Computes the series of `request.metric` of the listings in `data` matching `request.filter`.
+pub fn metric_by_group(
+    data: LazyFrame,
+    request: &TimeSeriesRequest,
+) -> Result<TrendSeries, String> {
+    let listings = matching_listings(data, &request.filter, request.group.as_deref())?;
+    series_by_group(&listings, request.period, request.metric)
+}
+
+/// Chart.js-ready trend of `request.metric` per period: `labels` are the periods and
+/// every dataset carries its `data` plus, if requested, `rolling` and `change`.
+pub fn trend_series(
+    data: LazyFrame,
+    request: &TimeSeriesRequest,
+) -> Result<HashMap<String, Value>, String> {
+    let mut trend = metric_by_group(data, request)?;
+    let period_labels = labels(&trend.periods, request.period);
+    let mut values = trend
+        .series
+        .iter()
+        .map(|(_, values)| values.clone())
+        .collect::<Vec<_>>();
+    apply_all(&request.transforms, &period_labels, &mut values)?;
+    for ((_, series), values) in trend.series.iter_mut().zip(values) {
+        *series = values;
+    }
+    let colors = generate_colors(trend.series.len());
+    let datasets = trend
+        .series
+        .iter()
+        .zip(colors)
+        .map(|((group, values), color)| {
+            let mut dataset = json!({
+                "label": group,
+                "data": values,
+                "borderColor": color,
+                "backgroundColor": color,
+            });
+            if let Some(window) = request.rolling {
+                dataset["rolling"] = json!(rolling_mean(values, window));
+            }
+            if request.change {
+                dataset["change"] = json!(period_change(values));
+            }
+            dataset
+        })
+        .collect::<Vec<_>>();
+
+    let mut json_map = HashMap::new();
+    json_map.insert("labels".to_string(), json!(period_labels));
+    json_map.insert("datasets".to_string(), Value::Array(datasets));
+    Ok(json_map)
+}
+
+pub fn labels(periods: &[NaiveDate], period: Period) -> Vec<String> {
+    periods.iter().map(|p| period.label(*p)).collect()
+}
+
+pub fn market_trend(request: TimeSeriesRequest) -> Result<HashMap<String, Value>, String> {
+    trend_series(VEHICLE_STATIC_DATA.clone(), &request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::TestUtils::date;
+
+    fn listing(created_on: &str, sold_date: Option<&str>, price: i32) -> Listing {
+        Listing {
+            group: "BMW".to_string(),
+            created_on: date(created_on),
+            sold_date: sold_date.map(date),
+            price: Some(price),
+        }
+    }
+
+    #[test]
+    fn test_periods() {
+        assert_eq!(Period::Week.start(date("2024-05-02")), date("2024-04-29"));
+        assert_eq!(Period::Week.label(date("2024-04-29")), "2024-W18");
+        assert_eq!(Period::Month.next(date("2024-01-01")), date("2024-02-01"));
+        let listings = [listing("2024-01-15", Some("2024-03-02"), 10_000)];
+        let months = periods(&listings, Period::Month);
+        assert_eq!(
+            months,
+            vec![date("2024-01-01"), date("2024-02-01"), date("2024-03-01")]
+        );
+    }
+
+    #[test]
+    fn test_metric_series() {
+        let listings = [
+            listing("2024-01-05", Some("2024-02-10"), 10_000),
+            listing("2024-01-20", None, 14_000),
+            listing("2024-02-03", Some("2024-02-25"), 12_000),
+            listing("2024-03-01", None, 20_000),
+        ];
+        let periods = periods(&listings, Period::Month);
+        let listings = listings.iter().collect::<Vec<_>>();
+        let series = |metric| metric_series(&listings, &periods, Period::Month, metric);
+        assert_eq!(
+            series(TrendMetric::NewListings),
+            vec![Some(2.0), Some(1.0), Some(1.0)]
+        );
+        assert_eq!(
+            series(TrendMetric::Sold),
+            vec![Some(0.0), Some(2.0), Some(0.0)]
+        );
+        assert_eq!(
+            series(TrendMetric::MedianPrice),
+            vec![Some(12_000.0), Some(12_000.0), Some(20_000.0)]
+        );
+        assert_eq!(
+            series(TrendMetric::ActiveInventory),
+            vec![Some(2.0), Some(1.0), Some(2.0)]
+        );
+    }
+
+    #[test]
+    fn test_rolling_mean_and_change() {
+        let values = [Some(10.0), Some(20.0), None, Some(40.0)];
+        assert_eq!(
+            rolling_mean(&values, 2),
+            vec![Some(10.0), Some(15.0), Some(20.0), Some(40.0)]
+        );
+        assert_eq!(period_change(&values), vec![None, Some(100.0), None, None]);
+    }
+}
@@ -0,0 +1,176 @@
+use polars::prelude::{
+    col, lit, when, DataType, JoinArgs, JoinType, LazyFrame, SortMultipleOptions,
+};
+
+use crate::model::{AxumAPIModel::StatisticSearchPayload, Chart::TopN};
+
+use super::Utils::get_aggregator;
+
+const RANK: &str = "top_n_rank";
+const KEPT: &str = "top_n_kept";
+
+/// The `limit` values of `top.column` with the largest (or smallest) metric.
+fn ranking(df: LazyFrame, top: &TopN, stat_column: &str) -> Result<LazyFrame, String> {
+    if top.limit == 0 {
+        return Err(format!("Top N of '{}' needs a limit above 0", top.column));
+    }
+    let metric = get_aggregator(top.by_column.as_deref().unwrap_or(stat_column), top.by_or())
+        .ok_or_else(|| format!("Unknown aggregator '{}'", top.by_or()))?;
+    Ok(df
+        .filter(col(&top.column).is_not_null())
+        .group_by([col(&top.column)])
+        .agg([metric.alias(RANK)])
+        .sort(
+            [RANK, top.column.as_str()],
+            SortMultipleOptions::new()
+                .with_order_descending_multi([!top.bottom, false])
+                .with_nulls_last(true),
+        )
+        .limit(top.limit as u32)
+        .select([col(&top.column), lit(true).alias(KEPT)]))
+}
+
+/// Listings of `df` with every value of a `top` column outside its top values replaced
+/// by the other label (or left out), so that aggregating afterwards gives the bucket's
+/// real median, mean or count instead of a sum of the aggregates of its values.
+pub fn collapse_top_n(df: LazyFrame, top: &[TopN], stat_column: &str) -> Result<LazyFrame, String> {
+    let mut df = df;
+    for top in top.iter() {
+        let kept = ranking(df.clone(), top, stat_column)?;
+        let column = col(&top.column);
+        df = df.join(
+            kept,
+            [column.clone()],
+            [column.clone()],
+            JoinArgs::new(JoinType::Left),
+        );
+        df = if top.drop_other {
+            df.filter(col(KEPT).is_not_null())
+        } else {
+            df.with_column(
+                when(col(KEPT).is_not_null())
+                    .then(column.cast(DataType::String))
+                    .otherwise(lit(top.other_label_or()))
+                    .alias(&top.column),
+            )
+        }
+        .drop([KEPT]);
+    }
+    Ok(df)
+}
+
+/// [`collapse_top_n`] with the `topN` of `search`, each of which has to be in `group`.
+pub fn with_top_n(
+    df: LazyFrame,
+    search: &StatisticSearchPayload,
+    group: &[String],
+    stat_column: &str,
+) -> Result<LazyFrame, String> {
+    if let Some(top) = search.topN.iter().find(|t| !group.contains(&t.column)) {
+        return Err(format!("Top N column '{}' is not grouped by", top.column));
+    }
+    collapse_top_n(df, &search.topN, stat_column)
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::{df, frame::DataFrame, prelude::IntoLazy};
+
+    use super::*;
+
+    /// Five Golfs, three Passats, two Polos and one Up, the Up the most expensive.
+    fn listings() -> LazyFrame {
+        df!(
+            "make" => ["VW"; 11],
+            "model" => ["Golf", "Golf", "Golf", "Golf", "Golf", "Passat", "Passat", "Passat", "Polo", "Polo", "Up"],
+            "price_in_eur" => [10, 11, 12, 13, 14, 20, 21, 22, 5, 7, 30]
+        )
+        .unwrap()
+        .lazy()
+    }
+
+    fn top(limit: usize) -> TopN {
+        TopN {
+            column: "model".to_string(),
+            limit,
+            ..Default::default()
+        }
+    }
+
+    fn medians(df: LazyFrame) -> DataFrame {
+        df.group_by([col("model")])
+            .agg([
+                col("price_in_eur").count().alias("count"),
+                col("price_in_eur").median().alias("median"),
+            ])
+            .sort(["model"], SortMultipleOptions::new())
+            .collect()
+            .unwrap()
+    }
+
+    fn strings(df: &DataFrame, name: &str) -> Vec<String> {
+        df.column(name)
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_iter()
+            .map(|v| v.unwrap().to_string())
+            .collect()
+    }
+
+    fn floats(df: &DataFrame, name: &str) -> Vec<f64> {
+        df.column(name)
+            .unwrap()
+            .cast(&DataType::Float64)
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_iter()
+            .map(|v| v.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_collapse_top_n() {
+        let result = medians(collapse_top_n(listings(), &[top(2)], "price_in_eur").unwrap());
+        assert_eq!(strings(&result, "model"), vec!["Golf", "Other", "Passat"]);
+        assert_eq!(floats(&result, "count"), vec![5.0, 3.0, 3.0]);
+        // The median of the Polos and the Up, not the sum of their medians.
+        assert_eq!(floats(&result, "median"), vec![12.0, 7.0, 21.0]);
+    }
+
+    #[test]
+    fn test_collapse_bottom_n_by_median() {
+        let bottom = TopN {
+            by: Some("median".to_string()),
+            bottom: true,
+            other_label: Some("Rest".to_string()),
+            ..top(1)
+        };
+        let result = medians(collapse_top_n(listings(), &[bottom], "price_in_eur").unwrap());
+        assert_eq!(strings(&result, "model"), vec!["Polo", "Rest"]);
+        assert_eq!(floats(&result, "count"), vec![2.0, 9.0]);
+    }
+
+    #[test]
+    fn test_drop_other() {
+        let dropped = TopN {
+            drop_other: true,
+            ..top(1)
+        };
+        let result = medians(collapse_top_n(listings(), &[dropped], "price_in_eur").unwrap());
+        assert_eq!(strings(&result, "model"), vec!["Golf"]);
+    }
+
+    #[test]
+    fn test_with_top_n_needs_group() {
+        let search = StatisticSearchPayload {
+            group: Some(vec!["make".to_string()]),
+            topN: vec![top(2)],
+            ..Default::default()
+        };
+        let group = search.group.clone().unwrap();
+        assert!(with_top_n(listings(), &search, &group, "price_in_eur").is_err());
+        assert!(collapse_top_n(listings(), &[top(0)], "price_in_eur").is_err());
+    }
+}
@@ -1,11 +1,15 @@
 use std::collections::HashMap;
 
 use chrono::{NaiveDate, TimeDelta, Utc};
-use log::info;
-use polars::prelude::{col, lit, Expr, Literal};
+use log::{error, info};
+use polars::prelude::{col, lit, Expr, IntoLazy, LazyFrame, Literal};
 
 use crate::model::AxumAPIModel::StatisticSearchPayload;
 
+use super::{
+    DedupService::deduplicate, EstimatorService::ESTIMATOR_CONFIG, OutlierService::without_outliers,
+};
+
 pub fn to_aggregator(aggregators: Vec<String>, column: &str) -> Vec<Expr> {
     let mut agg = vec![];
     for aggregator in aggregators {
@@ -104,6 +108,10 @@ pub fn to_predicate(search: StatisticSearchPayload) -> Expr {
         predicates.push(col("model").eq(lit(model)));
     }
 
+    if let Some(family) = search.modelFamily {
+        predicates.push(model_family_predicate(&family));
+    }
+
     if let Some(engine) = search.engine {
         let mut engine_predicates = vec![];
         for v in engine.iter() {
@@ -251,7 +259,65 @@ pub fn to_like_predicate<T: ToString + ToOwned + std::fmt::Debug + Literal>(
     }
     Some(predicate)
 }
-fn convert_days_to_date(days_ago: i64) -> NaiveDate {
+
+/// `df` filtered by `search`. With `"excludeOutliers": true` outliers are dropped
+/// before filtering, so that they are judged against their whole segment; with
+/// `"deduplicate": true` only one listing per duplicate cluster is kept.
+pub fn filter_listings(df: LazyFrame, search: &StatisticSearchPayload) -> LazyFrame {
+    let df = if search.excludeOutliers.unwrap_or_default() {
+        let config = &ESTIMATOR_CONFIG.outliers;
+        without_outliers(df, config, search.outlierMethod.unwrap_or(config.method))
+    } else {
+        df
+    };
+    let filtered = df.filter(to_predicate(search.clone()));
+    if !search.deduplicate.unwrap_or_default() {
+        return filtered;
+    }
+    let deduplicated = filtered
+        .clone()
+        .collect()
+        .map_err(|e| e.to_string())
+        .and_then(|df| {
+            let height = df.height();
+            deduplicate(df, &ESTIMATOR_CONFIG.dedup).inspect(|df| {
+                info!("Deduplicated {} listings to {}", height, df.height());
+            })
+        });
+    match deduplicated {
+        Ok(df) => df.lazy(),
+        Err(err) => {
+            error!("Deduplication failed: {}", err);
+            filtered
+        }
+    }
+}
+
+/// The family of a model: its leading word, or only the series digit of numeric model
+/// names such as BMW's 320.
+pub fn family_token(model: &str) -> Option<String> {
+    let word = model.split_whitespace().next()?;
+    match word.chars().next() {
+        Some(c) if c.is_ascii_digit() => Some(c.to_string()),
+        _ => Some(word.to_string()),
+    }
+}
+
+/// Models whose [`family_token`] is `family`: "C" matches "C 180" but not "CLA 200",
+/// "3" matches 318 and 330d but not 3 or M3.
+fn model_family_predicate(family: &str) -> Expr {
+    let model = col("model");
+    if family.len() == 1 && family.chars().all(|c| c.is_ascii_digit()) {
+        model.str().contains(lit(format!(r"^{}\d", family)), false)
+    } else {
+        model
+            .clone()
+            .eq(lit(family.to_string()))
+            .or(model.str().starts_with(lit(format!("{} ", family))))
+    }
+}
+
+pub fn convert_days_to_date(days_ago: i64) -> NaiveDate {
     let today = Utc::now().naive_utc().date();
     today.checked_sub_signed(TimeDelta::days(days_ago)).unwrap()
 }
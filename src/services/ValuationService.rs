@@ -0,0 +1,212 @@
+use std::{collections::BTreeSet, io::Write};
+
+use log::info;
+use polars::prelude::{col, lit, IntoLazy, LazyFrame, NamedFrom, Series};
+
+use crate::model::{
+    AxumAPIModel::{BatchValuationRequest, StatisticSearchPayload},
+    Valuation::{BatchValuation, BatchVehicle},
+};
+
+use super::{
+    EstimatorService::{estimator, ESTIMATOR_CONFIG},
+    PriceCalculatorService::comparables,
+};
+
+/// Reads vehicle specs from CSV with a header row. Both `;` and `,` separated
+/// files are accepted; unknown columns are ignored.
+pub fn parse_vehicles_csv(content: &str) -> Result<Vec<StatisticSearchPayload>, String> {
+    let header = content.lines().next().unwrap_or_default();
+    let delimiter = if header.contains(';') { b';' } else { b',' };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let mut vehicles = vec![];
+    for (row, record) in reader.deserialize::<BatchVehicle>().enumerate() {
+        let vehicle = record.map_err(|e| format!("Row {}: {}", row + 1, e))?;
+        vehicles.push(StatisticSearchPayload {
+            make: vehicle.make,
+            model: vehicle.model,
+            year: vehicle.year,
+            engine: vehicle.engine.map(|e| vec![e]),
+            gearbox: vehicle.gearbox,
+            power: vehicle.power,
+            mileage: vehicle.mileage,
+            cc: vehicle.cc,
+            estimator: vehicle.estimator,
+            ..Default::default()
+        });
+    }
+    Ok(vehicles)
+}
+
+/// Reads a batch from JSON: either a [`BatchValuationRequest`] or a plain list of vehicles.
+pub fn parse_batch_json(content: &str) -> Result<BatchValuationRequest, String> {
+    match serde_json::from_str::<BatchValuationRequest>(content) {
+        Ok(request) => Ok(request),
+        Err(e) => serde_json::from_str::<Vec<StatisticSearchPayload>>(content)
+            .map(|vehicles| BatchValuationRequest {
+                estimator: None,
+                vehicles,
+            })
+            .map_err(|_| e.to_string()),
+    }
+}
+
+/// Values every vehicle against `data`. The rows of the makes in the batch are
+/// collected once and every vehicle is matched against that in-memory frame.
+pub fn valuate_batch(
+    vehicles: &[StatisticSearchPayload],
+    default_estimator: Option<&str>,
+    data: &LazyFrame,
+) -> Result<Vec<BatchValuation>, String> {
+    let makes = vehicles
+        .iter()
+        .filter_map(|v| v.make.clone())
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    let scanned = data
+        .clone()
+        .filter(col("make").is_in(lit(Series::new("makes".into(), makes))))
+        .collect()
+        .map_err(|e| e.to_string())?
+        .lazy();
+    info!("Valuating {} vehicles", vehicles.len());
+
+    Ok(vehicles
+        .iter()
+        .enumerate()
+        .map(|(row, spec)| {
+            let mut spec = spec.clone();
+            if spec.estimator.is_none() {
+                spec.estimator = default_estimator.map(|e| e.to_string());
+            }
+            let mut valuation = BatchValuation {
+                row: row + 1,
+                make: spec.make.clone().unwrap_or_default(),
+                model: spec.model.clone().unwrap_or_default(),
+                year: spec.year,
+                engine: spec.engine.clone().unwrap_or_default().join(","),
+                gearbox: spec.gearbox.clone().unwrap_or_default(),
+                power: spec.power,
+                mileage: spec.mileage,
+                ..Default::default()
+            };
+            if spec.make.is_none() || spec.model.is_none() {
+                valuation.status = "make and model are required".to_string();
+                return valuation;
+            }
+            let estimate =
+                estimator(spec.estimator.as_deref(), &ESTIMATOR_CONFIG).and_then(|estimator| {
+                    let set = comparables(&spec, &scanned)?;
+                    estimator.estimate(&spec, &set.data)
+                });
+            match estimate {
+                Ok(estimate) => {
+                    valuation.status = "ok".to_string();
+                    valuation.estimator = estimate.strategy;
+                    valuation.estimation = Some(estimate.estimation.round() as i32);
+                    valuation.lower = Some(estimate.lower.round() as i32);
+                    valuation.upper = Some(estimate.upper.round() as i32);
+                    valuation.confidence = Some((estimate.confidence * 100.0).round() / 100.0);
+                    valuation.count = estimate.count;
+                }
+                Err(err) => valuation.status = err,
+            }
+            valuation
+        })
+        .collect())
+}
+
+pub fn write_valuations_csv<W: Write>(
+    valuations: &[BatchValuation],
+    writer: W,
+) -> Result<(), String> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b';')
+        .from_writer(writer);
+    for valuation in valuations {
+        writer.serialize(valuation).map_err(|e| e.to_string())?;
+    }
+    writer.flush().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::df;
+
+    use super::*;
+
+    fn listings() -> LazyFrame {
+        df!(
+            "make" => ["BMW", "BMW", "BMW", "BMW", "Audi"],
+            "model" => ["320", "320", "320", "320", "A4"],
+            "year" => [2018, 2018, 2019, 2018, 2018],
+            "title" => ["BMW 320d"; 5],
+            "equipment" => [""; 5],
+            "engine" => ["Diesel"; 5],
+            "gearbox" => ["Automatic"; 5],
+            "cc" => [1995; 5],
+            "mileage" => [65_000, 72_000, 70_000, 75_000, 60_000],
+            "power" => [190; 5],
+            "price_in_eur" => [21_000, 20_000, 23_000, 22_000, 19_000]
+        )
+        .unwrap()
+        .lazy()
+    }
+
+    #[test]
+    fn test_parse_vehicles_csv() {
+        let content = "make;model;year;engine;power;mileage\nBMW;320;2018;Diesel;190;61234\nAudi; A4 ;2018;;;\n";
+        let vehicles = parse_vehicles_csv(content).unwrap();
+        assert_eq!(vehicles.len(), 2);
+        assert_eq!(vehicles[0].engine, Some(vec!["Diesel".to_string()]));
+        assert_eq!(vehicles[0].mileage, Some(61234));
+        assert_eq!(vehicles[1].model, Some("A4".to_string()));
+        assert_eq!(vehicles[1].power, None);
+
+        let comma = parse_vehicles_csv("make,model,year\nBMW,320,2018\n").unwrap();
+        assert_eq!(comma[0].year, Some(2018));
+        assert!(parse_vehicles_csv("make,year\nBMW,new\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_batch_json() {
+        let request = parse_batch_json(
+            r#"{"estimator": "knn", "vehicles": [{"make": "BMW", "model": "320"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(request.estimator, Some("knn".to_string()));
+        assert_eq!(request.vehicles.len(), 1);
+        let list = parse_batch_json(r#"[{"make": "BMW"}, {"make": "Audi"}]"#).unwrap();
+        assert_eq!(list.vehicles.len(), 2);
+        assert!(parse_batch_json("{}").is_err());
+    }
+
+    #[test]
+    fn test_valuate_batch() {
+        let vehicles = parse_vehicles_csv(
+            "make,model,year,mileage,power\nBMW,320,2018,61234,190\nSkoda,Octavia,2018,,\n,320,2018,,\n",
+        )
+        .unwrap();
+        let valuations = valuate_batch(&vehicles, Some("median"), &listings()).unwrap();
+        assert_eq!(valuations.len(), 3);
+        assert_eq!(valuations[0].status, "ok");
+        assert_eq!(valuations[0].estimator, "median");
+        assert!(valuations[0].count >= 3);
+        assert!(valuations[0].estimation.is_some());
+        assert_eq!(valuations[1].status, "No data found");
+        assert_eq!(valuations[1].estimation, None);
+        assert_eq!(valuations[2].row, 3);
+        assert_eq!(valuations[2].status, "make and model are required");
+
+        let mut csv = vec![];
+        write_valuations_csv(&valuations, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with("row;make;model;year"));
+        assert_eq!(csv.lines().count(), 4);
+    }
+}
@@ -0,0 +1,279 @@
+use polars::{
+    df,
+    frame::DataFrame,
+    prelude::{Column, DataType},
+};
+use serde_json::{json, Map, Value};
+
+use crate::model::{
+    Chart::{ChartIntent, Mark},
+    DistributionChartData,
+};
+
+pub const VEGA_LITE_SCHEMA: &str = "https://vega.github.io/schema/vega-lite/v5.json";
+
+fn values(column: &Column) -> Result<Vec<Value>, String> {
+    let dtype = column.dtype();
+    let values = if dtype.is_integer() {
+        let column = column.cast(&DataType::Int64).map_err(|e| e.to_string())?;
+        let values = column.i64().map_err(|e| e.to_string())?;
+        values.into_iter().map(|v| json!(v)).collect()
+    } else if dtype.is_float() {
+        let column = column.cast(&DataType::Float64).map_err(|e| e.to_string())?;
+        let values = column.f64().map_err(|e| e.to_string())?;
+        values
+            .into_iter()
+            .map(|v| json!(v.filter(|v| v.is_finite())))
+            .collect()
+    } else {
+        let column = column.cast(&DataType::String).map_err(|e| e.to_string())?;
+        let values = column.str().map_err(|e| e.to_string())?;
+        values.into_iter().map(|v| json!(v)).collect()
+    };
+    Ok(values)
+}
+
+/// Rows of `data` as objects of column name and value, the inline data of a spec.
+pub fn records(data: &DataFrame) -> Result<Vec<Value>, String> {
+    let mut rows = vec![Map::new(); data.height()];
+    for column in data.get_columns() {
+        for (row, value) in rows.iter_mut().zip(values(column)?) {
+            row.insert(column.name().to_string(), value);
+        }
+    }
+    Ok(rows.into_iter().map(Value::Object).collect())
+}
+
+fn is_numeric(data: &DataFrame, field: &str) -> bool {
+    data.column(field)
+        .map(|c| c.dtype().is_numeric())
+        .unwrap_or(false)
+}
+
+/// Encoding of `field`; discrete axes keep the order of the rows.
+fn channel(field: &str, field_type: &str) -> Value {
+    let mut channel = json!({ "field": field, "type": field_type });
+    if field_type != "quantitative" {
+        channel["sort"] = Value::Null;
+    }
+    channel
+}
+
+/// Complete Vega-Lite specification of `data`, inlined, drawn as `intent` says.
+pub fn vega_lite(data: &DataFrame, intent: &ChartIntent) -> Result<Value, String> {
+    let x = intent
+        .x
+        .as_deref()
+        .ok_or("A Vega-Lite chart needs an x field")?;
+    let y = intent
+        .y
+        .as_deref()
+        .ok_or("A Vega-Lite chart needs a y field")?;
+    let color = intent.color.as_deref();
+    if intent.mark == Mark::Heatmap && color.is_none() {
+        return Err("A heatmap needs a color field".to_string());
+    }
+    for field in [Some(x), Some(y), color, intent.size.as_deref()]
+        .into_iter()
+        .flatten()
+    {
+        if data.column(field).is_err() {
+            return Err(format!("Column '{}' not found", field));
+        }
+    }
+    let numeric_type = |field: &str| {
+        if is_numeric(data, field) {
+            "quantitative"
+        } else {
+            "nominal"
+        }
+    };
+    let (mark, x_type, y_type) = match intent.mark {
+        Mark::Bar => (
+            json!({ "type": "bar", "tooltip": true }),
+            "ordinal",
+            "quantitative",
+        ),
+        Mark::Line => (
+            json!({ "type": "line", "point": true, "tooltip": true }),
+            if is_numeric(data, x) {
+                "quantitative"
+            } else {
+                "ordinal"
+            },
+            "quantitative",
+        ),
+        Mark::Heatmap => (
+            json!({ "type": "rect", "tooltip": true }),
+            "ordinal",
+            "ordinal",
+        ),
+        // Tukey whiskers at 1.5 interquartile ranges, the points beyond them as outliers.
+        Mark::Boxplot => (
+            json!({ "type": "boxplot", "extent": 1.5 }),
+            "ordinal",
+            "quantitative",
+        ),
+        Mark::Scatter => (
+            json!({ "type": "point", "tooltip": true }),
+            numeric_type(x),
+            numeric_type(y),
+        ),
+    };
+
+    let mut encoding = Map::new();
+    encoding.insert("x".to_string(), channel(x, x_type));
+    encoding.insert("y".to_string(), channel(y, y_type));
+    if let Some(color) = color {
+        let color_type = match intent.mark {
+            Mark::Heatmap => "quantitative",
+            Mark::Scatter => numeric_type(color),
+            _ => "nominal",
+        };
+        let mut channel = channel(color, color_type);
+        if let Some(scheme) = &intent.scheme {
+            channel["scale"] = json!({ "scheme": scheme });
+        }
+        encoding.insert("color".to_string(), channel);
+    }
+    if let Some(size) = &intent.size {
+        encoding.insert("size".to_string(), channel(size, "quantitative"));
+    }
+
+    let mut spec = json!({
+        "$schema": VEGA_LITE_SCHEMA,
+        "data": { "values": records(data)? },
+        "mark": mark,
+        "encoding": encoding,
+    });
+    if let Some(title) = &intent.title {
+        spec["title"] = json!(title);
+    }
+    if let Some(width) = intent.width {
+        spec["width"] = json!(width);
+    }
+    if let Some(height) = intent.height {
+        spec["height"] = json!(height);
+    }
+    Ok(spec)
+}
+
+/// Vega-Lite chart of the bins of `/data-distribution`: the listings per bin by default.
+pub fn distribution_vega_lite(
+    data: &DistributionChartData<i32>,
+    intent: &ChartIntent,
+) -> Result<Value, String> {
+    let bins = &data.data;
+    let frame = df!(
+        "bin" => bins.iter().map(|b| format!("{} - {}", b.min, b.max)).collect::<Vec<_>>(),
+        "min" => bins.iter().map(|b| b.min).collect::<Vec<_>>(),
+        "max" => bins.iter().map(|b| b.max).collect::<Vec<_>>(),
+        "median" => bins.iter().map(|b| b.median).collect::<Vec<_>>(),
+        "mean" => bins.iter().map(|b| b.mean).collect::<Vec<_>>(),
+        "count" => bins.iter().map(|b| b.count).collect::<Vec<_>>()
+    )
+    .map_err(|e| e.to_string())?;
+    let intent = ChartIntent {
+        x: intent.x.clone().or(Some("bin".to_string())),
+        y: intent.y.clone().or(Some("count".to_string())),
+        title: intent.title.clone().or(Some(data.axisLabel.clone())),
+        ..intent.clone()
+    };
+    vega_lite(&frame, &intent)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::IntervalData;
+
+    use super::*;
+
+    fn intent(mark: Mark) -> ChartIntent {
+        ChartIntent {
+            mark,
+            x: Some("year".to_string()),
+            y: Some("median".to_string()),
+            color: Some("make".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn rows() -> DataFrame {
+        df!(
+            "year" => ["2019", "2020"],
+            "make" => ["BMW", "Audi"],
+            "median" => [21_000.5, f64::NAN],
+            "count" => [2, 3]
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_vega_lite_bar() {
+        let spec = vega_lite(&rows(), &intent(Mark::Bar)).unwrap();
+        assert_eq!(spec["$schema"], VEGA_LITE_SCHEMA);
+        assert_eq!(spec["mark"]["type"], "bar");
+        assert_eq!(
+            spec["data"]["values"],
+            json!([
+                { "year": "2019", "make": "BMW", "median": 21_000.5, "count": 2 },
+                { "year": "2020", "make": "Audi", "median": null, "count": 3 },
+            ])
+        );
+        assert_eq!(
+            spec["encoding"]["x"],
+            json!({ "field": "year", "type": "ordinal", "sort": null })
+        );
+        assert_eq!(
+            spec["encoding"]["y"],
+            json!({ "field": "median", "type": "quantitative" })
+        );
+        assert_eq!(spec["encoding"]["color"]["type"], "nominal");
+    }
+
+    #[test]
+    fn test_vega_lite_heatmap_and_errors() {
+        let heatmap = ChartIntent {
+            y: Some("make".to_string()),
+            color: Some("count".to_string()),
+            scheme: Some("viridis".to_string()),
+            ..intent(Mark::Heatmap)
+        };
+        let spec = vega_lite(&rows(), &heatmap).unwrap();
+        assert_eq!(spec["mark"]["type"], "rect");
+        assert_eq!(spec["encoding"]["y"]["type"], "ordinal");
+        assert_eq!(
+            spec["encoding"]["color"],
+            json!({ "field": "count", "type": "quantitative", "scale": { "scheme": "viridis" } })
+        );
+
+        let unknown = ChartIntent {
+            size: Some("power".to_string()),
+            ..intent(Mark::Scatter)
+        };
+        assert_eq!(
+            vega_lite(&rows(), &unknown).unwrap_err(),
+            "Column 'power' not found"
+        );
+    }
+
+    #[test]
+    fn test_distribution_vega_lite() {
+        let bin = |min, max, count| IntervalData {
+            min,
+            max,
+            count,
+            ..Default::default()
+        };
+        let data = DistributionChartData {
+            axisLabel: "price_in_eur".to_string(),
+            data: vec![bin(0, 10_000, 4), bin(10_000, 20_000, 6)],
+            ..Default::default()
+        };
+        let spec = distribution_vega_lite(&data, &ChartIntent::default()).unwrap();
+        assert_eq!(spec["title"], "price_in_eur");
+        assert_eq!(spec["encoding"]["x"]["field"], "bin");
+        assert_eq!(spec["data"]["values"][1]["bin"], "10000 - 20000");
+        assert_eq!(spec["data"]["values"][1]["count"], 6);
+    }
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fmt::Debug, vec};
+use std::{collections::HashMap, fmt::Debug, path::Path, vec};
 
 use chrono::NaiveDate;
 use log::info;
@@ -13,9 +13,16 @@ use polars::{
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
-use crate::{model::AxumAPIModel::StatisticSearchPayload, HIDDEN_COLUMNS, VEHICLES_DATA};
+use crate::{
+    model::AxumAPIModel::StatisticSearchPayload, HIDDEN_COLUMNS, HISTORY_DIR, VEHICLES_DATA,
+};
 
-use super::Utils::to_predicate;
+use super::{
+    DealScoreService::{deal_predicate, with_deal_scores},
+    EstimatorService::ESTIMATOR_CONFIG,
+    HistoryService::{price_dropped_since, with_history},
+    Utils::to_predicate,
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum PredicateFilter<T: ToOwned + ToString + Debug + Clone + Literal> {
@@ -220,6 +227,8 @@ pub fn to_generic_json(data: &DataFrame) -> HashMap<String, Value> {
             json!(values)
         } else {
             let values = cv
+                .cast(&DataType::String)
+                .unwrap()
                 .str()
                 .unwrap()
                 .iter()
@@ -234,7 +243,8 @@ pub fn to_generic_json(data: &DataFrame) -> HashMap<String, Value> {
 }
 
 pub fn search(search: StatisticSearchPayload) -> HashMap<String, Value> {
-    let df = VEHICLES_DATA.clone();
+    let df = with_deal_scores(VEHICLES_DATA.clone(), &ESTIMATOR_CONFIG.deal_score);
+    let df = with_history(df, Path::new(HISTORY_DIR));
 
     // Group by the required columns and calculate the required statistics
 
@@ -267,9 +277,22 @@ pub fn search(search: StatisticSearchPayload) -> HashMap<String, Value> {
             col("url"),
             col("created_on"),
             col("updated_on"),
+            col("deal_score"),
+            col("deal_percentile"),
+            col("deal_rating"),
+            col("deal_segment_size"),
+            col("price_changes"),
+            col("last_price_drop"),
         ])
-        .filter(filterConditions)
-        .limit(100);
+        .filter(filterConditions);
+    let filtered = match deal_predicate(&search) {
+        Some(deal) => filtered.filter(deal),
+        None => filtered,
+    };
+    let filtered = match search.priceDroppedInDays {
+        Some(days) => filtered.filter(price_dropped_since(days as i64)),
+        None => filtered,
+    };
     let result = if !search.order.is_empty() {
         let mut columns = Vec::new();
         let mut orders = Vec::new();
@@ -287,10 +310,11 @@ pub fn search(search: StatisticSearchPayload) -> HashMap<String, Value> {
                     .with_order_descending_multi(orders)
                     .with_nulls_last(true),
             )
+            .limit(100)
             .collect()
             .unwrap()
     } else {
-        filtered.collect().unwrap()
+        filtered.limit(100).collect().unwrap()
     };
 
     to_generic_json(&result)
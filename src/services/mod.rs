@@ -1,44 +1,39 @@
 pub mod AnalysisService;
+pub mod BacktestService;
+pub mod BoxPlotService;
+pub mod BucketService;
+pub mod ChartMatrix;
 pub mod ChartServices;
+pub mod ChartTransform;
+pub mod ComparableService;
+pub mod DealScoreService;
+pub mod DedupService;
+pub mod DepreciationService;
 pub mod EnumService;
+pub mod EstimatorService;
+pub mod ForecastService;
+pub mod HeatmapService;
+pub mod HistoryService;
+pub mod LiquidityService;
+pub mod OutlierService;
 pub mod PivotService;
 pub mod PriceCalculatorService;
+pub mod PriceGapService;
+pub mod Regression;
+pub mod RenderService;
+pub mod SeasonalityService;
+pub mod SurvivalService;
+#[cfg(test)]
+pub mod TestUtils;
+pub mod TimeSeriesService;
+pub mod TopNService;
 pub mod Utils;
+pub mod ValuationService;
+pub mod VegaLiteService;
 pub mod VehicleService;
 
-use log::info;
 use polars::error::PolarsResult;
 use polars::prelude::*;
-use serde_json::{json, Value};
-
-pub fn process_datasets(
-    filled_pivoted: &DataFrame,
-    base_cols: &[String],
-    generate_colors: impl Fn(usize) -> Vec<String>,
-) -> Result<Vec<Value>, PolarsError> {
-    let unique_values = filled_pivoted
-        .get_columns()
-        .iter()
-        .filter(|col| !base_cols.contains(&col.name().to_string())) // Exclude the labels column
-        .map(|col| col.name().to_string())
-        .collect::<Vec<_>>();
-
-    let colors = generate_colors(unique_values.len());
-    let mut datasets = vec![];
-    info!("Unique values: {:?}", unique_values);
-
-    for (idx, value) in unique_values.iter().enumerate() {
-        if let Ok(data_series) = filled_pivoted.column(value) {
-            let data = extract_column_values(data_series.as_series().unwrap())?;
-            datasets.push(json!({
-                "label": value,
-                "data": data,
-                "backgroundColor": colors[idx]
-            }));
-        }
-    }
-    Ok(datasets)
-}
 
 pub fn extract_generic_column_values<T>(data_series: &Series) -> PolarsResult<Vec<T>>
 where
@@ -133,21 +128,6 @@ pub fn extract_column_values(data_series: &Series) -> PolarsResult<Vec<f64>> {
     }
 }
 
-pub fn extract_labels(
-    filled_pivoted: &DataFrame,
-    x_column: &str,
-) -> Result<Vec<String>, PolarsError> {
-    let binding = filled_pivoted.column(x_column)?.cast(&DataType::String)?;
-    let x_col = binding.str()?;
-
-    // Convert the column to a vector of strings, handling missing values
-    Ok(x_col
-        .unique()?
-        .into_iter()
-        .map(|opt| opt.unwrap_or("Unknown").to_string())
-        .collect())
-}
-
 /// A helper trait to make piping syntax more readable.
 trait Pipe: Sized {
     fn pipe<R>(self, f: impl FnOnce(Self) -> R) -> R {